@@ -1,10 +1,29 @@
 use log::*;
 use migration_rs::{cluster_note::CoreNoteFilePath, common::ObsidianVaultPath, *};
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 use tap::prelude::*;
 
 use clap::{Arg, ArgAction, ArgMatches, Command, arg, command, value_parser};
 
+fn incremental_arg() -> Arg {
+    Arg::new("incremental")
+        .long("incremental")
+        .help("Only process notes that changed since the last run, and the notes linking to them")
+        .action(ArgAction::SetTrue)
+}
+
+fn workspace_arg() -> Arg {
+    Arg::new("workspace")
+        .long("workspace")
+        .value_name("workspace_config")
+        .help("Workspace config listing the vaults that links of this vault may point into")
+        .value_parser(value_parser!(PathBuf))
+}
+
 fn parse_args() -> ArgMatches {
     command!()
         .arg(
@@ -13,6 +32,27 @@ fn parse_args() -> ArgMatches {
                 .short('v')
                 .action(ArgAction::Count),
         )
+        .arg(
+            Arg::new("cache-index")
+                .long("cache-index")
+                .help("Keep an index of the vault folders in the vault, so unchanged folders are not listed again")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-ignore")
+                .long("no-ignore")
+                .help("Also walk paths excluded by obsidian settings, .gitignore files and the .migration_rsignore file")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("symlinks")
+                .long("symlinks")
+                .help("What to do with symlinks in the vault: skip, follow, or follow-inside-vault. Defaults to skip")
+                .global(true)
+                .value_parser(value_parser!(vault_index::SymlinkPolicy)),
+        )
         .subcommand(
             Command::new("writeback")
                 .about("Parses and rewrites markdown files to the vault which includes some minor changes like line trims")
@@ -20,123 +60,804 @@ fn parse_args() -> ArgMatches {
                     arg!([vault_path] "Path to the vault")
                         .required(true)
                         .value_parser(value_parser!(PathBuf)),
-                ),
+                )
+                .arg(
+                    arg!(-j --threads <num_threads> "Number of threads to process notes with. Defaults to the available parallelism")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(incremental_arg()),
         )
         .subcommand(
             Command::new("extract_old_format_records")
                 .about("Extracts old format records into peripheral notes, replacing spawned events")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(-j --threads <num_threads> "Number of threads to process notes with. Defaults to the available parallelism")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(incremental_arg()),
+        )
+        .subcommand(
+            Command::new("lint")
+                .about("Reports notes that are not normalized, have unparsable or broken links, or old format records")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(-j --threads <num_threads> "Number of threads to process notes with. Defaults to the available parallelism")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(incremental_arg())
+                .arg(workspace_arg()),
+        )
+        .subcommand(
+            Command::new("watch")
+                .about("Follows the vault and runs the pipeline on every note once it is saved")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(--steps <steps> "Comma separated steps to run: writeback, index, lint, links. Defaults to all")
+                        .value_delimiter(',')
+                        .value_parser(value_parser!(watch::WatchStep)),
+                )
+                .arg(
+                    arg!(--"debounce-ms" <debounce_ms> "How long a note has to go without changes before it is processed")
+                        .value_parser(value_parser!(u64)),
+                ),
+        )
+        .subcommand(
+            Command::new("excluded")
+                .about("Lists the paths left out of the vault traversal, with the rule that excluded them or why a symlink was not followed")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                ),
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Reports note counts, remaining old format records, spawn markers and links to track the migration")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!(--json "Print the statistics as JSON")),
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Summarizes the status property of task, issue, howto and investigation notes per cluster")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(--"stale-days" <days> "Open notes not modified for this many days are stale. Defaults to 30")
+                        .value_parser(value_parser!(u64)),
+                )
+                .arg(arg!(--json "Print the report as JSON"))
+                .arg(arg!(--write "Write the summary into a status section of each core note, through the journal")),
+        )
+        .subcommand(
+            Command::new("timeline")
+                .about("Logs doer notes whose status changed since the Timeline note last recorded it")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!(--list "Only print the entries of the Timeline note"))
+                .arg(arg!(-d --"dry-run" "Only report the changes that would be made")),
+        )
+        .subcommand(
+            Command::new("fsck")
+                .about("Checks every cluster against the cluster rules, and can fix what has an automatic fix")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!(--fix "Apply the automatic fixes. They are journaled and can be reverted with undo")),
+        )
+        .subcommand(
+            Command::new("undo")
+                .about("Reverts the changes of the last command that changed the vault through the mutation journal")
                 .arg(
                     arg!([vault_path] "Path to the vault")
                         .required(true)
                         .value_parser(value_parser!(PathBuf)),
                 ),
         )
+        .subcommand(
+            Command::new("uncluster")
+                .about("Folds the peripheral notes of a cluster back into old format sections of its core note, and moves the note out of the cluster folder")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!([core_note] "Path to the core note of the cluster")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(--export <file> "Write the folded note to this file instead, leaving the vault as it is")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(workspace_arg())
+                .arg(arg!(-d --"dry-run" "Only report the changes that would be made")),
+        )
+        .subcommand(
+            Command::new("move_peripheral")
+                .about("Moves a peripheral note to another cluster, updating its parent, both core note indexes and links to it")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!([peripheral_note] "Path to the peripheral note to move")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!([core_note] "Path to the core note of the target cluster")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(workspace_arg())
+                .arg(arg!(-d --"dry-run" "Only report the changes that would be made")),
+        )
+        .subcommand(
+            Command::new("promote")
+                .about("Turns a peripheral note into a normal note next to its cluster, keeping the old parent as spawned_by")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!([peripheral_note] "Path to the peripheral note to promote")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!(--cluster "Make the note the core note of a new cluster instead"))
+                .arg(workspace_arg())
+                .arg(arg!(-d --"dry-run" "Only report the changes that would be made")),
+        )
+        .subcommand(
+            Command::new("split")
+                .about("Moves a heading section and its subsections into a new peripheral note, turning the note into a cluster if needed")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!([note] "Path to the normal note or core note to split")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!(--heading <heading> "Text of the heading of the section").required(true))
+                .arg(arg!(--as <context_type> "Context type of the new peripheral note, like task or idea").required(true))
+                .arg(arg!(--embed "Replace the section with an embed of the new note instead of a spawn marker"))
+                .arg(workspace_arg())
+                .arg(arg!(-d --"dry-run" "Only report the changes that would be made")),
+        )
+        .subcommand(
+            Command::new("new_cluster")
+                .about("Creates a cluster with a core note from the cluster.md template of the vault")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!([folder] "Path to the folder to create the cluster in")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!([title] "Title of the cluster, which names its folder and core note").required(true))
+                .arg(arg!(-d --"dry-run" "Only report the changes that would be made")),
+        )
+        .subcommand(
+            Command::new("new_peripheral")
+                .about("Creates a numbered peripheral note in a cluster from the template of its context type, and lists it in the core note index")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!([cluster] "Path to the core note or root folder of the cluster")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!([context_type] "Context type of the note, like task or idea").required(true))
+                .arg(arg!([title] "Title of the note, which names it after its number").required(true))
+                .arg(arg!(-d --"dry-run" "Only report the changes that would be made")),
+        )
+        .subcommand(
+            Command::new("merge_clusters")
+                .about("Moves everything in a cluster into another one, and folds its core note into the core note of the target")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!([source_core_note] "Path to the core note of the cluster to merge")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!([target_core_note] "Path to the core note of the cluster to merge into")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(workspace_arg())
+                .arg(arg!(-d --"dry-run" "Only report the changes that would be made")),
+        )
+        .subcommand(
+            Command::new("renumber")
+                .about("Gives peripheral notes with a missing or duplicate NNN prefix the next free number, and updates links to them")
+                .arg(
+                    arg!([vault_path] "Path to the vault")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(--per <scope> "What notes are numbered together: folder or year. Defaults to the vault config")
+                        .value_parser(value_parser!(vault_config::NumberingScope)),
+                )
+                .arg(workspace_arg())
+                .arg(arg!(-d --"dry-run" "Only report the changes that would be made")),
+        )
+        .subcommand(
+            Command::new("cross_vault_links")
+                .about("Rewrites links between the vaults of a workspace to GitHub webview URLs or obsidian:// links")
+                .arg(
+                    arg!([workspace_config] "Path to the workspace config")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(--to <form> "The form to rewrite links to: github or obsidian")
+                        .required(true)
+                        .value_parser(value_parser!(workspace::CrossVaultLinkForm)),
+                )
+                .arg(arg!(-d --"dry-run" "Only report the links that would be rewritten")),
+        )
+        .subcommand(
+            Command::new("graph")
+                .about("Exports the links between the notes of the vaults of a workspace, as graphviz DOT")
+                .arg(
+                    arg!([workspace_config] "Path to the workspace config")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!(--json "Print the graph as JSON")),
+        )
         .subcommand_required(true)
         .get_matches()
 }
 
-fn skip_processing_managed_path(path: &Path) -> bool {
-    let filename = path.file_name().unwrap().to_string_lossy();
-    let pathname = format!("{path:?}");
+fn app_writeback(vault_path: &ObsidianVaultPath, num_threads: usize, incremental: bool) {
+    let process_markdown_file = |path: &Path| -> Result<(), common::WritebackMarkdownFileError> {
+        // Some markdown files managed by extensions and should be skipped
+        if drivers::skip_processing_managed_path(vault_path, path) {
+            return Ok(());
+        }
+
+        common::writeback_markdown_file(path).map(|_| ())
+    };
+
+    if !incremental {
+        let processed_files = drivers::process_markdown_files_in_vault_parallel(
+            vault_path,
+            num_threads,
+            process_markdown_file,
+        );
+
+        drivers::log_processed_files(&processed_files);
+        return;
+    }
+
+    let paths = drivers::get_markdown_file_paths_in_vault(vault_path, true);
+
+    let run = incremental::IncrementalRun::start(vault_path, "writeback", &paths);
+
+    let dirty_paths = paths
+        .into_iter()
+        .filter(|path| run.is_dirty(path))
+        .collect::<Vec<_>>();
 
-    filename.contains("excalidraw")
-        || filename.contains("Kanban")
-        || filename.contains("Summarize")
-        || filename.contains("Summary")
-        || filename.contains("Timeline")
-        || pathname.contains("templater")
+    let processed_files =
+        drivers::process_files_parallel(dirty_paths, num_threads, process_markdown_file);
+
+    drivers::log_processed_files(&processed_files);
+
+    let failed_paths = processed_files
+        .into_iter()
+        .filter(|processed_file| processed_file.result.is_err())
+        .map(|processed_file| processed_file.path)
+        .collect::<BTreeSet<_>>();
+
+    let paths_after_run = drivers::get_markdown_file_paths_in_vault(vault_path, true);
+
+    run.finish(vault_path, &paths_after_run, &failed_paths)
+        .expect("Failed to save incremental state");
 }
 
-fn app_writeback(vault_path: &ObsidianVaultPath) {
-    let process_markdown_file = |path: &Path| -> Option<()> {
-        // Some markdown files managed by extensions and should be skipped
-        if skip_processing_managed_path(path) {
-            return Some(());
-        }
+fn app_extract_old_format_records(
+    vault_path: &ObsidianVaultPath,
+    num_threads: usize,
+    incremental: bool,
+) {
+    info!("value_path: {vault_path:?}");
+
+    let paths = drivers::get_markdown_file_paths_in_vault(vault_path, false);
+
+    let opt_run = incremental.then(|| {
+        incremental::IncrementalRun::start(vault_path, "extract_old_format_records", &paths)
+    });
+
+    let process_markdown_file =
+        |path: &Path| -> Result<(), cluster_note_io::ExtractOldFormatRecordsError> {
+            // Some markdown files managed by extensions and should be skipped
+            if drivers::skip_processing_managed_path(vault_path, path) {
+                return Ok(());
+            }
 
-        let content = common::read_file_content(path).expect("Could not read content");
+            // A note that fails is skipped and reported, the rest of the vault goes on
+            let content = common::read_file_content(path).ok_or(
+                cluster_note_io::ExtractOldFormatRecordsError::ReadFailed(path.to_path_buf()),
+            )?;
 
-        let events = common::parse_markdown_file(&content);
+            let events = common::parse_markdown_file(&content);
+
+            let config = &vault_path.config;
+
+            let layout = cluster_note::detect_old_format_layout(&events, config);
+
+            let old_format_records = cluster_note::get_note_old_format_entries_located(
+                path, &content, &events, config, &layout,
+            )?;
+
+            if old_format_records.is_empty() {
+                return Ok(());
+            }
 
-        let new_content = common::render_events_to_common_markdown(&events)
-            .expect("Failed to render back to common markdown")
-            .pipe(|new_content| {
-                common::adhoc_fix_rendered_markdown_output_for_obsidian(&content, &new_content)
-            });
+            let linkables = common::extract_linkable_obsidian_md_items(&events);
 
-        common::write_file_content(&new_content, path).expect("Failed to write file content");
+            let links = common::extract_obsidian_md_links(&events).map_err(|e| {
+                cluster_note_io::ExtractOldFormatRecordsError::UnparsableLinks(
+                    path.to_path_buf(),
+                    e,
+                )
+            })?;
 
-        Some(())
+            let _spawn_metadata =
+                cluster_note::extract_spawn_metadata_from_old_format(&linkables, &links);
+
+            // Are we in a cluster note already? if not, create one for this note by its name and replace its content
+            // with content that includes no old entries
+
+            let all_old_format_events = old_format_records
+                .iter()
+                .flat_map(|old_format_entry| old_format_entry.events.clone())
+                .collect::<Vec<_>>();
+
+            let events_excluding_old_format_records = events
+                .clone()
+                .into_iter()
+                .filter(|event| !all_old_format_events.contains(event))
+                .collect::<Vec<_>>();
+
+            let new_content =
+                common::render_events_to_common_markdown(&events_excluding_old_format_records)
+                    .map_err(|e| {
+                        cluster_note_io::ExtractOldFormatRecordsError::NotRenderable(
+                            path.to_path_buf(),
+                            e,
+                        )
+                    })?;
+
+            let core_note_path = {
+                let opt_core_note_path = CoreNoteFilePath::new(path);
+
+                match opt_core_note_path {
+                    Some(core_note_path) => core_note_path,
+                    None => cluster_note_io::turn_note_into_cluster_note(path).map_err(|e| {
+                        cluster_note_io::ExtractOldFormatRecordsError::TurnIntoCluster(
+                            path.to_path_buf(),
+                            e,
+                        )
+                    })?,
+                }
+            };
+
+            common::write_file_content(&new_content, &core_note_path.path).map_err(|e| {
+                cluster_note_io::ExtractOldFormatRecordsError::WriteFailed(
+                    core_note_path.path.clone(),
+                    e,
+                )
+            })?;
+
+            // Remove all spawned events before writing the new entries to file
+
+            // Write the entries to file, and parent, then if available, spawned by/spawned in paths
+
+            Ok(())
+        };
+
+    let dirty_paths = match &opt_run {
+        Some(run) => paths
+            .into_iter()
+            .filter(|path| run.is_dirty(path))
+            .collect::<Vec<_>>(),
+        None => paths,
     };
 
-    drivers::process_markdown_files_in_vault(vault_path, process_markdown_file);
+    let processed_files =
+        drivers::process_files_parallel(dirty_paths, num_threads, process_markdown_file);
+
+    let failure_count = drivers::log_processed_files(&processed_files);
+
+    if failure_count > 0 {
+        warn!(
+            "{failure_count} notes were skipped, their old format entries could not be extracted"
+        );
+    }
+
+    // Skipped notes are left dirty, so they are extracted once they are fixed
+    if let Some(run) = opt_run {
+        let paths_after_run = drivers::get_markdown_file_paths_in_vault(vault_path, false);
+
+        let failed_paths = processed_files
+            .into_iter()
+            .filter(|processed_file| processed_file.result.is_err())
+            .map(|processed_file| processed_file.path)
+            .collect::<BTreeSet<_>>();
+
+        run.finish(vault_path, &paths_after_run, &failed_paths)
+            .expect("Failed to save incremental state");
+    }
 }
 
-fn app_extract_old_format_records(vault_path: &ObsidianVaultPath) {
-    info!("value_path: {vault_path:?}");
+fn app_lint(
+    vault_path: &ObsidianVaultPath,
+    num_threads: usize,
+    incremental: bool,
+    opt_workspace: Option<&workspace::Workspace>,
+) {
+    let paths = drivers::get_markdown_file_paths_in_vault(vault_path, true);
 
-    let process_markdown_file = |path: &Path| -> Option<()> {
-        // Some markdown files managed by extensions and should be skipped
-        if skip_processing_managed_path(path) {
-            return Some(());
-        }
+    let opt_run =
+        incremental.then(|| incremental::IncrementalRun::start(vault_path, "lint", &paths));
 
-        let content = common::read_file_content(path).expect("Could not read content");
+    let working_items = cluster_note::get_working_item_paths_in_vault(vault_path)
+        .expect("Failed to get working items");
 
-        let events = common::parse_markdown_file(&content);
+    let lint_paths = paths
+        .into_iter()
+        .filter(|path| opt_run.as_ref().is_none_or(|run| run.is_dirty(path)))
+        .collect::<Vec<_>>();
 
-        let old_format_records = cluster_note::get_note_old_format_entries(&events).ok()?;
+    let processed_files = drivers::process_files_parallel(lint_paths, num_threads, |path| {
+        let mut mut_issues = lint::lint_note(path, &vault_path.config)?;
+        mut_issues.extend(lint::check_note_links(&working_items, path)?);
 
-        if old_format_records.is_empty() {
-            return None;
+        if let Some(workspace) = opt_workspace {
+            mut_issues.extend(lint::check_note_cross_vault_links(workspace, path)?);
         }
 
-        let linkables = common::extract_linkable_obsidian_md_items(&events);
-
-        let links = common::extract_obsidian_md_links(&events).expect("Could not process links");
+        Ok::<_, lint::LintNoteError>(mut_issues)
+    });
 
-        let _spawn_metadata =
-            cluster_note::extract_spawn_metadata_from_old_format(&linkables, &links);
+    drivers::log_processed_files(&processed_files);
 
-        // Are we in a cluster note already? if not, create one for this note by its name and replace its content
-        // with content that includes no old entries
+    let issue_count = processed_files
+        .iter()
+        .flat_map(|processed_file| match &processed_file.result {
+            Ok(issues) => issues
+                .iter()
+                .map(|issue| (&processed_file.path, issue))
+                .collect::<Vec<_>>(),
+            Err(_) => vec![],
+        })
+        .inspect(|(path, issue)| warn!("{path:?}: {issue}"))
+        .count();
 
-        let all_old_format_events = old_format_records
-            .iter()
-            .flat_map(|old_format_entry| old_format_entry.events.clone())
-            .collect::<Vec<_>>();
+    info!("Found {issue_count} lint issues");
 
-        let events_excluding_old_format_records = events
-            .clone()
+    // Notes with issues count as failed, so they are reported again on the next incremental run
+    if let Some(run) = opt_run {
+        let failed_paths = processed_files
             .into_iter()
-            .filter(|event| !all_old_format_events.contains(event))
-            .collect::<Vec<_>>();
+            .filter(
+                |processed_file| !matches!(&processed_file.result, Ok(issues) if issues.is_empty()),
+            )
+            .map(|processed_file| processed_file.path)
+            .collect::<BTreeSet<_>>();
+
+        let paths_after_run = drivers::get_markdown_file_paths_in_vault(vault_path, true);
+
+        run.finish(vault_path, &paths_after_run, &failed_paths)
+            .expect("Failed to save incremental state");
+    }
+}
+
+fn app_excluded(vault_path: &ObsidianVaultPath) {
+    let index =
+        vault_index::VaultIndex::build_for_vault(vault_path).expect("Failed to index vault");
+
+    for (path, rule) in index.excluded.iter() {
+        let relative_path = path.strip_prefix(&vault_path.path).unwrap_or(path);
+
+        println!("{}: {rule}", relative_path.display());
+    }
+
+    for (path, reason) in index.skipped_symlinks.iter() {
+        let relative_path = path.strip_prefix(&vault_path.path).unwrap_or(path);
+
+        println!("{}: {reason}", relative_path.display());
+    }
+}
+
+fn app_stats(vault_path: &ObsidianVaultPath, json: bool) {
+    let vault_stats = stats::get_vault_stats(vault_path).expect("Failed to get vault statistics");
+
+    match json {
+        true => println!(
+            "{}",
+            serde_json::to_string_pretty(&vault_stats).expect("Failed to serialize statistics")
+        ),
+        false => println!("{vault_stats}"),
+    }
+}
+
+fn app_status(vault_path: &ObsidianVaultPath, stale_after_days: u64, json: bool, write: bool) {
+    let report = dashboard::get_status_report(vault_path, stale_after_days)
+        .expect("Failed to get the status report");
+
+    match json {
+        true => println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("Failed to serialize the status report")
+        ),
+        false => println!("{report}"),
+    }
+
+    if write {
+        app_apply_planned_mutations(
+            vault_path,
+            "status",
+            dashboard::get_write_status_mutations(vault_path, &report)
+                .ok_or("Failed to index the vault to write the status sections"),
+            false,
+        );
+    }
+}
+
+fn app_list_timeline(vault_path: &ObsidianVaultPath) {
+    let timeline_note = timeline::get_timeline_note_path(vault_path);
+
+    let content =
+        common::read_file_content(&timeline_note).expect("Failed to read the Timeline note");
+
+    for entry in timeline::parse_timeline(&content) {
+        println!("{} {}", entry.date, entry.text);
+    }
+}
+
+fn app_fsck(vault_path: &ObsidianVaultPath, fix: bool) {
+    let problems = fsck::fsck_vault(vault_path).expect("Failed to check the vault");
+
+    for problem in problems.iter() {
+        warn!("{problem}");
+    }
 
-        let new_content =
-            common::render_events_to_common_markdown(&events_excluding_old_format_records)
-                .expect("Failed to render back to common markdown");
+    info!("Found {} problems", problems.len());
 
-        let core_note_path = {
-            let opt_core_note_path = CoreNoteFilePath::new(path);
+    if !fix {
+        return;
+    }
 
-            match opt_core_note_path {
-                Some(core_note_path) => core_note_path,
-                None => cluster_note_io::turn_note_into_cluster_note(path)
-                    .expect("Failed to turn note into cluster note"),
+    // Each fix is journaled on its own, so one that conflicts with an earlier fix does not hold back the rest
+    let fixed_count = problems
+        .into_iter()
+        .filter(|problem| !problem.fix.is_empty())
+        .filter(|problem| {
+            match mutation::apply_mutations(vault_path, "fsck", problem.fix.clone()) {
+                Ok(()) => true,
+                Err(e) => {
+                    error!("Failed to fix {:?}: {e}", problem.path);
+                    false
+                }
             }
-        };
+        })
+        .count();
+
+    info!("Fixed {fixed_count} problems. Run fsck again to check fixes that conflicted");
+}
+
+fn app_undo(vault_path: &ObsidianVaultPath) {
+    match mutation::undo_last_mutations(vault_path) {
+        Ok(Some(entry)) => info!("Reverted {} changes of {}", entry.applied, entry.command),
+        Ok(None) => info!("Nothing to undo"),
+        Err(e) => error!("Failed to undo: {e}"),
+    }
+}
+
+fn app_uncluster(
+    sub_matches: &ArgMatches,
+    vault_path: &ObsidianVaultPath,
+    core_note_path: &Path,
+    opt_export_path: Option<&PathBuf>,
+    dry_run: bool,
+) {
+    let plan = match uncluster::plan_uncluster(vault_path, core_note_path) {
+        Ok(plan) => plan,
+        Err(e) => {
+            error!("{e}");
+            return;
+        }
+    };
+
+    if let Some(export_path) = opt_export_path {
+        match common::write_file_content(&plan.content, export_path) {
+            Ok(_) => info!("Exported {core_note_path:?} to {export_path:?}"),
+            Err(e) => error!("Failed to write {export_path:?}: {e}"),
+        }
+        return;
+    }
 
-        common::write_file_content(&new_content, &core_note_path.path)
-            .expect("Failed to write file content");
+    let Ok(mutations) = with_cross_vault_links_redirected(
+        sub_matches,
+        vault_path,
+        Ok::<_, std::convert::Infallible>(plan.mutations),
+    );
 
-        // Remove all spawned events before writing the new entries to file
+    for mutation in mutations.iter() {
+        info!("{mutation}");
+    }
+
+    for attachment_file in plan.attachment_files.iter() {
+        warn!("Attachment left in the cluster folder: {attachment_file:?}");
+    }
 
-        // Write the entries to file, and parent, then if available, spawned by/spawned in paths
+    if dry_run {
+        return;
+    }
+
+    match mutation::apply_mutations(vault_path, "uncluster", mutations) {
+        Ok(()) => info!("Unclustered into {:?}", plan.note_path),
+        Err(e) => error!("Failed to uncluster {core_note_path:?}: {e}"),
+    }
+}
 
-        Some(())
+/// Reports planned mutations, and applies them through the journal unless it is a dry run
+fn app_apply_planned_mutations<E: std::fmt::Display>(
+    vault_path: &ObsidianVaultPath,
+    command: &str,
+    planned: Result<Vec<mutation::Mutation>, E>,
+    dry_run: bool,
+) {
+    let mutations = match planned {
+        Ok(mutations) => mutations,
+        Err(e) => {
+            error!("{e}");
+            return;
+        }
     };
 
-    drivers::process_non_peripheral_markdown_files_in_vault(vault_path, process_markdown_file);
+    for mutation in mutations.iter() {
+        info!("{mutation}");
+    }
+
+    if dry_run {
+        return;
+    }
+
+    match mutation::apply_mutations(vault_path, command, mutations) {
+        Ok(()) => info!("Done. Run undo to revert {command}"),
+        Err(e) => error!("Failed to {command}: {e}"),
+    }
+}
+
+fn app_graph(workspace: &workspace::Workspace, json: bool) {
+    let graph =
+        graph::get_workspace_link_graph(workspace).expect("Failed to index the workspace vaults");
+
+    match json {
+        true => println!(
+            "{}",
+            serde_json::to_string_pretty(&graph).expect("Failed to serialize the graph")
+        ),
+        false => println!("{graph}"),
+    }
+}
+
+/// Rewrites the cross vault links of each vault of the workspace through its own journal, so undo in a vault
+/// reverts the links rewritten in it
+fn app_cross_vault_links(
+    workspace: &workspace::Workspace,
+    form: workspace::CrossVaultLinkForm,
+    dry_run: bool,
+) {
+    for workspace_vault in workspace.vaults.iter() {
+        let mutations = workspace::plan_rewrite_cross_vault_links(workspace, workspace_vault, form);
+
+        if mutations.is_empty() {
+            continue;
+        }
+
+        info!(
+            "{}: {} notes with cross vault links",
+            workspace_vault.name,
+            mutations.len()
+        );
+
+        app_apply_planned_mutations(
+            &workspace_vault.vault,
+            "cross_vault_links",
+            Ok::<_, std::convert::Infallible>(mutations),
+            dry_run,
+        );
+    }
+}
+
+/// Also redirects the links of the workspace vaults to notes the planned mutations move, if a workspace is given
+fn with_cross_vault_links_redirected<E>(
+    sub_matches: &ArgMatches,
+    vault_path: &ObsidianVaultPath,
+    planned: Result<Vec<mutation::Mutation>, E>,
+) -> Result<Vec<mutation::Mutation>, E> {
+    let Some(workspace) = get_opt_workspace(sub_matches) else {
+        return planned;
+    };
+
+    planned
+        .map(|mutations| workspace::redirect_cross_vault_links(&workspace, vault_path, mutations))
+}
+
+fn get_opt_workspace(sub_matches: &ArgMatches) -> Option<workspace::Workspace> {
+    sub_matches
+        .get_one::<PathBuf>("workspace")
+        .map(|config_path| {
+            workspace::Workspace::load(config_path).expect("Failed to load workspace")
+        })
+}
+
+fn get_vault_path(sub_matches: &ArgMatches) -> ObsidianVaultPath {
+    // Global args are propagated to the subcommand matches
+    let traversal_options = vault_index::TraversalOptions {
+        cache_index: sub_matches.get_flag("cache-index"),
+        no_ignore: sub_matches.get_flag("no-ignore"),
+        symlink_policy: sub_matches
+            .get_one::<vault_index::SymlinkPolicy>("symlinks")
+            .copied()
+            .unwrap_or_default(),
+    };
+
+    sub_matches
+        .get_one::<PathBuf>("vault_path")
+        .unwrap()
+        .pipe(|path| ObsidianVaultPath::new(path))
+        .unwrap_or_else(|e| panic!("Failed to open the vault: {e}"))
+        .with_traversal_options(traversal_options)
 }
 
 fn main() {
@@ -153,23 +874,299 @@ fn main() {
 
     match matches.subcommand() {
         Some(("writeback", sub_matches)) => {
-            let vault_path = sub_matches
-                .get_one::<PathBuf>("vault_path")
-                .unwrap()
-                .pipe(|path| ObsidianVaultPath::new(path))
-                .expect("vault path should be valid");
+            let vault_path = get_vault_path(sub_matches);
 
-            app_writeback(&vault_path);
+            let num_threads = sub_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_default();
+
+            app_writeback(
+                &vault_path,
+                num_threads,
+                sub_matches.get_flag("incremental"),
+            );
         }
 
         Some(("extract_old_format_records", sub_matches)) => {
-            let vault_path = sub_matches
-                .get_one::<PathBuf>("vault_path")
+            let vault_path = get_vault_path(sub_matches);
+
+            let num_threads = sub_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_default();
+
+            app_extract_old_format_records(
+                &vault_path,
+                num_threads,
+                sub_matches.get_flag("incremental"),
+            );
+        }
+
+        Some(("lint", sub_matches)) => {
+            let vault_path = get_vault_path(sub_matches);
+
+            let num_threads = sub_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_default();
+
+            let opt_workspace = get_opt_workspace(sub_matches);
+
+            app_lint(
+                &vault_path,
+                num_threads,
+                sub_matches.get_flag("incremental"),
+                opt_workspace.as_ref(),
+            );
+        }
+
+        Some(("watch", sub_matches)) => {
+            let mut mut_vault_path = get_vault_path(sub_matches);
+
+            let options = {
+                let mut mut_options = watch::WatchOptions::default();
+
+                if let Some(steps) = sub_matches.get_many::<watch::WatchStep>("steps") {
+                    mut_options.steps = steps.copied().collect();
+                }
+
+                if let Some(debounce_ms) = sub_matches.get_one::<u64>("debounce-ms") {
+                    mut_options.debounce = std::time::Duration::from_millis(*debounce_ms);
+                }
+
+                mut_options
+            };
+
+            if let Err(e) = watch::watch_vault(&mut mut_vault_path, &options) {
+                error!("{e}");
+            }
+        }
+
+        Some(("excluded", sub_matches)) => {
+            let vault_path = get_vault_path(sub_matches);
+
+            app_excluded(&vault_path);
+        }
+
+        Some(("stats", sub_matches)) => {
+            let vault_path = get_vault_path(sub_matches);
+
+            app_stats(&vault_path, sub_matches.get_flag("json"));
+        }
+
+        Some(("status", sub_matches)) => {
+            let vault_path = get_vault_path(sub_matches);
+
+            let stale_after_days = sub_matches
+                .get_one::<u64>("stale-days")
+                .copied()
+                .unwrap_or(30);
+
+            app_status(
+                &vault_path,
+                stale_after_days,
+                sub_matches.get_flag("json"),
+                sub_matches.get_flag("write"),
+            );
+        }
+
+        Some(("timeline", sub_matches)) => {
+            let vault_path = get_vault_path(sub_matches);
+
+            if sub_matches.get_flag("list") {
+                app_list_timeline(&vault_path);
+            } else {
+                app_apply_planned_mutations(
+                    &vault_path,
+                    "timeline",
+                    timeline::plan_record_status_changes(&vault_path, SystemTime::now()),
+                    sub_matches.get_flag("dry-run"),
+                );
+            }
+        }
+
+        Some(("fsck", sub_matches)) => {
+            let vault_path = get_vault_path(sub_matches);
+
+            app_fsck(&vault_path, sub_matches.get_flag("fix"));
+        }
+
+        Some(("undo", sub_matches)) => {
+            let vault_path = get_vault_path(sub_matches);
+
+            app_undo(&vault_path);
+        }
+
+        Some(("uncluster", sub_matches)) => {
+            let vault_path = get_vault_path(sub_matches);
+
+            app_uncluster(
+                sub_matches,
+                &vault_path,
+                sub_matches.get_one::<PathBuf>("core_note").unwrap(),
+                sub_matches.get_one::<PathBuf>("export"),
+                sub_matches.get_flag("dry-run"),
+            );
+        }
+
+        Some(("move_peripheral", sub_matches)) => {
+            let vault_path = get_vault_path(sub_matches);
+
+            let planned = cluster_move::plan_move_peripheral_note(
+                &vault_path,
+                sub_matches.get_one::<PathBuf>("peripheral_note").unwrap(),
+                sub_matches.get_one::<PathBuf>("core_note").unwrap(),
+            );
+
+            let planned = with_cross_vault_links_redirected(sub_matches, &vault_path, planned);
+
+            app_apply_planned_mutations(
+                &vault_path,
+                "move_peripheral",
+                planned,
+                sub_matches.get_flag("dry-run"),
+            );
+        }
+
+        Some(("promote", sub_matches)) => {
+            let vault_path = get_vault_path(sub_matches);
+
+            let planned = cluster_move::plan_promote_peripheral_note(
+                &vault_path,
+                sub_matches.get_one::<PathBuf>("peripheral_note").unwrap(),
+                sub_matches.get_flag("cluster"),
+            );
+
+            let planned = with_cross_vault_links_redirected(sub_matches, &vault_path, planned);
+
+            app_apply_planned_mutations(
+                &vault_path,
+                "promote",
+                planned,
+                sub_matches.get_flag("dry-run"),
+            );
+        }
+
+        Some(("split", sub_matches)) => {
+            let vault_path = get_vault_path(sub_matches);
+
+            let planned = cluster_move::plan_split_heading_section(
+                &vault_path,
+                sub_matches.get_one::<PathBuf>("note").unwrap(),
+                sub_matches.get_one::<String>("heading").unwrap(),
+                sub_matches.get_one::<String>("as").unwrap(),
+                sub_matches.get_flag("embed"),
+            );
+
+            let planned = with_cross_vault_links_redirected(sub_matches, &vault_path, planned);
+
+            app_apply_planned_mutations(
+                &vault_path,
+                "split",
+                planned,
+                sub_matches.get_flag("dry-run"),
+            );
+        }
+
+        Some(("new_cluster", sub_matches)) => {
+            let vault_path = get_vault_path(sub_matches);
+
+            let planned = scaffold::plan_new_cluster(
+                &vault_path,
+                sub_matches.get_one::<PathBuf>("folder").unwrap(),
+                sub_matches.get_one::<String>("title").unwrap(),
+                SystemTime::now(),
+            );
+
+            app_apply_planned_mutations(
+                &vault_path,
+                "new_cluster",
+                planned,
+                sub_matches.get_flag("dry-run"),
+            );
+        }
+
+        Some(("new_peripheral", sub_matches)) => {
+            let vault_path = get_vault_path(sub_matches);
+
+            let planned = scaffold::plan_new_peripheral_note(
+                &vault_path,
+                sub_matches.get_one::<PathBuf>("cluster").unwrap(),
+                sub_matches.get_one::<String>("context_type").unwrap(),
+                sub_matches.get_one::<String>("title").unwrap(),
+                SystemTime::now(),
+            );
+
+            app_apply_planned_mutations(
+                &vault_path,
+                "new_peripheral",
+                planned,
+                sub_matches.get_flag("dry-run"),
+            );
+        }
+
+        Some(("merge_clusters", sub_matches)) => {
+            let vault_path = get_vault_path(sub_matches);
+
+            let planned = cluster_move::plan_merge_clusters(
+                &vault_path,
+                sub_matches.get_one::<PathBuf>("source_core_note").unwrap(),
+                sub_matches.get_one::<PathBuf>("target_core_note").unwrap(),
+            );
+
+            let planned = with_cross_vault_links_redirected(sub_matches, &vault_path, planned);
+
+            app_apply_planned_mutations(
+                &vault_path,
+                "merge_clusters",
+                planned,
+                sub_matches.get_flag("dry-run"),
+            );
+        }
+
+        Some(("renumber", sub_matches)) => {
+            let vault_path = get_vault_path(sub_matches);
+
+            let planned = numbering::plan_renumber(
+                &vault_path,
+                sub_matches
+                    .get_one::<vault_config::NumberingScope>("per")
+                    .copied(),
+            );
+
+            let planned = with_cross_vault_links_redirected(sub_matches, &vault_path, planned);
+
+            app_apply_planned_mutations(
+                &vault_path,
+                "renumber",
+                planned,
+                sub_matches.get_flag("dry-run"),
+            );
+        }
+
+        Some(("cross_vault_links", sub_matches)) => {
+            let workspace = sub_matches
+                .get_one::<PathBuf>("workspace_config")
+                .unwrap()
+                .pipe(|config_path| workspace::Workspace::load(config_path))
+                .expect("Failed to load workspace");
+
+            let form = *sub_matches
+                .get_one::<workspace::CrossVaultLinkForm>("to")
+                .unwrap();
+
+            app_cross_vault_links(&workspace, form, sub_matches.get_flag("dry-run"));
+        }
+
+        Some(("graph", sub_matches)) => {
+            let workspace = sub_matches
+                .get_one::<PathBuf>("workspace_config")
                 .unwrap()
-                .pipe(|path| ObsidianVaultPath::new(path))
-                .expect("vault path should be valid");
+                .pipe(|config_path| workspace::Workspace::load(config_path))
+                .expect("Failed to load workspace");
 
-            app_extract_old_format_records(&vault_path);
+            app_graph(&workspace, sub_matches.get_flag("json"));
         }
 
         _ => unreachable!(),
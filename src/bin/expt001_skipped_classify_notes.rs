@@ -16,6 +16,7 @@ fn main() {
                 cluster_root_folder: _cluster_root_folder,
                 core_note_file: _core_note_file,
                 category_folders_with_peripheral_files: _category_folders_with_peripheral_files,
+                attachment_files: _attachment_files,
             } => {}
         }
     }
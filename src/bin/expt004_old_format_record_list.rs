@@ -12,7 +12,12 @@ fn main() {
 
         let events = common::parse_markdown_file(&content);
 
-        let old_format_records = cluster_note::get_note_old_format_entries(&events).ok()?;
+        let config = &vault_folder.config;
+
+        let layout = cluster_note::detect_old_format_layout(&events, config);
+
+        let old_format_records =
+            cluster_note::get_note_old_format_entries(&content, &events, config, &layout).ok()?;
 
         let records_with_extracts = old_format_records
             .iter()
@@ -40,7 +45,7 @@ fn main() {
             log::trace!(
                 "record {:?} of {:?} has {} events.",
                 record.entry_name,
-                record.entry_type,
+                record.context_type.heading_plural,
                 record.events.len()
             );
             log::trace!(
@@ -0,0 +1,1033 @@
+use regex::{Captures, Regex};
+use std::{
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::SystemTime,
+};
+use tap::prelude::*;
+use thiserror::Error;
+
+use crate::{
+    cluster_note::NormalNoteFilePath,
+    cluster_note::{self, ClusterRootFolderPath, CoreNoteFilePath, PeripheralNoteFilePath},
+    common::{self as comm, ObsidianVaultPath},
+    drivers,
+    mutation::{Mutation, NoteEdits},
+    numbering, timeline, uncluster,
+    vault_index::VaultIndex,
+};
+
+#[derive(Error, Debug)]
+pub enum ClusterMoveError {
+    #[error("Failed to index vault {0:?}: {1}")]
+    IndexFailed(PathBuf, String),
+
+    #[error("Not the core note of a cluster: {0:?}")]
+    NotACoreNote(PathBuf),
+
+    #[error("Not a peripheral note: {0:?}")]
+    NotAPeripheralNote(PathBuf),
+
+    #[error("Source and target are the same cluster: {0:?}")]
+    SameCluster(PathBuf),
+
+    #[error("Failed to list the peripheral notes of cluster {0:?}")]
+    ListFailed(PathBuf),
+
+    #[error("Failed to read note {0:?}")]
+    ReadFailed(PathBuf),
+
+    #[error("{0:?} already exists")]
+    NameTaken(PathBuf),
+
+    #[error("Cluster {0:?} has nested folders or symlinks, which fsck reports")]
+    BrokenCluster(PathBuf),
+
+    #[error("Only normal notes and core notes can be split: {0:?}")]
+    CannotSplit(PathBuf),
+
+    #[error("No heading {1:?} in {0:?}")]
+    HeadingNotFound(PathBuf, String),
+
+    #[error("No context type is named {0:?}")]
+    UnknownContextType(String),
+}
+
+/// A cluster as it is before the move
+struct Cluster {
+    root: PathBuf,
+    core_note: PathBuf,
+    core_note_stem: String,
+    note_names_per_folder: Vec<(String, Vec<String>)>,
+}
+
+impl Cluster {
+    fn get_note_names_per_folder_without(&self, stem: &str) -> Vec<(String, Vec<String>)> {
+        self.note_names_per_folder
+            .iter()
+            .map(|(name, note_names)| {
+                (
+                    name.clone(),
+                    note_names
+                        .iter()
+                        .filter(|note_name| *note_name != stem)
+                        .cloned()
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// fsck reports empty context type folders, so none is left behind when its last note moves out
+fn get_emptied_category_folder_removal(
+    index: &VaultIndex,
+    category_folder: &Path,
+) -> Option<Mutation> {
+    index
+        .get_listing(category_folder)
+        .is_some_and(|listing| {
+            listing.files.len() == 1 && listing.dirs.is_empty() && listing.symlinks.is_empty()
+        })
+        .then(|| Mutation::RemoveDir {
+            path: category_folder.to_path_buf(),
+        })
+}
+
+fn get_cluster(index: &VaultIndex, core_note_path: &Path) -> Result<Cluster, ClusterMoveError> {
+    let core_note = CoreNoteFilePath::new_in_index(index, core_note_path)
+        .ok_or(ClusterMoveError::NotACoreNote(core_note_path.to_path_buf()))?;
+
+    let cluster_root_folder = core_note
+        .path
+        .parent()
+        .and_then(|parent| ClusterRootFolderPath::new_in_index(index, parent))
+        .ok_or(ClusterMoveError::NotACoreNote(core_note.path.clone()))?;
+
+    let (category_folders_with_peripheral_files, _diagnostics) = index
+        .get_category_folders_with_peripheral_files_from_cluster_root_folder(&cluster_root_folder)
+        .ok_or(ClusterMoveError::ListFailed(
+            cluster_root_folder.path.clone(),
+        ))?;
+
+    let note_names_per_folder = category_folders_with_peripheral_files
+        .iter()
+        .flat_map(|(category_folder, files)| {
+            let folder = category_folder
+                .path
+                .file_name()?
+                .to_string_lossy()
+                .to_string();
+
+            let note_names = files
+                .iter()
+                .flat_map(|file| Some(file.path.file_stem()?.to_string_lossy().to_string()))
+                .collect::<Vec<_>>();
+
+            Some((folder, note_names))
+        })
+        .collect();
+
+    Ok(Cluster {
+        core_note_stem: core_note
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        root: cluster_root_folder.path,
+        core_note: core_note.path,
+        note_names_per_folder,
+    })
+}
+
+static FOLDER_LINK_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[\[([^\[\]|#\n]+/[^\[\]|#\n]+)").unwrap());
+
+/// Rewrites links that name a moved note by its folders. Links by note name alone still resolve after the move.
+pub fn redirect_folder_links(
+    vault: &ObsidianVaultPath,
+    content: &str,
+    moves: &[(PathBuf, PathBuf)],
+) -> String {
+    FOLDER_LINK_PATTERN
+        .replace_all(content, |caps: &Captures| {
+            moves
+                .iter()
+                .find(|(from, _)| cluster_note::note_link_matches_path(from, &caps[1]))
+                .and_then(|(_, to)| vault.get_note_link_path(to))
+                .map(|to_link| format!("[[{to_link}"))
+                .unwrap_or_else(|| caps[0].to_owned())
+        })
+        .to_string()
+}
+
+fn get_cluster_of_category_folder(index: &VaultIndex, category_folder: &Path) -> Option<Cluster> {
+    let cluster_root_folder =
+        ClusterRootFolderPath::new_in_index(index, category_folder.parent()?)?;
+
+    let core_note = index.get_core_note_file_from_cluster_root_folder(&cluster_root_folder)?;
+
+    get_cluster(index, &core_note.path).ok()
+}
+
+fn edit_note(
+    mut_edits: &mut NoteEdits,
+    path: &Path,
+    f: impl FnOnce(&str) -> String,
+) -> Result<(), ClusterMoveError> {
+    mut_edits
+        .edit(path, f)
+        .ok_or(ClusterMoveError::ReadFailed(path.to_path_buf()))
+}
+
+fn set_core_note_index(
+    mut_edits: &mut NoteEdits,
+    index: &VaultIndex,
+    cluster: &Cluster,
+    note_names_per_folder: &[(String, Vec<String>)],
+) -> Result<(), ClusterMoveError> {
+    let core_note_index =
+        cluster_note::render_core_note_index_of_note_names(&index.config, note_names_per_folder);
+
+    edit_note(mut_edits, &cluster.core_note, |content| {
+        cluster_note::replace_core_note_index(content, &core_note_index)
+            .unwrap_or_else(|| content.to_owned())
+    })
+}
+
+/// Lists a note that is about to be created in the index of its core note, and adds lines like its spawn marker
+/// above the index
+pub(crate) fn plan_core_note_index_with_new_note(
+    index: &VaultIndex,
+    core_note_path: &Path,
+    folder: &str,
+    note_name: &str,
+    core_note_lines: &[String],
+) -> Result<Vec<Mutation>, ClusterMoveError> {
+    let cluster = get_cluster(index, core_note_path)?;
+
+    let note_names_per_folder = {
+        let mut mut_note_names_per_folder = cluster.note_names_per_folder.clone();
+
+        mut_note_names_per_folder.push((folder.to_owned(), vec![note_name.to_owned()]));
+
+        mut_note_names_per_folder
+    };
+
+    let mut mut_edits = NoteEdits::default();
+
+    edit_note(&mut mut_edits, &cluster.core_note, |content| {
+        insert_core_note_lines(content, core_note_lines)
+    })?;
+
+    set_core_note_index(&mut mut_edits, index, &cluster, &note_names_per_folder)?;
+
+    Ok(mut_edits.into_mutations())
+}
+
+fn set_parent(content: &str, core_note_stem: &str) -> String {
+    comm::set_frontmatter_property(content, "parent", &format!("\"[[{core_note_stem}]]\""))
+}
+
+static SPAWNED_FROM_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"From \[\[#(\^spawn-[A-Za-z0-9-]+)\]\] in \[\[([^\[\]|#]+)\]\]").unwrap()
+});
+
+/// Block identifiers of the `Spawn [[note]] ^spawn-...` markers in the core note that the note says it came from
+pub(crate) fn get_spawned_from_block_identifiers(
+    content: &str,
+    core_note_stem: &str,
+) -> Vec<String> {
+    SPAWNED_FROM_PATTERN
+        .captures_iter(content)
+        .filter(|caps| &caps[2] == core_note_stem)
+        .map(|caps| caps[1].to_owned())
+        .collect()
+}
+
+/// Takes the lines ending in one of the block identifiers out of the content
+fn take_block_identifier_lines(
+    content: &str,
+    block_identifiers: &[String],
+) -> (String, Vec<String>) {
+    let (taken, kept): (Vec<_>, Vec<_>) = content.lines().partition(|line| {
+        BLOCK_IDENTIFIER_PATTERN
+            .captures(line)
+            .is_some_and(|caps| block_identifiers.iter().any(|id| *id == caps[1]))
+    });
+
+    let kept_content = {
+        let mut mut_content = format!("{}\n", kept.join("\n").trim_end());
+
+        while mut_content.contains("\n\n\n") {
+            mut_content = mut_content.replace("\n\n\n", "\n\n");
+        }
+
+        mut_content
+    };
+
+    (kept_content, taken.into_iter().map(str::to_owned).collect())
+}
+
+/// Adds lines to a core note above its index, or at its end without one
+fn insert_core_note_lines(content: &str, lines: &[String]) -> String {
+    if lines.is_empty() {
+        return content.to_owned();
+    }
+
+    let block = lines.join("\n");
+
+    match content.find(cluster_note::CORE_NOTE_INDEX_START) {
+        Some(pos) => format!(
+            "{}\n\n{block}\n\n{}",
+            content[..pos].trim_end(),
+            &content[pos..]
+        ),
+        None => format!("{}\n\n{block}\n", content.trim_end()),
+    }
+}
+
+/// Spawn markers follow a peripheral note to its new core note, so links to them name that core note
+fn redirect_spawn_marker_links(
+    content: &str,
+    from_core_note_stem: &str,
+    to_core_note_stem: &str,
+    block_identifiers: &[String],
+) -> String {
+    block_identifiers
+        .iter()
+        .fold(content.to_owned(), |content, block_identifier| {
+            content.replace(
+                &format!("[[{from_core_note_stem}#{block_identifier}"),
+                &format!("[[{to_core_note_stem}#{block_identifier}"),
+            )
+        })
+}
+
+/// Moves a peripheral note into the same context type folder of another cluster, updating its parent, the indexes
+/// of both core notes and links that name the note by its folders. The spawn marker of the note moves to the new
+/// core note with it.
+pub fn plan_move_peripheral_note(
+    vault: &ObsidianVaultPath,
+    peripheral_note_path: &Path,
+    target_core_note_path: &Path,
+) -> Result<Vec<Mutation>, ClusterMoveError> {
+    let index = VaultIndex::build_for_vault(vault)
+        .map_err(|e| ClusterMoveError::IndexFailed(vault.path.clone(), e.to_string()))?;
+
+    let peripheral_note =
+        PeripheralNoteFilePath::new_in_index(&index, &vault.resolve_path(peripheral_note_path))
+            .ok_or(ClusterMoveError::NotAPeripheralNote(
+                peripheral_note_path.to_path_buf(),
+            ))?;
+
+    let (Some(category_folder), Some(file_name), Some(stem)) = (
+        peripheral_note.path.parent(),
+        peripheral_note.path.file_name(),
+        peripheral_note.path.file_stem(),
+    ) else {
+        return Err(ClusterMoveError::NotAPeripheralNote(peripheral_note.path));
+    };
+
+    let stem = stem.to_string_lossy().to_string();
+
+    let folder = category_folder
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let source = get_cluster_of_category_folder(&index, category_folder).ok_or(
+        ClusterMoveError::NotAPeripheralNote(peripheral_note.path.clone()),
+    )?;
+
+    let target = get_cluster(&index, &vault.resolve_path(target_core_note_path))?;
+
+    if source.root == target.root {
+        return Err(ClusterMoveError::SameCluster(target.root));
+    }
+
+    let target_folder = target.root.join(&folder);
+    let to = target_folder.join(file_name);
+
+    if to.exists() {
+        return Err(ClusterMoveError::NameTaken(to));
+    }
+
+    let moves = [(peripheral_note.path.clone(), to.clone())];
+
+    let spawn_block_identifiers = comm::read_file_content(&peripheral_note.path)
+        .ok_or(ClusterMoveError::ReadFailed(peripheral_note.path.clone()))?
+        .pipe_ref(|content| get_spawned_from_block_identifiers(content, &source.core_note_stem));
+
+    let edits = {
+        let mut mut_edits = NoteEdits::default();
+
+        for path in drivers::get_markdown_file_paths_in_vault(vault, true) {
+            edit_note(&mut mut_edits, &path, |content| {
+                redirect_spawn_marker_links(
+                    &redirect_folder_links(vault, content, &moves),
+                    &source.core_note_stem,
+                    &target.core_note_stem,
+                    &spawn_block_identifiers,
+                )
+            })?;
+        }
+
+        edit_note(&mut mut_edits, &peripheral_note.path, |content| {
+            spawn_block_identifiers.iter().fold(
+                set_parent(content, &target.core_note_stem),
+                |content, block_identifier| {
+                    content.replace(
+                        &format!(
+                            "From [[#{block_identifier}]] in [[{}]]",
+                            source.core_note_stem
+                        ),
+                        &format!(
+                            "From [[#{block_identifier}]] in [[{}]]",
+                            target.core_note_stem
+                        ),
+                    )
+                },
+            )
+        })?;
+
+        let mut mut_spawn_markers = vec![];
+
+        edit_note(&mut mut_edits, &source.core_note, |content| {
+            let (kept_content, spawn_markers) =
+                take_block_identifier_lines(content, &spawn_block_identifiers);
+
+            mut_spawn_markers = spawn_markers;
+
+            match mut_spawn_markers.is_empty() {
+                true => content.to_owned(),
+                false => kept_content,
+            }
+        })?;
+
+        edit_note(&mut mut_edits, &target.core_note, |content| {
+            insert_core_note_lines(content, &mut_spawn_markers)
+        })?;
+
+        let source_note_names_per_folder = source.get_note_names_per_folder_without(&stem);
+
+        let target_note_names_per_folder = {
+            let mut mut_note_names_per_folder = target.note_names_per_folder.clone();
+
+            mut_note_names_per_folder.push((folder.clone(), vec![stem.clone()]));
+
+            mut_note_names_per_folder
+        };
+
+        set_core_note_index(
+            &mut mut_edits,
+            &index,
+            &source,
+            &source_note_names_per_folder,
+        )?;
+        set_core_note_index(
+            &mut mut_edits,
+            &index,
+            &target,
+            &target_note_names_per_folder,
+        )?;
+
+        mut_edits
+    };
+
+    let mutations = {
+        let mut mut_mutations = edits.into_mutations();
+
+        if !index.is_dir(&target_folder) {
+            mut_mutations.push(Mutation::CreateDir {
+                path: target_folder,
+            });
+        }
+
+        mut_mutations.push(Mutation::Rename {
+            from: peripheral_note.path.clone(),
+            to,
+        });
+
+        mut_mutations.extend(get_emptied_category_folder_removal(&index, category_folder));
+
+        mut_mutations
+    };
+
+    Ok(mutations)
+}
+
+/// Turns a peripheral note into a normal note next to its cluster folder, or into the core note of a new cluster
+/// there. The old parent is kept as the `spawned_by` property.
+pub fn plan_promote_peripheral_note(
+    vault: &ObsidianVaultPath,
+    peripheral_note_path: &Path,
+    into_cluster: bool,
+) -> Result<Vec<Mutation>, ClusterMoveError> {
+    let index = VaultIndex::build_for_vault(vault)
+        .map_err(|e| ClusterMoveError::IndexFailed(vault.path.clone(), e.to_string()))?;
+
+    let peripheral_note =
+        PeripheralNoteFilePath::new_in_index(&index, &vault.resolve_path(peripheral_note_path))
+            .ok_or(ClusterMoveError::NotAPeripheralNote(
+                peripheral_note_path.to_path_buf(),
+            ))?;
+
+    let (Some(category_folder), Some(file_name), Some(stem)) = (
+        peripheral_note.path.parent(),
+        peripheral_note.path.file_name(),
+        peripheral_note.path.file_stem(),
+    ) else {
+        return Err(ClusterMoveError::NotAPeripheralNote(peripheral_note.path));
+    };
+
+    let stem = stem.to_string_lossy().to_string();
+
+    let source = get_cluster_of_category_folder(&index, category_folder).ok_or(
+        ClusterMoveError::NotAPeripheralNote(peripheral_note.path.clone()),
+    )?;
+
+    let Some(destination_folder) = source.root.parent() else {
+        return Err(ClusterMoveError::NotAPeripheralNote(peripheral_note.path));
+    };
+
+    let (opt_new_cluster_folder, to) = match into_cluster {
+        true => {
+            let new_cluster_folder = destination_folder.join(&stem);
+            let to = new_cluster_folder.join(file_name);
+
+            (Some(new_cluster_folder), to)
+        }
+        false => (None, destination_folder.join(file_name)),
+    };
+
+    if let Some(taken) = [opt_new_cluster_folder.clone(), Some(to.clone())]
+        .into_iter()
+        .flatten()
+        .find(|path| path.exists())
+    {
+        return Err(ClusterMoveError::NameTaken(taken));
+    }
+
+    let moves = [(peripheral_note.path.clone(), to.clone())];
+
+    let edits = {
+        let mut mut_edits = NoteEdits::default();
+
+        for path in drivers::get_markdown_file_paths_in_vault(vault, true) {
+            edit_note(&mut mut_edits, &path, |content| {
+                redirect_folder_links(vault, content, &moves)
+            })?;
+        }
+
+        edit_note(&mut mut_edits, &peripheral_note.path, |content| {
+            comm::set_frontmatter_property(
+                &comm::remove_frontmatter_property(content, "parent"),
+                "spawned_by",
+                &format!("\"[[{}]]\"", source.core_note_stem),
+            )
+        })?;
+
+        let note_names_per_folder = source.get_note_names_per_folder_without(&stem);
+
+        set_core_note_index(&mut mut_edits, &index, &source, &note_names_per_folder)?;
+
+        mut_edits
+    };
+
+    let mutations = {
+        let mut mut_mutations = edits.into_mutations();
+
+        if let Some(new_cluster_folder) = opt_new_cluster_folder {
+            mut_mutations.push(Mutation::CreateDir {
+                path: new_cluster_folder,
+            });
+        }
+
+        mut_mutations.push(Mutation::Rename {
+            from: peripheral_note.path.clone(),
+            to,
+        });
+
+        mut_mutations.extend(get_emptied_category_folder_removal(&index, category_folder));
+
+        mut_mutations
+    };
+
+    Ok(mutations)
+}
+
+/// Moves everything in a cluster into another one and folds its core note into the core note of the target,
+/// under an H1 section named after it. Links to the source core note are redirected to that section.
+pub fn plan_merge_clusters(
+    vault: &ObsidianVaultPath,
+    source_core_note_path: &Path,
+    target_core_note_path: &Path,
+) -> Result<Vec<Mutation>, ClusterMoveError> {
+    let index = VaultIndex::build_for_vault(vault)
+        .map_err(|e| ClusterMoveError::IndexFailed(vault.path.clone(), e.to_string()))?;
+
+    let source = get_cluster(&index, &vault.resolve_path(source_core_note_path))?;
+    let target = get_cluster(&index, &vault.resolve_path(target_core_note_path))?;
+
+    if source.root == target.root {
+        return Err(ClusterMoveError::SameCluster(target.root));
+    }
+
+    let source_listing = index
+        .get_listing(&source.root)
+        .ok_or(ClusterMoveError::ListFailed(source.root.clone()))?;
+
+    if !source_listing.symlinks.is_empty() {
+        return Err(ClusterMoveError::BrokenCluster(source.root.clone()));
+    }
+
+    // Files in the cluster folder and in each of its folders move to the same place in the target
+    let moves = {
+        let mut mut_moves = source_listing
+            .files
+            .iter()
+            .map(|file_name| source.root.join(file_name))
+            .filter(|path| *path != source.core_note)
+            .map(|path| {
+                let to = target.root.join(path.file_name().unwrap_or_default());
+                (path, to)
+            })
+            .collect::<Vec<_>>();
+
+        for dir_name in source_listing.dirs.iter() {
+            let folder = source.root.join(dir_name);
+
+            let listing = index
+                .get_listing(&folder)
+                .ok_or(ClusterMoveError::ListFailed(folder.clone()))?;
+
+            if !listing.dirs.is_empty() || !listing.symlinks.is_empty() {
+                return Err(ClusterMoveError::BrokenCluster(source.root.clone()));
+            }
+
+            mut_moves.extend(listing.files.iter().map(|file_name| {
+                (
+                    folder.join(file_name),
+                    target.root.join(dir_name).join(file_name),
+                )
+            }));
+        }
+
+        mut_moves
+    };
+
+    if let Some((_, to)) = moves.iter().find(|(_, to)| to.exists()) {
+        return Err(ClusterMoveError::NameTaken(to.clone()));
+    }
+
+    let redirect_links = |content: &str| {
+        uncluster::redirect_links_to_folded_notes(
+            vault,
+            &redirect_folder_links(vault, content, &moves),
+            &target.core_note_stem,
+            std::slice::from_ref(&source.core_note),
+        )
+    };
+
+    let source_core_note_content = comm::read_file_content(&source.core_note)
+        .ok_or(ClusterMoveError::ReadFailed(source.core_note.clone()))?;
+
+    let folded_section = {
+        let without_index = cluster_note::replace_core_note_index(&source_core_note_content, "")
+            .unwrap_or_else(|| source_core_note_content.clone());
+
+        let (properties, body) = uncluster::split_folded_note_properties(&without_index);
+
+        let section = [
+            format!("# {}", source.core_note_stem),
+            properties.join("\n"),
+            uncluster::demote_headings(body.trim(), 1),
+        ]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+        redirect_links(&section)
+    };
+
+    let peripheral_paths = moves
+        .iter()
+        .filter(|(from, _)| PeripheralNoteFilePath::new_in_index(&index, from).is_some())
+        .map(|(from, _)| from.clone())
+        .collect::<Vec<_>>();
+
+    let edits = {
+        let mut mut_edits = NoteEdits::default();
+
+        for path in drivers::get_markdown_file_paths_in_vault(vault, true) {
+            if path != source.core_note {
+                edit_note(&mut mut_edits, &path, redirect_links)?;
+            }
+        }
+
+        for path in peripheral_paths.iter() {
+            edit_note(&mut mut_edits, path, |content| {
+                set_parent(content, &target.core_note_stem)
+            })?;
+        }
+
+        edit_note(&mut mut_edits, &target.core_note, |content| {
+            format!("{}\n\n{folded_section}\n", content.trim_end())
+        })?;
+
+        let note_names_per_folder = {
+            let mut mut_note_names_per_folder = target.note_names_per_folder.clone();
+
+            mut_note_names_per_folder.extend(source.note_names_per_folder.iter().cloned());
+
+            mut_note_names_per_folder
+        };
+
+        set_core_note_index(&mut mut_edits, &index, &target, &note_names_per_folder)?;
+
+        mut_edits
+    };
+
+    let mutations = {
+        let mut mut_mutations = edits.into_mutations();
+
+        mut_mutations.push(Mutation::RemoveFile {
+            path: source.core_note.clone(),
+            content: source_core_note_content,
+        });
+
+        for dir_name in source_listing.dirs.iter() {
+            if !index.is_dir(&target.root.join(dir_name)) {
+                mut_mutations.push(Mutation::CreateDir {
+                    path: target.root.join(dir_name),
+                });
+            }
+        }
+
+        mut_mutations.extend(moves.iter().map(|(from, to)| Mutation::Rename {
+            from: from.clone(),
+            to: to.clone(),
+        }));
+        mut_mutations.extend(
+            source_listing
+                .dirs
+                .iter()
+                .map(|dir_name| Mutation::RemoveDir {
+                    path: source.root.join(dir_name),
+                }),
+        );
+        mut_mutations.push(Mutation::RemoveDir {
+            path: source.root.clone(),
+        });
+
+        mut_mutations
+    };
+
+    Ok(mutations)
+}
+
+/// Lines of a heading section: the heading, and everything up to the next heading of the same or a higher level, or
+/// up to the index of a core note
+struct HeadingSection {
+    start: usize,
+    end: usize,
+    level: usize,
+}
+
+fn find_heading_section(content: &str, heading: &str) -> Option<HeadingSection> {
+    let heading_lines = comm::get_atx_heading_lines(content);
+
+    let start = heading_lines
+        .iter()
+        .position(|opt_heading| opt_heading.is_some_and(|(_, text)| text == heading))?;
+
+    let (level, _) = heading_lines[start]?;
+
+    // The index of a core note is not part of the section above it
+    let end = heading_lines
+        .iter()
+        .zip(content.lines())
+        .skip(start + 1)
+        .position(|(opt_heading, line)| {
+            opt_heading.is_some_and(|(other_level, _)| other_level <= level)
+                || line.trim() == cluster_note::CORE_NOTE_INDEX_START
+        })
+        .map_or(heading_lines.len(), |offset| start + 1 + offset);
+
+    Some(HeadingSection { start, end, level })
+}
+
+/// Characters obsidian does not allow in note names, or that break links
+const NOTE_NAME_FORBIDDEN_CHARS: &[char] = &[
+    '*', '"', '\\', '/', '<', '>', ':', '|', '?', '#', '^', '[', ']',
+];
+
+pub(crate) fn get_note_title_of_heading(heading: &str) -> String {
+    heading
+        .replace(NOTE_NAME_FORBIDDEN_CHARS, " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub(crate) static BLOCK_IDENTIFIER_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s(\^[A-Za-z0-9-]+)\s*$").unwrap());
+
+static SAME_NOTE_LINK_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[\[#([^\[\]|]+)").unwrap());
+
+/// Points links to a section that moved into a new note at that note. Links to the section heading go to the note
+/// itself, and links to its subheadings and blocks go to them in the note.
+pub(crate) fn redirect_links_to_split_section(
+    content: &str,
+    note_link: &str,
+    heading: &str,
+    sublinks: &[String],
+    new_note_name: &str,
+) -> String {
+    let content = ["]]", "|"].iter().fold(content.to_owned(), |content, end| {
+        content.replace(
+            &format!("[[{note_link}#{heading}{end}"),
+            &format!("[[{new_note_name}{end}"),
+        )
+    });
+
+    sublinks.iter().fold(content, |content, sublink| {
+        ["]]", "|"].iter().fold(content, |content, end| {
+            content.replace(
+                &format!("[[{note_link}#{sublink}{end}"),
+                &format!("[[{new_note_name}#{sublink}{end}"),
+            )
+        })
+    })
+}
+
+/// Moves a heading section with its subsections into a new peripheral note of the context type, numbered like
+/// other peripheral notes. The section is replaced by a spawn marker, or by an embed of the new note. A normal note
+/// is turned into a cluster first. The Timeline note logs the new note.
+pub fn plan_split_heading_section(
+    vault: &ObsidianVaultPath,
+    note_path: &Path,
+    heading: &str,
+    context_type_name: &str,
+    embed: bool,
+) -> Result<Vec<Mutation>, ClusterMoveError> {
+    let index = VaultIndex::build_for_vault(vault)
+        .map_err(|e| ClusterMoveError::IndexFailed(vault.path.clone(), e.to_string()))?;
+
+    let note_path = vault.resolve_path(note_path);
+
+    let context_type = index.config.find_context_type(context_type_name).ok_or(
+        ClusterMoveError::UnknownContextType(context_type_name.to_owned()),
+    )?;
+
+    let opt_cluster = match CoreNoteFilePath::new_in_index(&index, &note_path) {
+        Some(core_note) => Some(get_cluster(&index, &core_note.path)?),
+        None if NormalNoteFilePath::new_in_index(&index, &note_path).is_some() => None,
+        None => return Err(ClusterMoveError::CannotSplit(note_path)),
+    };
+
+    let (Some(parent), Some(file_name), Some(stem)) = (
+        note_path.parent(),
+        note_path.file_name(),
+        note_path.file_stem(),
+    ) else {
+        return Err(ClusterMoveError::CannotSplit(note_path));
+    };
+
+    let stem = stem.to_string_lossy().to_string();
+
+    let cluster_root = match &opt_cluster {
+        Some(cluster) => cluster.root.clone(),
+        None => parent.join(&stem),
+    };
+
+    if opt_cluster.is_none() && cluster_root.exists() {
+        return Err(ClusterMoveError::NameTaken(cluster_root));
+    }
+
+    let category_folder = cluster_root.join(&context_type.folder);
+
+    let new_note_name = numbering::get_next_peripheral_note_name(
+        vault,
+        &category_folder,
+        &get_note_title_of_heading(heading),
+    )
+    .ok_or(ClusterMoveError::ListFailed(vault.path.clone()))?;
+
+    let new_note_path = category_folder.join(format!("{new_note_name}.md"));
+
+    if new_note_path.exists() {
+        return Err(ClusterMoveError::NameTaken(new_note_path));
+    }
+
+    let content = comm::read_file_content(&note_path)
+        .ok_or(ClusterMoveError::ReadFailed(note_path.clone()))?;
+
+    let section = find_heading_section(&content, heading).ok_or(
+        ClusterMoveError::HeadingNotFound(note_path.clone(), heading.to_owned()),
+    )?;
+
+    let lines = content.lines().collect::<Vec<_>>();
+
+    let section_lines = &lines[section.start + 1..section.end];
+
+    let sublinks = {
+        let section_content = section_lines.join("\n");
+
+        let mut mut_sublinks = comm::get_atx_heading_lines(&section_content)
+            .into_iter()
+            .flatten()
+            .map(|(_, text)| text.to_owned())
+            .collect::<Vec<_>>();
+
+        mut_sublinks.extend(
+            section_lines
+                .iter()
+                .flat_map(|line| Some(BLOCK_IDENTIFIER_PATTERN.captures(line)?[1].to_owned())),
+        );
+
+        mut_sublinks
+    };
+
+    let spawn_block_identifier = cluster_note::new_spawn_block_identifier(
+        &context_type.block_code,
+        &format!("{note_path:?}{heading}"),
+    );
+
+    let marker = match embed {
+        true => format!("![[{new_note_name}]]"),
+        false => format!("Spawn [[{new_note_name}]] {spawn_block_identifier}"),
+    };
+
+    // Links within the section to the rest of the note now have to name it
+    let body = SAME_NOTE_LINK_PATTERN
+        .replace_all(
+            section_lines.join("\n").trim(),
+            |caps: &Captures| match sublinks.iter().any(|sublink| *sublink == caps[1]) {
+                true => caps[0].to_owned(),
+                false => format!("[[{stem}#{}", &caps[1]),
+            },
+        )
+        .to_string()
+        .pipe_ref(|body| uncluster::promote_headings(body, section.level));
+
+    let new_note_content = {
+        let mut mut_parts = vec![format!("---\nparent: \"[[{stem}]]\"\n---")];
+
+        if !embed {
+            mut_parts.push(format!("From [[#{spawn_block_identifier}]] in [[{stem}]]"));
+        }
+
+        if !body.is_empty() {
+            mut_parts.push(body);
+        }
+
+        format!("{}\n", mut_parts.join("\n\n"))
+    };
+
+    let split_note_content = {
+        let parts = [
+            lines[..section.start].join("\n").trim_end().to_owned(),
+            marker,
+            lines[section.end..].join("\n").trim_start().to_owned(),
+        ];
+
+        let remaining = parts
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        redirect_links_to_split_section(&remaining, "", heading, &sublinks, &new_note_name)
+            .pipe_ref(|remaining| {
+                redirect_links_to_split_section(
+                    remaining,
+                    &stem,
+                    heading,
+                    &sublinks,
+                    &new_note_name,
+                )
+            })
+            .pipe(|remaining| format!("{remaining}\n"))
+    };
+
+    let edits = {
+        let mut mut_edits = NoteEdits::default();
+
+        for path in drivers::get_markdown_file_paths_in_vault(vault, true) {
+            if path != note_path {
+                edit_note(&mut mut_edits, &path, |content| {
+                    redirect_links_to_split_section(
+                        content,
+                        &stem,
+                        heading,
+                        &sublinks,
+                        &new_note_name,
+                    )
+                })?;
+            }
+        }
+
+        edit_note(&mut mut_edits, &note_path, |_| split_note_content)?;
+
+        if let Some(cluster) = &opt_cluster {
+            let note_names_per_folder = {
+                let mut mut_note_names_per_folder = cluster.note_names_per_folder.clone();
+
+                mut_note_names_per_folder
+                    .push((context_type.folder.clone(), vec![new_note_name.clone()]));
+
+                mut_note_names_per_folder
+            };
+
+            set_core_note_index(&mut mut_edits, &index, cluster, &note_names_per_folder)?;
+        }
+
+        let timeline_note = timeline::get_timeline_note_path(vault);
+
+        if timeline_note.is_file() {
+            edit_note(&mut mut_edits, &timeline_note, |content| {
+                timeline::append_timeline_entries(
+                    content,
+                    &comm::format_date(SystemTime::now()),
+                    &[timeline::render_created_entry(&new_note_name, &stem)],
+                )
+            })?;
+        }
+
+        mut_edits
+    };
+
+    let mutations = {
+        let mut mut_mutations = edits.into_mutations();
+
+        if opt_cluster.is_none() {
+            mut_mutations.push(Mutation::CreateDir {
+                path: cluster_root.clone(),
+            });
+            mut_mutations.push(Mutation::Rename {
+                from: note_path.clone(),
+                to: cluster_root.join(file_name),
+            });
+        }
+
+        if !index.is_dir(&category_folder) {
+            mut_mutations.push(Mutation::CreateDir {
+                path: category_folder,
+            });
+        }
+
+        mut_mutations.push(Mutation::WriteFile {
+            path: new_note_path,
+            opt_old_content: None,
+            new_content: new_note_content,
+        });
+
+        mut_mutations
+    };
+
+    Ok(mutations)
+}
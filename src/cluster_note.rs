@@ -1,84 +1,27 @@
 use std::{
-    cmp::min,
     path::{Path, PathBuf},
     str::FromStr,
+    sync::Arc,
+    time::SystemTime,
 };
 
-use crate::common::{
-    self as comm, BlockIdentifier, CategorizedDirEntry, GetEventText, GetEventTextInternalError,
-    ObsidianLink, ObsidianLinkItem, ObsidianLinkableItem,
+use crate::{
+    common::{
+        self as comm, BlockIdentifier, CategorizedDirEntry, DirListing, GetEventText,
+        GetEventTextInternalError, HeadingEvents, ObsidianLink, ObsidianLinkItem,
+        ObsidianLinkableItem, ProcessHeadingEventError, SourceLocation,
+    },
+    diagnostics::{ClusterRuleViolation, VaultDiagnostic},
+    exclusion::ExclusionRules,
+    vault_config::{ContextType, VaultConfig},
+    vault_index::{SymlinkPolicy, VaultIndex},
 };
 
 use itertools::Itertools;
 use pulldown_cmark::{Event, HeadingLevel, Tag};
-use tap::prelude::*;
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 
-pub const NUM_EXPECTED_FOLDERS: usize = 7;
-pub const _NUM_OLD_FORMAT_HEADINGS: usize = 6;
-
-pub const CONTEXT_TYPE_FOLDERS: [&str; NUM_EXPECTED_FOLDERS] = [
-    "entries",
-    "howtos",
-    "ideas",
-    "inferences",
-    "investigations",
-    "issues",
-    "tasks",
-];
-
-pub const CONTEXT_TYPE_BLOCK_IDENTIFIER_CODE: [&str; NUM_EXPECTED_FOLDERS] =
-    ["entry", "howto", "idea", "infer", "invst", "issue", "task"];
-
-pub const CONTEXT_TYPE_HEADINGS_SINGULAR: [&str; NUM_EXPECTED_FOLDERS] = [
-    "Entry",
-    "HowTo",
-    "Idea",
-    "Inference",
-    "Investigation",
-    "Issue",
-    "Task",
-];
-
-pub const CONTEXT_TYPE_HEADINGS: [&str; NUM_EXPECTED_FOLDERS] = [
-    "Entries",
-    "HowTos",
-    "Ideas",
-    "Inferences",
-    "Investigations",
-    "Issues",
-    "Tasks",
-];
-
-pub const OLD_FORMAT_HEADINGS: [&str; _NUM_OLD_FORMAT_HEADINGS] = [
-    "Tasks",
-    "Issues",
-    "HowTos",
-    "Investigations",
-    "Ideas",
-    "Side Notes",
-];
-
-pub fn context_type_is_doer(context_type_id: usize) -> bool {
-    if context_type_id > NUM_EXPECTED_FOLDERS {
-        return false;
-    }
-
-    let doer_context_type_headings = [
-        // "Entries",
-        "HowTos",
-        // "Ideas",
-        // "Inferences",
-        "Investigations",
-        "Issues",
-        "Tasks",
-    ];
-
-    doer_context_type_headings
-        .into_iter()
-        .any(|heading| heading == CONTEXT_TYPE_HEADINGS[context_type_id])
-}
-
 pub fn file_exists_in_folder_of_same_name(path: &Path) -> bool {
     if !path.is_file() {
         return false;
@@ -95,22 +38,74 @@ pub fn file_exists_in_folder_of_same_name(path: &Path) -> bool {
     inner_fn().unwrap_or_default()
 }
 
+pub const CLUSTER_ATTACHMENTS_FOLDER: &str = "attachments";
+pub const DRAWING_FILE_SUFFIX: &str = ".excalidraw.md";
+
+/// Excalidraw drawings are markdown files, but they belong to a note rather than being one
+pub fn is_drawing_file_name(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().ends_with(DRAWING_FILE_SUFFIX))
+}
+
+/// Anything in a cluster that is not a note, like pasted images, PDFs and drawings
+pub fn is_attachment_file_name(path: &Path) -> bool {
+    !has_markdown_extension(path) || is_drawing_file_name(path)
+}
+
+pub fn get_core_note_file_name(cluster_root_folder: &Path) -> Option<PathBuf> {
+    let folder_name = cluster_root_folder.file_name()?.to_string_lossy();
+
+    Some(format!("{folder_name}.md").into())
+}
+
+/// Checks the cluster root folder rules against the listing of the folder. The only note in a cluster root folder
+/// is the core note of the same name. Everything else is an attachment.
+pub fn is_cluster_root_folder_listing(folder: &Path, listing: &DirListing) -> Option<bool> {
+    let core_note_file_name = get_core_note_file_name(folder)?;
+
+    let note_file_names = listing
+        .files
+        .iter()
+        .filter(|file_name| !is_attachment_file_name(file_name))
+        .collect::<Vec<_>>();
+
+    if note_file_names != [&core_note_file_name] {
+        return Some(false);
+    }
+
+    Some(true)
+}
+
 pub fn is_cluster_root_folder(folder: &Path) -> Option<bool> {
-    if !comm::folder_has_file_of_same_name(folder)? {
+    let listing = comm::get_dir_listing(folder).ok()?;
+
+    is_cluster_root_folder_listing(folder, &listing)
+}
+
+pub fn is_cluster_attachments_folder_name(folder: &Path) -> bool {
+    folder.ends_with(CLUSTER_ATTACHMENTS_FOLDER)
+}
+
+pub fn is_cluster_attachments_folder(folder: &Path) -> Option<bool> {
+    if !folder.is_dir() || !is_cluster_attachments_folder_name(folder) {
         return Some(false);
     }
 
-    if comm::get_folder_child_file_count_non_recursive(folder)? != 1 {
+    if !is_cluster_root_folder(folder.parent()?)? {
         return Some(false);
     }
 
     Some(true)
 }
 
-pub fn is_cluster_category_folder(folder: &Path) -> Option<bool> {
+pub fn is_cluster_category_folder_name(folder: &Path, config: &VaultConfig) -> Option<bool> {
     let folder_name = folder.file_name()?.to_str()?;
 
-    if !CONTEXT_TYPE_FOLDERS.contains(&folder_name) {
+    Some(config.is_context_type_folder_name(folder_name))
+}
+
+pub fn is_cluster_category_folder(folder: &Path, config: &VaultConfig) -> Option<bool> {
+    if !is_cluster_category_folder_name(folder, config)? {
         return Some(false);
     }
 
@@ -126,6 +121,10 @@ pub fn is_markdown_file_path(path: &Path) -> bool {
         return false;
     }
 
+    has_markdown_extension(path)
+}
+
+pub fn has_markdown_extension(path: &Path) -> bool {
     let opt_ext = path.extension();
 
     if let Some(ext) = opt_ext {
@@ -140,7 +139,7 @@ pub fn is_markdown_file_path(path: &Path) -> bool {
 }
 
 pub fn is_cluster_core_file_path(path: &Path) -> Option<bool> {
-    if !is_markdown_file_path(path) {
+    if !is_markdown_file_path(path) || is_attachment_file_name(path) {
         return Some(false);
     }
 
@@ -153,32 +152,58 @@ pub fn is_cluster_core_file_path(path: &Path) -> Option<bool> {
     Some(true)
 }
 
-pub fn is_cluster_peripheral_file_path(path: &Path) -> Option<bool> {
-    if !is_markdown_file_path(path) {
+pub fn is_cluster_peripheral_file_path(path: &Path, config: &VaultConfig) -> Option<bool> {
+    if !is_markdown_file_path(path) || is_attachment_file_name(path) {
         return Some(false);
     }
 
     let parent = path.parent()?.to_path_buf();
 
-    if !is_cluster_category_folder(&parent)? {
+    if !is_cluster_category_folder(&parent, config)? {
         return Some(false);
     }
 
     Some(true)
 }
 
-pub fn is_normal_markdown_file_path(path: &Path) -> Option<bool> {
+pub fn is_normal_markdown_file_path(path: &Path, config: &VaultConfig) -> Option<bool> {
     if !is_markdown_file_path(path) {
         return Some(false);
     }
 
-    if is_cluster_core_file_path(path)? || is_cluster_peripheral_file_path(path)? {
+    if is_cluster_core_file_path(path)?
+        || is_cluster_peripheral_file_path(path, config)?
+        || is_cluster_attachment_file_path(path, config)?
+    {
         return Some(false);
     }
 
     Some(true)
 }
 
+/// Attachments sit next to the notes of a cluster, or anywhere under its attachments folder
+pub fn is_cluster_attachment_file_path(path: &Path, config: &VaultConfig) -> Option<bool> {
+    if !path.is_file() {
+        return Some(false);
+    }
+
+    let parent = path.parent()?;
+
+    if is_attachment_file_name(path)
+        && (is_cluster_root_folder(parent)? || is_cluster_category_folder(parent, config)?)
+    {
+        return Some(true);
+    }
+
+    for ancestor in parent.ancestors() {
+        if is_cluster_attachments_folder(ancestor)? {
+            return Some(true);
+        }
+    }
+
+    Some(false)
+}
+
 #[derive(Debug)]
 pub struct ClusterRootFolderPath {
     pub path: PathBuf,
@@ -194,6 +219,16 @@ impl ClusterRootFolderPath {
             path: path.to_owned(),
         })
     }
+
+    pub fn new_in_index(index: &VaultIndex, path: &Path) -> Option<Self> {
+        if !index.is_cluster_root_folder(path)? {
+            return None;
+        }
+
+        Some(Self {
+            path: path.to_owned(),
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -202,8 +237,18 @@ pub struct ClusterCategoryFolderPath {
 }
 
 impl ClusterCategoryFolderPath {
-    pub fn new(path: &Path) -> Option<Self> {
-        if !is_cluster_category_folder(path)? {
+    pub fn new(path: &Path, config: &VaultConfig) -> Option<Self> {
+        if !is_cluster_category_folder(path, config)? {
+            return None;
+        }
+
+        Some(Self {
+            path: path.to_owned(),
+        })
+    }
+
+    pub fn new_in_index(index: &VaultIndex, path: &Path) -> Option<Self> {
+        if !index.is_cluster_category_folder(path)? {
             return None;
         }
 
@@ -228,6 +273,16 @@ impl CoreNoteFilePath {
             path: path.to_owned(),
         })
     }
+
+    pub fn new_in_index(index: &VaultIndex, path: &Path) -> Option<Self> {
+        if !index.is_cluster_core_file_path(path)? {
+            return None;
+        }
+
+        Some(Self {
+            path: path.to_owned(),
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -236,8 +291,18 @@ pub struct PeripheralNoteFilePath {
 }
 
 impl PeripheralNoteFilePath {
-    pub fn new(path: &Path) -> Option<Self> {
-        if !is_cluster_peripheral_file_path(path)? {
+    pub fn new(path: &Path, config: &VaultConfig) -> Option<Self> {
+        if !is_cluster_peripheral_file_path(path, config)? {
+            return None;
+        }
+
+        Some(Self {
+            path: path.to_owned(),
+        })
+    }
+
+    pub fn new_in_index(index: &VaultIndex, path: &Path) -> Option<Self> {
+        if !index.is_cluster_peripheral_file_path(path)? {
             return None;
         }
 
@@ -253,8 +318,45 @@ pub struct NormalNoteFilePath {
 }
 
 impl NormalNoteFilePath {
-    pub fn new(path: &Path) -> Option<Self> {
-        if !is_normal_markdown_file_path(path)? {
+    pub fn new(path: &Path, config: &VaultConfig) -> Option<Self> {
+        if !is_normal_markdown_file_path(path, config)? {
+            return None;
+        }
+
+        Some(Self {
+            path: path.to_owned(),
+        })
+    }
+
+    pub fn new_in_index(index: &VaultIndex, path: &Path) -> Option<Self> {
+        if !index.is_normal_markdown_file_path(path)? {
+            return None;
+        }
+
+        Some(Self {
+            path: path.to_owned(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct AttachmentFilePath {
+    pub path: PathBuf,
+}
+
+impl AttachmentFilePath {
+    pub fn new(path: &Path, config: &VaultConfig) -> Option<Self> {
+        if !is_cluster_attachment_file_path(path, config)? {
+            return None;
+        }
+
+        Some(Self {
+            path: path.to_owned(),
+        })
+    }
+
+    pub fn new_in_index(index: &VaultIndex, path: &Path) -> Option<Self> {
+        if !index.is_cluster_attachment_file_path(path)? {
             return None;
         }
 
@@ -267,32 +369,93 @@ impl NormalNoteFilePath {
 pub fn get_core_note_file_from_cluster_root_folder(
     cluster_root_folder: &ClusterRootFolderPath,
 ) -> Option<CoreNoteFilePath> {
-    let dir_entries = comm::get_and_categorize_dir_entries(&cluster_root_folder.path).ok()?;
+    let core_note_file_name = get_core_note_file_name(&cluster_root_folder.path)?;
 
-    let core_note_path = dir_entries
-        .into_iter()
-        .find(|entry| matches!(entry, CategorizedDirEntry::File(_)))?
-        .pipe(|path| match path {
-            CategorizedDirEntry::File(dir_entry) => Some(CoreNoteFilePath::new(&dir_entry.path())?),
-            _ => None,
-        })?;
+    CoreNoteFilePath::new(&cluster_root_folder.path.join(core_note_file_name))
+}
 
-    Some(core_note_path)
+/// Attachment files of a cluster, next to its notes and under its attachments folder, sorted by path
+pub fn get_attachment_files_from_cluster_root_folder(
+    cluster_root_folder: &ClusterRootFolderPath,
+    config: &VaultConfig,
+) -> Option<Vec<AttachmentFilePath>> {
+    let attachment_files =
+        {
+            let mut mut_attachment_files = vec![];
+            let mut mut_pending = vec![cluster_root_folder.path.clone()];
+
+            while let Some(folder) = mut_pending.pop() {
+                let listing = comm::get_dir_listing(&folder).ok()?;
+
+                mut_attachment_files.extend(listing.files.iter().flat_map(|file_name| {
+                    AttachmentFilePath::new(&folder.join(file_name), config)
+                }));
+
+                let attachments_folder = cluster_root_folder.path.join(CLUSTER_ATTACHMENTS_FOLDER);
+
+                // Attachments are in the category folders and anywhere under the attachments folder
+                mut_pending.extend(
+                    listing
+                        .dirs
+                        .iter()
+                        .map(|dir_name| folder.join(dir_name))
+                        .filter(|dir| {
+                            dir.starts_with(&attachments_folder)
+                                || (folder == cluster_root_folder.path
+                                    && is_cluster_category_folder_name(dir, config)
+                                        .unwrap_or_default())
+                        }),
+                );
+            }
+
+            mut_attachment_files.sort_by(|a, b| a.path.cmp(&b.path));
+            mut_attachment_files
+        };
+
+    Some(attachment_files)
 }
 
+pub type CategoryFoldersWithPeripheralFiles =
+    Vec<(ClusterCategoryFolderPath, Vec<PeripheralNoteFilePath>)>;
+
+/// The category folders of a cluster with their peripheral notes. Paths breaking the cluster rules are left out
+/// and reported as diagnostics. None only if the cluster could not be listed.
 pub fn get_category_folders_with_peripheral_files_from_cluster_root_folder(
     cluster_root_folder: &ClusterRootFolderPath,
-) -> Option<Vec<(ClusterCategoryFolderPath, Vec<PeripheralNoteFilePath>)>> {
+    config: &VaultConfig,
+) -> Option<(CategoryFoldersWithPeripheralFiles, Vec<VaultDiagnostic>)> {
     let cluster_entries = comm::get_and_categorize_dir_entries(&cluster_root_folder.path).ok()?;
 
+    let mut mut_diagnostics = vec![];
+
+    let mut mut_diagnose = |path: PathBuf, violation| {
+        mut_diagnostics.push(VaultDiagnostic::new(
+            path,
+            cluster_root_folder.path.clone(),
+            violation,
+            config,
+        ))
+    };
+
     let category_folders_and_periphal_files = {
         let mut mut_category_folders_and_periphal_files = vec![];
 
         for cluster_entry in cluster_entries {
             match cluster_entry {
                 CategorizedDirEntry::Dir(category_dir_entry) => {
-                    let category_folder_path =
-                        ClusterCategoryFolderPath::new(&category_dir_entry.path())?;
+                    if is_cluster_attachments_folder_name(&category_dir_entry.path()) {
+                        continue;
+                    }
+
+                    let Some(category_folder_path) =
+                        ClusterCategoryFolderPath::new(&category_dir_entry.path(), config)
+                    else {
+                        mut_diagnose(
+                            category_dir_entry.path(),
+                            ClusterRuleViolation::UnknownCategoryFolder,
+                        );
+                        continue;
+                    };
 
                     let peripheral_note_files = {
                         let mut mut_peripheral_note_files = vec![];
@@ -304,15 +467,24 @@ pub fn get_category_folders_with_peripheral_files_from_cluster_root_folder(
 
                         for category_entry in category_enries {
                             match category_entry {
+                                // Anything that is not a peripheral note is an attachment
                                 CategorizedDirEntry::File(dir_entry) => {
-                                    let peripheral_note_file =
-                                        PeripheralNoteFilePath::new(&dir_entry.path())?;
-
-                                    mut_peripheral_note_files.push(peripheral_note_file);
+                                    if let Some(peripheral_note_file) =
+                                        PeripheralNoteFilePath::new(&dir_entry.path(), config)
+                                    {
+                                        mut_peripheral_note_files.push(peripheral_note_file);
+                                    }
                                 }
 
                                 // There shouldn't be anything else
-                                _ => return None,
+                                CategorizedDirEntry::Dir(dir_entry) => mut_diagnose(
+                                    dir_entry.path(),
+                                    ClusterRuleViolation::FolderInCategoryFolder,
+                                ),
+                                CategorizedDirEntry::Symlink(dir_entry) => mut_diagnose(
+                                    dir_entry.path(),
+                                    ClusterRuleViolation::SymlinkInCategoryFolder,
+                                ),
                             }
                         }
 
@@ -329,7 +501,7 @@ pub fn get_category_folders_with_peripheral_files_from_cluster_root_folder(
         mut_category_folders_and_periphal_files
     };
 
-    Some(category_folders_and_periphal_files)
+    Some((category_folders_and_periphal_files, mut_diagnostics))
 }
 
 /// Paths of consideration for updates in the vault
@@ -341,59 +513,55 @@ pub enum WorkingPath {
         core_note_file: CoreNoteFilePath,
         category_folders_with_peripheral_files:
             Vec<(ClusterCategoryFolderPath, Vec<PeripheralNoteFilePath>)>,
+        attachment_files: Vec<AttachmentFilePath>,
     },
 }
 
-pub fn get_working_item_paths_recursive(folder: &Path) -> Option<Vec<WorkingPath>> {
-    let dir_entries = comm::get_and_categorize_dir_entries(folder).ok()?;
+/// Working items together with the problems found while gathering them
+#[derive(Debug, Default)]
+pub struct WorkingItems {
+    pub items: Vec<WorkingPath>,
+    pub diagnostics: Vec<VaultDiagnostic>,
+}
 
-    let items = {
-        let mut mut_items = vec![];
+/// Working items of a folder within a vault, classified by the config of the vault rather than one in the folder
+pub fn get_working_item_paths_recursive(
+    folder: &Path,
+    config: Arc<VaultConfig>,
+) -> Option<Vec<WorkingPath>> {
+    let index = ExclusionRules::load(folder)
+        .map_err(Into::into)
+        .and_then(|rules| {
+            VaultIndex::build_with_rules(folder, None, rules, config, SymlinkPolicy::default())
+        })
+        .map_err(|e| log::error!("Failed to index {folder:?}: {e}"))
+        .ok()?;
 
-        for dir_entry in dir_entries {
-            match dir_entry {
-                comm::CategorizedDirEntry::Dir(dir_entry) => {
-                    let opt_cluster_root_folder = ClusterRootFolderPath::new(&dir_entry.path());
+    index.get_working_item_paths(folder)
+}
 
-                    match opt_cluster_root_folder {
-                        Some(cluster_root_folder) => {
-                            let core_note_file =
-                                get_core_note_file_from_cluster_root_folder(&cluster_root_folder)?;
-                            let category_folders_with_peripheral_files = get_category_folders_with_peripheral_files_from_cluster_root_folder(&cluster_root_folder)?;
+pub fn get_working_item_paths_in_vault(
+    vault_folder: &comm::ObsidianVaultPath,
+) -> Option<Vec<WorkingPath>> {
+    let index = VaultIndex::build_for_vault(vault_folder)
+        .map_err(|e| log::error!("Failed to index vault {:?}: {e}", vault_folder.path))
+        .ok()?;
 
-                            mut_items.push(WorkingPath::ClusterFolder {
-                                cluster_root_folder,
-                                core_note_file,
-                                category_folders_with_peripheral_files,
-                            });
-                        }
-                        None => {
-                            // Keep looking!
-                            mut_items.extend(get_working_item_paths_recursive(&dir_entry.path())?);
-                        }
-                    }
-                }
-                comm::CategorizedDirEntry::File(dir_entry) => {
-                    // Found outside a note cluster, so it should be a normal markdown file,
-                    // or just ignore it if it's an unrelated file.
-                    if let Some(normal_note_file) = NormalNoteFilePath::new(&dir_entry.path()) {
-                        mut_items.push(WorkingPath::Note(normal_note_file));
-                    }
-                }
-                comm::CategorizedDirEntry::Symlink(_) => continue,
-            }
-        }
+    index.get_working_item_paths(&vault_folder.path)
+}
 
-        mut_items
-    };
+pub fn get_working_items_in_vault(vault_folder: &comm::ObsidianVaultPath) -> Option<WorkingItems> {
+    let index = VaultIndex::build_for_vault(vault_folder)
+        .map_err(|e| log::error!("Failed to index vault {:?}: {e}", vault_folder.path))
+        .ok()?;
 
-    Some(items)
+    index.get_working_items(&vault_folder.path)
 }
 
-pub fn get_working_item_paths_in_vault(
-    vault_folder: &comm::ObsidianVaultPath,
-) -> Option<Vec<WorkingPath>> {
-    get_working_item_paths_recursive(&vault_folder.path)
+/// Obsidian note links may leave out the markdown extension and any number of leading folders
+pub fn note_link_matches_path(path: &Path, note_link: &str) -> bool {
+    path.ends_with(note_link)
+        || (has_markdown_extension(path) && path.with_extension("").ends_with(note_link))
 }
 
 pub fn note_link_to_path(vault: &[WorkingPath], note_link: &str) -> Option<PathBuf> {
@@ -401,7 +569,7 @@ pub fn note_link_to_path(vault: &[WorkingPath], note_link: &str) -> Option<PathB
         .iter()
         .flat_map(|item| match item {
             WorkingPath::Note(normal_note_file_path) => {
-                if normal_note_file_path.path.ends_with(note_link) {
+                if note_link_matches_path(&normal_note_file_path.path, note_link) {
                     Some(normal_note_file_path.path.clone())
                 } else {
                     None
@@ -412,14 +580,14 @@ pub fn note_link_to_path(vault: &[WorkingPath], note_link: &str) -> Option<PathB
                 category_folders_with_peripheral_files,
                 ..
             } => {
-                if core_note_file.path.ends_with(note_link) {
+                if note_link_matches_path(&core_note_file.path, note_link) {
                     Some(core_note_file.path.clone())
                 } else {
                     category_folders_with_peripheral_files
                         .iter()
                         .flat_map(|(_, files)| files)
                         .flat_map(|file| {
-                            if file.path.ends_with(note_link) {
+                            if note_link_matches_path(&file.path, note_link) {
                                 Some(file.path.clone())
                             } else {
                                 None
@@ -432,6 +600,114 @@ pub fn note_link_to_path(vault: &[WorkingPath], note_link: &str) -> Option<PathB
         .next()
 }
 
+/// The cluster a note belongs to, whether it is the core note or a peripheral note. This only looks at folders,
+/// so it also works for notes that were just removed.
+pub fn get_cluster_root_folder_of_note_path(
+    path: &Path,
+    config: &VaultConfig,
+) -> Option<ClusterRootFolderPath> {
+    let parent = path.parent()?;
+
+    if is_cluster_root_folder(parent)? {
+        return ClusterRootFolderPath::new(parent);
+    }
+
+    if is_cluster_category_folder(parent, config)? {
+        return ClusterRootFolderPath::new(parent.parent()?);
+    }
+
+    None
+}
+
+pub const CORE_NOTE_INDEX_START: &str = "%% index start %%";
+pub const CORE_NOTE_INDEX_END: &str = "%% index end %%";
+
+/// Lists the peripheral notes of a cluster under their context type, between the index markers. Written the way
+/// writeback renders it, so the two do not keep rewriting each other.
+pub fn render_core_note_index(
+    config: &VaultConfig,
+    category_folders_with_peripheral_files: &[(
+        ClusterCategoryFolderPath,
+        Vec<PeripheralNoteFilePath>,
+    )],
+) -> String {
+    let note_names_per_folder = category_folders_with_peripheral_files
+        .iter()
+        .flat_map(|(category_folder, files)| {
+            let folder = category_folder
+                .path
+                .file_name()?
+                .to_string_lossy()
+                .to_string();
+
+            let note_names = files
+                .iter()
+                .flat_map(|file| Some(file.path.file_stem()?.to_string_lossy().to_string()))
+                .collect::<Vec<_>>();
+
+            Some((folder, note_names))
+        })
+        .collect::<Vec<_>>();
+
+    render_core_note_index_of_note_names(config, &note_names_per_folder)
+}
+
+/// Like `render_core_note_index`, for peripheral note names by context type folder name
+pub fn render_core_note_index_of_note_names(
+    config: &VaultConfig,
+    note_names_per_folder: &[(String, Vec<String>)],
+) -> String {
+    let context_type_lines = config
+        .context_types
+        .iter()
+        .flat_map(|context_type| {
+            let heading = &context_type.heading_plural;
+
+            let note_names = note_names_per_folder
+                .iter()
+                .filter(|(folder, _)| *folder == context_type.folder)
+                .flat_map(|(_, note_names)| note_names)
+                .sorted()
+                .collect::<Vec<_>>();
+
+            if note_names.is_empty() {
+                return vec![];
+            }
+
+            let mut mut_lines = vec![format!("- {heading}")];
+
+            mut_lines.extend(note_names.iter().map(|name| format!("  - [[{name}]]")));
+
+            mut_lines
+        })
+        .collect::<Vec<_>>();
+
+    // Blank lines keep the end marker from being read as part of the list
+    format!(
+        "{CORE_NOTE_INDEX_START}\n\n{}\n\n{CORE_NOTE_INDEX_END}",
+        context_type_lines.join("\n")
+    )
+}
+
+/// Replaces a section of a note from the start marker through the end marker. None if the note has no such section.
+pub fn replace_marked_section(
+    content: &str,
+    start_marker: &str,
+    end_marker: &str,
+    section: &str,
+) -> Option<String> {
+    let start = content.find(start_marker)?;
+
+    let end = start + content[start..].find(end_marker)? + end_marker.len();
+
+    Some(format!("{}{section}{}", &content[..start], &content[end..]))
+}
+
+/// Replaces the index section of a core note. None if the note has no index section.
+pub fn replace_core_note_index(content: &str, index: &str) -> Option<String> {
+    replace_marked_section(content, CORE_NOTE_INDEX_START, CORE_NOTE_INDEX_END, index)
+}
+
 pub fn get_cluster_core_file_from_peripheral(
     vault: &[WorkingPath],
     peripheral_file: &PeripheralNoteFilePath,
@@ -444,44 +720,6 @@ pub fn get_cluster_core_file_from_peripheral(
     CoreNoteFilePath::new(&path)
 }
 
-#[derive(Debug, Clone)]
-pub enum OldFormatEntryType {
-    Task,
-    Issue,
-    HowTo,
-    Investigation,
-    Idea,
-    SideNote,
-}
-
-#[derive(Error, Debug)]
-pub enum OldFormatEntryTypeFromStrError {
-    #[error("Invalid type provided: {0:?}")]
-    InvalidType(String),
-}
-
-impl FromStr for OldFormatEntryType {
-    type Err = OldFormatEntryTypeFromStrError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s == "Tasks" {
-            Ok(Self::Task)
-        } else if s == "Issues" {
-            Ok(Self::Issue)
-        } else if s == "HowTos" {
-            Ok(Self::HowTo)
-        } else if s == "Investigations" {
-            Ok(Self::Investigation)
-        } else if s == "Ideas" {
-            Ok(Self::Idea)
-        } else if s == "Side Notes" {
-            Ok(Self::SideNote)
-        } else {
-            Err(OldFormatEntryTypeFromStrError::InvalidType(s.to_string()))
-        }
-    }
-}
-
 pub fn is_autonumbered_section_segment(s: &str) -> bool {
     // all are numbers
     s.split(".")
@@ -508,116 +746,224 @@ pub fn strip_autonumbered_sections(s: &str) -> String {
 
 #[derive(Debug, Clone)]
 pub struct OldFormatEntry<'a> {
-    pub entry_type: OldFormatEntryType,
+    /// The context type whose plural heading the entry is under
+    pub context_type: ContextType,
+
+    /// The entry heading as obsidian links to it, like ``Fix `foo` crash``
     pub entry_name: String,
+
+    /// The inline events of the entry heading, which the name flattens
+    pub heading_events: Vec<Event<'a>>,
     pub events: Vec<Event<'a>>,
 }
 
 #[derive(Error, Debug)]
 pub enum GetNoteOldFormatEntriesError {
-    #[error("Invalid entry type read: {0:?}")]
-    InvalidEntryType(#[from] OldFormatEntryTypeFromStrError),
+    #[error("No context type of the vault config has the category heading of {heading_context:?}")]
+    UnknownContextType {
+        event_index: usize,
+        heading_context: Vec<String>,
+    },
+
+    #[error("Content under {heading_context:?} is not under an entry heading")]
+    EventTypeAndNameNotConfigured {
+        event_index: usize,
+        heading_context: Vec<String>,
+    },
+
+    #[error("Could not read the heading after {heading_context:?}: {error}")]
+    UnparsableHeading {
+        event_index: usize,
+        heading_context: Vec<String>,
+        error: ProcessHeadingEventError,
+    },
+}
 
-    #[error("Tried to parse old entry records but could not infer placement")]
-    EventTypeAndNameNotConfigured,
+impl GetNoteOldFormatEntriesError {
+    /// Index of the event the error is about, in the events of the note
+    pub fn event_index(&self) -> usize {
+        match self {
+            Self::UnknownContextType { event_index, .. }
+            | Self::EventTypeAndNameNotConfigured { event_index, .. }
+            | Self::UnparsableHeading { event_index, .. } => *event_index,
+        }
+    }
+}
+
+/// Heading levels of the old format, like `# Tasks` categories with `## Fix crash` entries under them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OldFormatLayout {
+    pub category_level: HeadingLevel,
+    pub entry_level: HeadingLevel,
 }
 
+impl Default for OldFormatLayout {
+    fn default() -> Self {
+        Self {
+            category_level: HeadingLevel::H1,
+            entry_level: HeadingLevel::H2,
+        }
+    }
+}
+
+impl OldFormatLayout {
+    /// Entries one level under the categories. H6 categories can't have entries.
+    pub fn with_category_level(category_level: HeadingLevel) -> Option<Self> {
+        let entry_level = HeadingLevel::try_from(category_level as usize + 1).ok()?;
+
+        Some(Self {
+            category_level,
+            entry_level,
+        })
+    }
+}
+
+/// The layout of the first old format category heading of the note, like `## Tasks` under a title H1. Notes
+/// without one get the default layout.
+pub fn detect_old_format_layout(events: &[Event], config: &VaultConfig) -> OldFormatLayout {
+    events
+        .iter()
+        .enumerate()
+        .flat_map(|(i, event)| match event {
+            Event::Start(Tag::Heading { .. }) => comm::parse_heading_events(&events[i..]),
+            _ => None,
+        })
+        .find(|heading| {
+            let text = strip_autonumbered_sections(&heading.text);
+
+            config
+                .old_format_headings
+                .iter()
+                .any(|old_format_heading| old_format_heading == text.trim())
+        })
+        .and_then(|heading| OldFormatLayout::with_category_level(heading.level))
+        .unwrap_or_default()
+}
+
+/// Entries are named by their heading as written in the content, which the events must be parsed from
 pub fn get_note_old_format_entries<'a>(
+    content: &str,
     events: &'a [Event<'a>],
+    config: &VaultConfig,
+    layout: &OldFormatLayout,
 ) -> Result<Vec<OldFormatEntry<'a>>, GetNoteOldFormatEntriesError> {
-    // Let's first turn this into groups of H1/H2/SubH2
+    let offsets = comm::get_markdown_event_offsets(content);
+
+    // Let's first turn this into groups of categories/entries/sub entry content, each with the index of its first
+    // event
     #[derive(Debug)]
     enum Grouped<'a> {
-        H1(String),
-        H2(String),
+        /// A heading above the categories, like the title of the note, which ends the category before it
+        Above,
+        Category(String),
+        Entry(HeadingEvents<'a>),
         Content(&'a [Event<'a>]),
     }
 
     let grouped_events = {
         let mut mut_grouped_events = vec![];
+        let mut mut_heading_context = vec![];
         let mut mut_cur: usize = 0;
 
         while mut_cur < events.len() {
-            match comm::process_heading_event_of_level(&HeadingLevel::H1, &events[mut_cur..]) {
-                Ok(heading1) => {
-                    mut_grouped_events.push(Grouped::H1(heading1));
-                    mut_cur += 3;
+            let opt_heading_level = match &events[mut_cur] {
+                Event::Start(Tag::Heading { level, .. }) => Some(*level),
+                _ => None,
+            };
+
+            match opt_heading_level {
+                Some(level) if level < layout.category_level => {
+                    let heading = comm::parse_heading_events(&events[mut_cur..]).ok_or(
+                        GetNoteOldFormatEntriesError::UnparsableHeading {
+                            event_index: mut_cur,
+                            heading_context: mut_heading_context.clone(),
+                            error: ProcessHeadingEventError::MissingEndTag,
+                        },
+                    )?;
+
+                    mut_heading_context = vec![];
+                    mut_grouped_events.push((mut_cur, Grouped::Above));
+                    mut_cur += heading.event_count();
                 }
-                Err(_) => {
-                    match comm::process_heading_event_of_level(
-                        &HeadingLevel::H2,
-                        &events[mut_cur..],
-                    ) {
-                        Ok(heading2) => {
-                            mut_grouped_events.push(Grouped::H2(heading2));
-                            mut_cur += 3;
+                Some(level) if level == layout.category_level || level == layout.entry_level => {
+                    match comm::process_heading_event_of_level(&level, &events[mut_cur..])
+                        .map(|heading| heading.with_source_text(content, &offsets, mut_cur))
+                    {
+                        Ok(heading) if level == layout.category_level => {
+                            let event_count = heading.event_count();
+
+                            mut_heading_context = vec![heading.text.clone()];
+                            mut_grouped_events.push((mut_cur, Grouped::Category(heading.text)));
+                            mut_cur += event_count;
                         }
-                        Err(_) => {
-                            if !mut_grouped_events.is_empty() {
-                                // Process all sub H2 under the same group
-                                let mut mut_inner_cur = mut_cur;
-
-                                while mut_inner_cur < events.len() {
-                                    let keep_going = match &events[mut_inner_cur] {
-                                        Event::Start(Tag::Heading { level, .. }) => {
-                                            // keep growing
-                                            *level != HeadingLevel::H1 && *level != HeadingLevel::H2
-                                        }
-                                        _ => true,
-                                    };
-
-                                    if keep_going {
-                                        mut_inner_cur += 1;
-                                    } else {
-                                        break;
-                                    }
-                                }
+                        Ok(heading) => {
+                            let event_count = heading.event_count();
 
-                                if mut_inner_cur - mut_cur > 0 {
-                                    mut_grouped_events
-                                        .push(Grouped::Content(&events[mut_cur..mut_inner_cur]));
-                                    mut_cur = mut_inner_cur;
-                                } else {
-                                    // We should not get here. This should've been handled by H1 or H2.
-                                    log::error!(
-                                        "Could not get any content within H2: {:?}",
-                                        &events[mut_cur..mut_cur + min(5, events.len())]
-                                    );
-
-                                    // mut_cur += 1;
-                                    panic!("Remove those!");
-                                }
-                            } else {
-                                mut_cur += 1;
-                            }
+                            mut_heading_context.truncate(1);
+                            mut_heading_context.push(heading.text.clone());
+                            mut_grouped_events.push((mut_cur, Grouped::Entry(heading)));
+                            mut_cur += event_count;
+                        }
+                        // Before any group, unreadable headings are skipped like other content
+                        Err(_) if mut_grouped_events.is_empty() => mut_cur += 1,
+                        // A category or entry heading that could not be read, like an empty one
+                        Err(error) => {
+                            return Err(GetNoteOldFormatEntriesError::UnparsableHeading {
+                                event_index: mut_cur,
+                                heading_context: mut_heading_context,
+                                error,
+                            });
                         }
                     }
                 }
+                _ if mut_grouped_events.is_empty() => mut_cur += 1,
+                _ => {
+                    // Process all headings under the entry level in the same group
+                    let content_end = events[mut_cur + 1..]
+                        .iter()
+                        .position(|event| {
+                            matches!(
+                                event,
+                                Event::Start(Tag::Heading { level, .. }) if *level <= layout.entry_level
+                            )
+                        })
+                        .map_or(events.len(), |pos| mut_cur + 1 + pos);
+
+                    mut_grouped_events
+                        .push((mut_cur, Grouped::Content(&events[mut_cur..content_end])));
+                    mut_cur = content_end;
+                }
             }
         }
 
         mut_grouped_events
     };
 
-    // Skip all H1 content and what's under it and only keep relevant ones (old format events)
+    // Skip all category content and what's under it and only keep relevant ones (old format events)
     let relevant_grouped_events = {
         let mut mut_relevant_grouped_events = vec![];
-        let mut mut_h1_is_relevant = false;
+        let mut mut_category_is_relevant = false;
 
         grouped_events.iter().for_each(|grp| {
-            match grp {
-                Grouped::H1(heading1) => {
-                    if !OLD_FORMAT_HEADINGS.contains(&strip_autonumbered_sections(heading1).trim())
+            match &grp.1 {
+                Grouped::Above => mut_category_is_relevant = false,
+                Grouped::Category(category) => {
+                    let category = strip_autonumbered_sections(category);
+
+                    if !config
+                        .old_format_headings
+                        .iter()
+                        .any(|heading| heading == category.trim())
                     {
                         // Not relevant, not an old format heading
-                        mut_h1_is_relevant = false;
+                        mut_category_is_relevant = false;
                     } else {
-                        mut_h1_is_relevant = true;
+                        mut_category_is_relevant = true;
                         mut_relevant_grouped_events.push(grp);
                     }
                 }
                 _ => {
-                    if !mut_h1_is_relevant {
+                    if !mut_category_is_relevant {
                         // skip
                     } else {
                         mut_relevant_grouped_events.push(grp);
@@ -629,38 +975,51 @@ pub fn get_note_old_format_entries<'a>(
         mut_relevant_grouped_events
     };
 
-    // Now the old entry content are the Content groups, their name is in H2, and their category in H1.
+    // Now the old entry content are the Content groups, their name is in the entry heading, and their type in the
+    // category heading.
     let old_format_entries = {
         let mut mut_old_format_entries = vec![];
-        let mut mut_opt_last_entry_type: Option<OldFormatEntryType> = None;
-        let mut mut_opt_last_entry_name: Option<String> = None;
+        let mut mut_opt_last_context_type: Option<&ContextType> = None;
+        let mut mut_opt_last_entry_heading: Option<&HeadingEvents<'a>> = None;
+        let mut mut_heading_context = vec![];
 
-        for grp in relevant_grouped_events {
+        for (event_index, grp) in relevant_grouped_events {
             match grp {
-                Grouped::H1(heading1) => {
-                    let entry_type =
-                        OldFormatEntryType::from_str(strip_autonumbered_sections(heading1).trim())
-                            .map_err(GetNoteOldFormatEntriesError::InvalidEntryType)?;
-
-                    mut_opt_last_entry_type = Some(entry_type)
+                Grouped::Above => (),
+                Grouped::Category(category) => {
+                    mut_heading_context = vec![category.clone()];
+                    mut_opt_last_entry_heading = None;
+
+                    let context_type = config
+                        .get_context_type_by_heading(strip_autonumbered_sections(category).trim())
+                        .ok_or(GetNoteOldFormatEntriesError::UnknownContextType {
+                            event_index: *event_index,
+                            heading_context: mut_heading_context.clone(),
+                        })?;
+
+                    mut_opt_last_context_type = Some(context_type)
                 }
-                Grouped::H2(heading2) => {
-                    mut_opt_last_entry_name = Some(heading2.clone());
+                Grouped::Entry(entry_heading) => {
+                    mut_heading_context.truncate(1);
+                    mut_heading_context.push(entry_heading.text.clone());
+                    mut_opt_last_entry_heading = Some(entry_heading);
                 }
                 Grouped::Content(events) => {
-                    let entry_type = mut_opt_last_entry_type.clone().pipe(|opt| match opt {
-                        Some(val) => Ok(val),
-                        None => Err(GetNoteOldFormatEntriesError::EventTypeAndNameNotConfigured),
-                    })?;
-
-                    let entry_name = mut_opt_last_entry_name.clone().pipe(|opt| match opt {
-                        Some(val) => Ok(val),
-                        None => Err(GetNoteOldFormatEntriesError::EventTypeAndNameNotConfigured),
-                    })?;
+                    let (Some(context_type), Some(entry_heading)) =
+                        (mut_opt_last_context_type, mut_opt_last_entry_heading)
+                    else {
+                        return Err(
+                            GetNoteOldFormatEntriesError::EventTypeAndNameNotConfigured {
+                                event_index: *event_index,
+                                heading_context: mut_heading_context,
+                            },
+                        );
+                    };
 
                     mut_old_format_entries.push(OldFormatEntry {
-                        entry_type,
-                        entry_name,
+                        context_type: context_type.clone(),
+                        entry_name: entry_heading.text.clone(),
+                        heading_events: entry_heading.inline_events.clone(),
                         events: events.to_vec(),
                     })
                 }
@@ -673,6 +1032,36 @@ pub fn get_note_old_format_entries<'a>(
     Ok(old_format_entries)
 }
 
+/// An old format parsing failure, with where it happened in the note
+#[derive(Error, Debug)]
+#[error("{location}: {error}")]
+pub struct LocatedOldFormatEntriesError {
+    pub location: SourceLocation,
+    pub error: GetNoteOldFormatEntriesError,
+}
+
+/// Like `get_note_old_format_entries`, but failures say where in the note they happened. The events must be
+/// parsed from the content.
+pub fn get_note_old_format_entries_located<'a>(
+    path: &Path,
+    content: &str,
+    events: &'a [Event<'a>],
+    config: &VaultConfig,
+    layout: &OldFormatLayout,
+) -> Result<Vec<OldFormatEntry<'a>>, LocatedOldFormatEntriesError> {
+    get_note_old_format_entries(content, events, config, layout).map_err(|error| {
+        let offset = comm::get_markdown_event_offsets(content)
+            .get(error.event_index())
+            .map(|range| range.start)
+            .unwrap_or_default();
+
+        LocatedOldFormatEntriesError {
+            location: SourceLocation::of_offset(path, content, offset),
+            error,
+        }
+    })
+}
+
 #[derive(Debug)]
 pub enum SpawnMetadata<'a> {
     Spawning {
@@ -701,6 +1090,19 @@ impl<'a> GetEventText for SpawnMetadata<'a> {
     }
 }
 
+/// A new `^spawn-{block_code}-{hex6}` block identifier. The hex is derived from the seed and the current time, so
+/// splitting the same section twice gives different identifiers.
+pub fn new_spawn_block_identifier(block_code: &str, seed: &str) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+
+    let hash = format!("{:x}", Sha256::digest(format!("{seed}{nanos}").as_bytes()));
+
+    format!("^spawn-{block_code}-{}", &hash[..6])
+}
+
 /// This method fails on detecting patterns that should have been fixed manually.
 pub fn extract_spawn_metadata_from_old_format<'a>(
     linkables: &'a [ObsidianLinkableItem<'a>],
@@ -3,9 +3,19 @@ use std::{
     fs,
     path::{Path, PathBuf},
 };
+use tap::prelude::*;
 use thiserror::Error;
 
-use crate::cluster_note::*;
+use crate::{
+    cluster_move::{self, ClusterMoveError},
+    cluster_note::*,
+    common::{
+        self as comm, ExtractOBsidianMdLinksError, ObsidianVaultPath,
+        RenderEventsToCommonMarkdownError,
+    },
+    mutation::{self, Mutation, NoteEdits},
+    vault_config::VaultConfig,
+};
 
 pub fn remove_old_format_entries_from_note<'a>(
     _path: &Path,
@@ -116,23 +126,152 @@ pub fn turn_note_into_cluster_note(
     Ok(out)
 }
 
-pub fn create_new_peripheral_note_from_old_format_entry<'a>(
-    _root: ClusterRootFolderPath,
-    _entry: OldFormatEntry<'a>,
-) {
-    todo!()
+/// Why a note was skipped by `extract_old_format_records`
+#[derive(Error, Debug)]
+pub enum ExtractOldFormatRecordsError {
+    #[error("Could not read content of {0:?}")]
+    ReadFailed(PathBuf),
+
+    #[error("{0}")]
+    Entries(#[from] LocatedOldFormatEntriesError),
+
+    #[error("Links of {0:?} could not be parsed: {1}")]
+    UnparsableLinks(PathBuf, ExtractOBsidianMdLinksError),
+
+    #[error("{0:?} could not be rendered back to markdown: {1:?}")]
+    NotRenderable(PathBuf, RenderEventsToCommonMarkdownError),
+
+    #[error("Failed to turn {0:?} into a cluster: {1}")]
+    TurnIntoCluster(PathBuf, TurnNoteIntoClusterNoteError),
+
+    #[error("Failed to write {0:?}: {1}")]
+    WriteFailed(PathBuf, std::io::Error),
 }
 
-pub fn generate_index_for_core_note(_core_note: CoreNoteFilePath) -> Option<()> {
-    todo!()
+/// Plans moving an old format entry of the note into a new peripheral note of its context type, named with the next
+/// free number of its sequence. The entry is split out of the note like any heading section, leaving a spawn marker.
+pub fn create_new_peripheral_note_from_old_format_entry(
+    vault: &ObsidianVaultPath,
+    note_path: &Path,
+    entry: &OldFormatEntry,
+) -> Result<Vec<Mutation>, ClusterMoveError> {
+    cluster_move::plan_split_heading_section(
+        vault,
+        note_path,
+        &entry.entry_name,
+        &entry.context_type.folder,
+        false,
+    )
+}
+
+#[derive(Error, Debug)]
+pub enum GenerateIndexForCoreNoteError {
+    #[error("Core note is not in a cluster root folder: {0:?}")]
+    NotInCluster(PathBuf),
+
+    #[error("Failed to list the peripheral notes of cluster {0:?}")]
+    ListFailed(PathBuf),
+
+    #[error("Failed to read core note {0:?}")]
+    ReadFailed(PathBuf),
+
+    #[error("Got IO Error {0:?}")]
+    Io(#[from] std::io::Error),
 }
 
+/// Regenerates the index section of the core note if it has one. Returns whether the note changed.
+pub fn generate_index_for_core_note(
+    core_note: &CoreNoteFilePath,
+    config: &VaultConfig,
+) -> Result<bool, GenerateIndexForCoreNoteError> {
+    let cluster_root_folder = core_note
+        .path
+        .parent()
+        .and_then(ClusterRootFolderPath::new)
+        .ok_or(GenerateIndexForCoreNoteError::NotInCluster(
+            core_note.path.clone(),
+        ))?;
+
+    // Misplaced files are not peripheral notes, so they are left out of the index
+    let (category_folders_with_peripheral_files, _diagnostics) =
+        get_category_folders_with_peripheral_files_from_cluster_root_folder(
+            &cluster_root_folder,
+            config,
+        )
+        .ok_or(GenerateIndexForCoreNoteError::ListFailed(
+            cluster_root_folder.path.clone(),
+        ))?;
+
+    let content = comm::read_file_content(&core_note.path).ok_or(
+        GenerateIndexForCoreNoteError::ReadFailed(core_note.path.clone()),
+    )?;
+
+    let index = render_core_note_index(config, &category_folders_with_peripheral_files);
+
+    match replace_core_note_index(&content, &index) {
+        Some(new_content) if new_content != content => {
+            comm::write_file_content(&new_content, &core_note.path)?;
+
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Plans pointing links to an old format entry at the peripheral note it became. With a parent note, the links name
+/// the entry heading in it, otherwise the old note name. Timeline notes are rewritten too. None if a note can't be
+/// read, before anything is written.
 pub fn redirect_links_to_new_peripheral_note(
-    _vault: &[WorkingPath],
-    _opt_parent_link: Option<String>,
-    _old_link: String,
-    _new_link: String,
-) -> Option<()> {
-    // Include Timeline strings too
-    todo!()
+    vault: &[WorkingPath],
+    opt_parent_link: Option<String>,
+    old_link: String,
+    new_link: String,
+) -> Option<Vec<Mutation>> {
+    let note_paths = vault.iter().flat_map(|item| match item {
+        WorkingPath::Note(note) => vec![&note.path],
+        WorkingPath::ClusterFolder {
+            core_note_file,
+            category_folders_with_peripheral_files,
+            ..
+        } => std::iter::once(&core_note_file.path)
+            .chain(
+                category_folders_with_peripheral_files
+                    .iter()
+                    .flat_map(|(_, files)| files.iter().map(|file| &file.path)),
+            )
+            .collect(),
+    });
+
+    let mut mut_edits = NoteEdits::default();
+
+    for path in note_paths {
+        mut_edits.edit(path, |content| match &opt_parent_link {
+            Some(parent_link) => {
+                let is_parent_note = path
+                    .file_stem()
+                    .is_some_and(|stem| stem.to_string_lossy() == *parent_link);
+
+                cluster_move::redirect_links_to_split_section(
+                    content,
+                    parent_link,
+                    &old_link,
+                    &[],
+                    &new_link,
+                )
+                .pipe(|content| match is_parent_note {
+                    true => cluster_move::redirect_links_to_split_section(
+                        &content,
+                        "",
+                        &old_link,
+                        &[],
+                        &new_link,
+                    ),
+                    false => content,
+                })
+            }
+            None => mutation::rename_note_links(content, &old_link, &new_link),
+        })?;
+    }
+
+    Some(mut_edits.into_mutations())
 }
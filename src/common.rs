@@ -1,15 +1,26 @@
 use itertools::Itertools;
 use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd, TextMergeStream};
 use pulldown_cmark_to_cmark::cmark_with_options;
+use serde::{Deserialize, Serialize};
 use std::{
-    fs::{DirEntry, File},
+    ffi::OsStr,
+    fmt,
+    fs::{self, DirEntry, File},
     io::Read,
+    ops::Range,
     path::{Path, PathBuf},
     str::FromStr,
+    sync::Arc,
+    time::SystemTime,
 };
 use tap::prelude::*;
 use thiserror::Error;
 
+use crate::{
+    vault_config::{LoadVaultConfigError, VaultConfig},
+    vault_index::TraversalOptions,
+};
+
 #[derive(Debug)]
 pub enum CategorizedDirEntry {
     Dir(DirEntry),
@@ -67,36 +78,82 @@ pub fn get_and_categorize_dir_entries(
     Ok(categorized)
 }
 
-pub fn get_folder_child_file_count_non_recursive(folder: &Path) -> Option<usize> {
-    let dir_entries = get_and_categorize_dir_entries(folder).ok()?;
+/// Names of the direct children of a folder by category, sorted by name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DirListing {
+    pub files: Vec<PathBuf>,
+    pub dirs: Vec<PathBuf>,
+    pub symlinks: Vec<PathBuf>,
+}
 
-    let count = dir_entries
-        .into_iter()
-        .filter(|e| matches!(e, CategorizedDirEntry::File(..)))
-        .count();
+impl DirListing {
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.dirs.is_empty() && self.symlinks.is_empty()
+    }
+
+    /// Checks that there is a direct child file with the given name (ignoring extensions).
+    pub fn has_file_of_same_name(&self, folder_name: &OsStr) -> bool {
+        let found = self
+            .files
+            .iter()
+            .filter(|file_name| match file_name.file_stem() {
+                Some(filename) => folder_name == filename,
+                None => false,
+            })
+            .count();
+
+        found == 1
+    }
+}
+
+pub fn get_dir_listing(path: &Path) -> Result<DirListing, GetAndCategorizeDirEntriesError> {
+    let dir_entries = get_and_categorize_dir_entries(path)?;
 
-    Some(count)
+    let listing = {
+        let mut mut_listing = DirListing::default();
+
+        for entry in dir_entries {
+            match entry {
+                CategorizedDirEntry::Dir(dir_entry) => {
+                    mut_listing.dirs.push(dir_entry.file_name().into())
+                }
+                CategorizedDirEntry::File(dir_entry) => {
+                    mut_listing.files.push(dir_entry.file_name().into())
+                }
+                CategorizedDirEntry::Symlink(dir_entry) => {
+                    mut_listing.symlinks.push(dir_entry.file_name().into())
+                }
+            }
+        }
+
+        mut_listing.files.sort();
+        mut_listing.dirs.sort();
+        mut_listing.symlinks.sort();
+
+        mut_listing
+    };
+
+    Ok(listing)
+}
+
+pub fn get_folder_child_file_count_non_recursive(folder: &Path) -> Option<usize> {
+    let listing = get_dir_listing(folder).ok()?;
+
+    Some(listing.file_count())
 }
 
 /// Checks that the folder has a direct child file with the same name (ignoring extensions).
 /// Returns None on error.
 pub fn folder_has_file_of_same_name(folder: &Path) -> Option<bool> {
-    let dir_entries = get_and_categorize_dir_entries(folder).ok()?;
+    let listing = get_dir_listing(folder).ok()?;
 
     let folder_name = folder.file_name()?;
 
-    let found = dir_entries
-        .into_iter()
-        .filter(|entry| match entry {
-            CategorizedDirEntry::File(dir_entry) => match dir_entry.path().file_stem() {
-                Some(filename) => folder_name == filename,
-                None => false,
-            },
-            _ => false,
-        })
-        .count();
-
-    Some(found == 1)
+    Some(listing.has_file_of_same_name(folder_name))
 }
 
 pub fn read_file_content(path: &Path) -> Option<String> {
@@ -119,6 +176,34 @@ pub fn write_file_content(s: &str, output_path: &Path) -> std::io::Result<usize>
     std::io::Write::write(&mut file, s.as_bytes())
 }
 
+pub const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The UTC date of the time as `YYYY-MM-DD`
+pub fn format_date(time: SystemTime) -> String {
+    let days = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() / SECS_PER_DAY)
+        .unwrap_or_default() as i64;
+
+    // Days to civil date, counting in eras of 400 years that start on March 1st
+    let shifted_days = days + 719_468;
+    let era = shifted_days.div_euclid(146_097);
+    let day_of_era = shifted_days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = match shifted_month < 10 {
+        true => shifted_month + 3,
+        false => shifted_month - 9,
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
 #[derive(Error, Debug)]
 pub enum RenderEventsToCommonMarkdownError {
     #[error("Failed to convert events back to cmark: {0:?}")]
@@ -167,6 +252,14 @@ pub fn adhoc_fix_rendered_markdown_output_for_obsidian(
         // log::trace!("{}", _s)
     }
 
+    /// Whether the last line of `s` so far is only made of leading whitespace
+    fn ends_in_line_indentation(s: &str) -> bool {
+        match s.rsplit_once('\n') {
+            Some((_, last_line)) => last_line.chars().all(|c| c == ' ' || c == '\t'),
+            None => false,
+        }
+    }
+
     let new_content1 = {
         let mut mut_out = String::new();
 
@@ -208,6 +301,8 @@ pub fn adhoc_fix_rendered_markdown_output_for_obsidian(
                     // - In some instances, math is changes by removing `_` for `*`.
                     // - In case of subtask - [ ] , they may change them to - \[ ] with "- \\". This needs to be modified.
                     //   Note that this is for the setting of using "-" bullets for markdown rendering.
+                    // - Trailing spaces before a new line are dropped, but the indentation after it is kept so
+                    //   nested lists are not flattened.
 
                     if s.trim() != "\\"
                         && s.trim() != ""
@@ -222,7 +317,12 @@ pub fn adhoc_fix_rendered_markdown_output_for_obsidian(
                         mut_out += &s.replace("\\", "");
                     } else if s.trim() == "" && s.contains("\n") {
                         log_(&format!("+0.2 \"{disp_s}\" len: {}", s.len()));
-                        mut_out += &s.replace(" ", "").replace("\t", "");
+                        let (trailing, indentation) =
+                            s.rsplit_once('\n').expect("Checked to contain a new line");
+
+                        mut_out += &trailing.replace(" ", "").replace("\t", "");
+                        mut_out += "\n";
+                        mut_out += indentation;
                     } else {
                         log_(&format!("+0.3 S \"{disp_s}\" len: {}", s.len()));
                     }
@@ -236,6 +336,7 @@ pub fn adhoc_fix_rendered_markdown_output_for_obsidian(
     // Now we run a character diff processing on some remaining items
     {
         let mut mut_out = String::new();
+        let mut mut_replacing_tab_indentation = false;
 
         log_(&format!("<new_content1>\n{new_content1}\n</new_content1>"));
 
@@ -250,7 +351,13 @@ pub fn adhoc_fix_rendered_markdown_output_for_obsidian(
                 .replace(" ", "\\s");
 
             match change.tag() {
-                similar::ChangeTag::Equal => mut_out += s,
+                similar::ChangeTag::Equal => {
+                    if s.trim() != "" || s == "\n" {
+                        mut_replacing_tab_indentation = false;
+                    }
+
+                    mut_out += s
+                }
                 similar::ChangeTag::Delete => {
                     // Remaining additions from old content include:
                     // - Obsidian sometimes adds escaping. For example for obsidian link title bars in tables.
@@ -258,6 +365,9 @@ pub fn adhoc_fix_rendered_markdown_output_for_obsidian(
                     if s.trim() == "\\" || s.trim() == "_" {
                         log_(&format!("-1.0 \"{disp_s}\" len: {}", s.len()));
                         mut_out += s;
+                    } else if s == "\t" && ends_in_line_indentation(&mut_out) {
+                        log_(&format!("-1.2 S \"{disp_s}\" len: {}", s.len()));
+                        mut_replacing_tab_indentation = true;
                     } else {
                         log_(&format!("-1.1 S \"{disp_s}\" len: {}", s.len()));
                     }
@@ -267,6 +377,7 @@ pub fn adhoc_fix_rendered_markdown_output_for_obsidian(
                     // - Removing escaped bars from obsidian links within tables (tested by: test_obsidian_patch_writeback table-002)
                     // - extra quote ">" lines are added and they shouldn't be
                     // - Sometimes when * bullets are replaced for dashes, a space is not inserted.
+                    // - Tab indentation of nested list items, which is re-rendered with spaces.
 
                     if s.trim() != "\\"
                         && s.trim() != ""
@@ -279,6 +390,12 @@ pub fn adhoc_fix_rendered_markdown_output_for_obsidian(
                     } else if s.trim() == "-" {
                         log_(&format!("+1.1 \"{disp_s}\" len: {}", s.len()));
                         mut_out += "- ";
+                    } else if s == " "
+                        && mut_replacing_tab_indentation
+                        && ends_in_line_indentation(&mut_out)
+                    {
+                        log_(&format!("+1.3 \"{disp_s}\" len: {}", s.len()));
+                        mut_out += s;
                     } else {
                         log_(&format!("+1.2 S \"{disp_s}\" len: {}", s.len()));
                     }
@@ -290,12 +407,103 @@ pub fn adhoc_fix_rendered_markdown_output_for_obsidian(
     }
 }
 
+/// Parses, renders back and applies the obsidian fixes to markdown content. This is what `writeback` does
+/// to every note.
+pub fn writeback_markdown_content(
+    content: &str,
+) -> Result<String, RenderEventsToCommonMarkdownError> {
+    let events = parse_markdown_file(content);
+
+    let new_content = render_events_to_common_markdown(&events)?
+        .pipe(|new_content| adhoc_fix_rendered_markdown_output_for_obsidian(content, &new_content));
+
+    Ok(new_content)
+}
+
+#[derive(Error, Debug)]
+pub enum WritebackMarkdownFileError {
+    #[error("Could not read content of {0:?}")]
+    ReadFailed(PathBuf),
+
+    #[error("Failed to render back to common markdown: {0:?}")]
+    RenderFailed(#[from] RenderEventsToCommonMarkdownError),
+
+    #[error("Failed to write file content: {0:?}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Returns whether the note changed. Notes that are already normalized are not written.
+pub fn writeback_markdown_file(path: &Path) -> Result<bool, WritebackMarkdownFileError> {
+    let content =
+        read_file_content(path).ok_or(WritebackMarkdownFileError::ReadFailed(path.to_owned()))?;
+
+    let new_content = writeback_markdown_content(&content)?;
+
+    if new_content == content {
+        return Ok(false);
+    }
+
+    write_file_content(&new_content, path)?;
+
+    Ok(true)
+}
+
 pub fn parse_markdown_file<'a>(content: &'a str) -> Vec<Event<'a>> {
     let parser = Parser::new(content);
 
     TextMergeStream::new(parser).collect_vec()
 }
 
+/// Byte ranges in the content of the events of `parse_markdown_file`, in the same order. Merged text events span
+/// the text they were merged from.
+pub fn get_markdown_event_offsets(content: &str) -> Vec<Range<usize>> {
+    let mut mut_offsets: Vec<Range<usize>> = vec![];
+    let mut mut_last_is_text = false;
+
+    for (event, range) in Parser::new(content).into_offset_iter() {
+        let is_text = matches!(event, Event::Text(_));
+
+        match mut_offsets.last_mut() {
+            Some(last_range) if is_text && mut_last_is_text => last_range.end = range.end,
+            _ => mut_offsets.push(range),
+        }
+
+        mut_last_is_text = is_text;
+    }
+
+    mut_offsets
+}
+
+/// A position in a note, with lines and columns counted from 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourceLocation {
+    /// The location of a byte offset in the content of the note. Columns count characters.
+    pub fn of_offset(path: &Path, content: &str, offset: usize) -> Self {
+        // Offsets of events fall on character boundaries
+        let before = &content[..offset.min(content.len())];
+
+        let line_start = before.rfind('\n').map_or(0, |pos| pos + 1);
+
+        Self {
+            path: path.to_path_buf(),
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.path.display(), self.line, self.column)
+    }
+}
+
 pub fn parse_markdown_file_frontmatter_section(path: &Path) -> Option<Vec<(String, String)>> {
     let content = read_file_content(path)?;
 
@@ -371,6 +579,9 @@ pub fn get_file_frontmatter_note_property(path: &Path, prop: &str) -> Option<Str
         .trim()
         .to_owned();
 
+    // Obsidian quotes links in properties
+    let matching_value = matching_value.trim_matches('"');
+
     // we need to clean up the note value. We expect it to look like [[this]], so remove those boxes!
     if !matching_value.starts_with("[[") || !matching_value.ends_with("]]") {
         return None;
@@ -381,6 +592,124 @@ pub fn get_file_frontmatter_note_property(path: &Path, prop: &str) -> Option<Str
     Some(matching_note_link)
 }
 
+/// Index of the line closing the frontmatter, if the note starts with one
+fn get_frontmatter_end_line(lines: &[&str]) -> Option<usize> {
+    if lines.first() != Some(&"---") {
+        return None;
+    }
+
+    lines
+        .iter()
+        .skip(1)
+        .position(|line| *line == "---")
+        .map(|pos| pos + 1)
+}
+
+/// Reads a property straight from the frontmatter lines, with quotes removed. Unlike
+/// `parse_markdown_file_frontmatter_section` this does not depend on how the frontmatter parses as markdown.
+pub fn get_frontmatter_property(content: &str, prop: &str) -> Option<String> {
+    let lines = content.lines().collect_vec();
+
+    let end = get_frontmatter_end_line(&lines)?;
+
+    lines[1..end].iter().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+
+        (key.trim() == prop).then(|| value.trim().trim_matches('"').to_owned())
+    })
+}
+
+/// Sets a property in the frontmatter of a note, adding the frontmatter if the note has none
+pub fn set_frontmatter_property(content: &str, prop: &str, value: &str) -> String {
+    let lines = content.lines().collect_vec();
+    let prop_line = format!("{prop}: {value}");
+
+    let Some(end) = get_frontmatter_end_line(&lines) else {
+        return format!("---\n{prop_line}\n---\n\n{content}");
+    };
+
+    let new_lines = {
+        let mut mut_lines = lines.iter().map(|line| line.to_string()).collect_vec();
+
+        match (1..end).find(|i| {
+            lines[*i]
+                .split_once(':')
+                .is_some_and(|(key, _)| key.trim() == prop)
+        }) {
+            Some(i) => mut_lines[i] = prop_line,
+            None => mut_lines.insert(end, prop_line),
+        }
+
+        mut_lines
+    };
+
+    let trailing_newline = if content.ends_with('\n') { "\n" } else { "" };
+
+    format!("{}{trailing_newline}", new_lines.join("\n"))
+}
+
+/// The level and text of each line that is an ATX heading, like `## Text`. Lines in fenced code blocks are never
+/// headings.
+pub fn get_atx_heading_lines(content: &str) -> Vec<Option<(usize, &str)>> {
+    let mut mut_in_code_block = false;
+
+    content
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                mut_in_code_block = !mut_in_code_block;
+                return None;
+            }
+
+            let level = line.chars().take_while(|c| *c == '#').count();
+
+            let text = &line[level..];
+
+            (!mut_in_code_block
+                && (1..=6).contains(&level)
+                && (text.is_empty() || text.starts_with(' ')))
+            .then(|| (level, text.trim()))
+        })
+        .collect()
+}
+
+/// Removes a property from the frontmatter of a note, and the frontmatter if nothing is left in it
+pub fn remove_frontmatter_property(content: &str, prop: &str) -> String {
+    let lines = content.lines().collect_vec();
+
+    let Some(end) = get_frontmatter_end_line(&lines) else {
+        return content.to_owned();
+    };
+
+    let is_prop_line = |line: &&str| {
+        line.split_once(':')
+            .is_some_and(|(key, _)| key.trim() == prop)
+    };
+
+    let properties = lines[1..end]
+        .iter()
+        .filter(|line| !is_prop_line(line))
+        .collect_vec();
+
+    if properties.len() == end - 1 {
+        return content.to_owned();
+    }
+
+    let trailing_newline = if content.ends_with('\n') { "\n" } else { "" };
+
+    let body = lines[end + 1..].join("\n");
+
+    match properties.is_empty() {
+        true => format!("{}{trailing_newline}", body.trim_start_matches('\n')),
+        false => format!(
+            "---\n{}\n---\n{body}{trailing_newline}",
+            properties.into_iter().join("\n")
+        ),
+    }
+}
+
 pub fn is_obsidian_vault_folder(path: &Path) -> Option<bool> {
     let dir_entries = get_and_categorize_dir_entries(path).ok()?;
 
@@ -394,49 +723,191 @@ pub fn is_obsidian_vault_folder(path: &Path) -> Option<bool> {
         .pipe(|count| Some(count == 1))
 }
 
+/// Folder within the vault where this tool keeps its own files, such as caches
+pub const TOOL_FOLDER_NAME: &str = ".migration_rs";
+
 #[derive(Debug)]
 pub struct ObsidianVaultPath {
     pub path: PathBuf,
+    pub traversal_options: TraversalOptions,
+
+    /// Read once when the vault is opened, and passed along with it
+    pub config: Arc<VaultConfig>,
+}
+
+#[derive(Error, Debug)]
+pub enum OpenObsidianVaultError {
+    #[error("Folder is not an obsidian vault: {0:?}")]
+    NotAVault(PathBuf),
+
+    #[error("Failed to load the vault config: {0}")]
+    Config(#[from] LoadVaultConfigError),
 }
 
 impl ObsidianVaultPath {
-    pub fn new(path: &Path) -> Option<Self> {
-        if !is_obsidian_vault_folder(path)? {
-            return None;
+    pub fn new(path: &Path) -> Result<Self, OpenObsidianVaultError> {
+        if !is_obsidian_vault_folder(path).unwrap_or_default() {
+            return Err(OpenObsidianVaultError::NotAVault(path.to_owned()));
         }
 
-        Some(Self {
+        Ok(Self {
             path: path.to_owned(),
+            traversal_options: TraversalOptions::default(),
+            config: Arc::new(VaultConfig::load(path)?),
         })
     }
+
+    /// Reads the config again, after it changed on disk
+    pub fn reload_config(&mut self) -> Result<(), LoadVaultConfigError> {
+        self.config = Arc::new(VaultConfig::load(&self.path)?);
+
+        Ok(())
+    }
+
+    pub fn with_traversal_options(self, traversal_options: TraversalOptions) -> Self {
+        Self {
+            traversal_options,
+            ..self
+        }
+    }
+
+    pub fn tool_folder(&self) -> PathBuf {
+        self.path.join(TOOL_FOLDER_NAME)
+    }
+
+    /// A path given on the command line, written the way vault indexes have it: starting with the vault path
+    pub fn resolve_path(&self, path: &Path) -> PathBuf {
+        match (fs::canonicalize(path), fs::canonicalize(&self.path)) {
+            (Ok(canonical_path), Ok(vault_root)) => canonical_path
+                .strip_prefix(&vault_root)
+                .map(|relative_path| self.path.join(relative_path))
+                .unwrap_or(canonical_path),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    /// How links written as paths refer to a note: relative to the vault, without the markdown extension
+    pub fn get_note_link_path(&self, path: &Path) -> Option<String> {
+        let relative_path = path.strip_prefix(&self.path).ok()?;
+
+        let relative_path = match relative_path.extension().is_some_and(|ext| ext == "md") {
+            true => relative_path.with_extension(""),
+            false => relative_path.to_path_buf(),
+        };
+
+        Some(
+            relative_path
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join("/"),
+        )
+    }
 }
 
-pub fn process_heading_event<'a>(events: &[Event<'a>]) -> Option<(HeadingLevel, String)> {
-    // Process H{level}, then Text with the tring, then /H{level}.
-    if events.len() < 3 {
-        return None;
+/// A heading, with its inline events like code, emphasis and links
+#[derive(Debug, Clone)]
+pub struct HeadingEvents<'a> {
+    pub level: HeadingLevel,
+
+    /// The inline markdown of the heading, which is how obsidian links to it. Flattened back from the inline
+    /// events, unless taken from the source with `with_source_text`.
+    pub text: String,
+    pub inline_events: Vec<Event<'a>>,
+}
+
+impl HeadingEvents<'_> {
+    /// Number of events of the heading, with its start and end
+    pub fn event_count(&self) -> usize {
+        self.inline_events.len() + 2
     }
 
-    let level = match &events[0] {
-        Event::Start(Tag::Heading { level, .. }) => Some(level),
-        _ => None,
-    }?;
+    /// Takes the text from the source the heading was parsed from, so markup reads as written, like `_` emphasis.
+    /// `offsets` are those of `get_markdown_event_offsets`, and `heading_index` is where the heading starts in them.
+    pub fn with_source_text(
+        mut self,
+        content: &str,
+        offsets: &[Range<usize>],
+        heading_index: usize,
+    ) -> Self {
+        let opt_source = offsets
+            .get(heading_index + 1)
+            .zip(offsets.get(heading_index + self.inline_events.len()))
+            .filter(|_| !self.inline_events.is_empty())
+            .and_then(|(first, last)| content.get(first.start..last.end));
+
+        if let Some(source) = opt_source {
+            self.text = source.trim().to_owned();
+        }
 
-    let heading_text = match &events[1] {
-        Event::Text(cow_str) => Some(cow_str.to_string()),
-        _ => None,
-    }?;
+        self
+    }
+}
 
-    match &events[2] {
-        Event::End(TagEnd::Heading(heading_level)) => {
-            if heading_level != level {
-                log::error!("Assertion failed: Data does not properly close same heading level");
+/// Writes inline events back as markdown, for when the source is not at hand. Emphasis is always written with `*`.
+/// Notes are parsed without extensions, so wikilinks, math, footnotes and strikethrough stay text.
+pub fn flatten_inline_events(events: &[Event]) -> String {
+    let mut mut_text = String::new();
+
+    // Links and images close with their destination
+    let mut mut_link_ends = vec![];
+
+    for event in events {
+        match event {
+            Event::Text(text) => mut_text.push_str(text),
+            Event::Code(code) if code.contains('`') => mut_text.push_str(&format!("`` {code} ``")),
+            Event::Code(code) => mut_text.push_str(&format!("`{code}`")),
+            Event::Html(html) | Event::InlineHtml(html) => mut_text.push_str(html),
+            Event::SoftBreak | Event::HardBreak => mut_text.push(' '),
+            Event::Start(Tag::Emphasis) | Event::End(TagEnd::Emphasis) => mut_text.push('*'),
+            Event::Start(Tag::Strong) | Event::End(TagEnd::Strong) => mut_text.push_str("**"),
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                mut_text.push('[');
+                mut_link_ends.push(format!("]({dest_url})"));
             }
+            Event::Start(Tag::Image { dest_url, .. }) => {
+                mut_text.push_str("![");
+                mut_link_ends.push(format!("]({dest_url})"));
+            }
+            Event::End(TagEnd::Link | TagEnd::Image) => {
+                mut_text.push_str(&mut_link_ends.pop().unwrap_or_default());
+            }
+            _ => {}
         }
-        _ => return None,
     }
 
-    Some((*level, heading_text))
+    mut_text
+}
+
+/// Reads a heading at the start of the events, whatever inline events it is made of
+pub fn parse_heading_events<'a>(events: &[Event<'a>]) -> Option<HeadingEvents<'a>> {
+    let Event::Start(Tag::Heading { level, .. }) = events.first()? else {
+        return None;
+    };
+
+    let end = events
+        .iter()
+        .position(|event| matches!(event, Event::End(TagEnd::Heading(_))))?;
+
+    if let Event::End(TagEnd::Heading(end_level)) = &events[end]
+        && end_level != level
+    {
+        log::error!("Assertion failed: Data does not properly close same heading level");
+    }
+
+    let inline_events = events[1..end].to_vec();
+
+    Some(HeadingEvents {
+        level: *level,
+        text: flatten_inline_events(&inline_events),
+        inline_events,
+    })
+}
+
+pub fn process_heading_event<'a>(events: &[Event<'a>]) -> Option<(HeadingLevel, String)> {
+    let heading = parse_heading_events(events)?;
+
+    (!heading.text.is_empty()).then_some((heading.level, heading.text))
 }
 
 #[derive(Error, Debug)]
@@ -447,19 +918,22 @@ pub enum ProcessHeadingEventInternalError {
 
 #[derive(Error, Debug)]
 pub enum ProcessHeadingEventError {
-    #[error("Heading events come in 3, and there aren't enough events")]
-    RequiresThreeEvents,
+    #[error("Heading events come with a start and an end, and there aren't enough events")]
+    RequiresTwoEvents,
 
     #[error(
-        "Invalid structure. We expect a heading start, then text, then heading end. But on the {0}th we get: {1}."
+        "Invalid structure. We expect a heading start, then inline events, then heading end. But on the {0}th we get: {1}."
     )]
     InvalidScheme(u32, String),
 
     #[error("Expected a heading start tag but got {0:?}")]
     InvalidStartTag(String),
 
-    #[error("Expected a heading end tag but got {0:?}")]
-    InvalidEndTag(String),
+    #[error("The heading is never closed")]
+    MissingEndTag,
+
+    #[error("The heading has no text")]
+    EmptyHeading,
 
     #[error("Expected heading level {0:?} but got {1:?}")]
     WrongLevel(HeadingLevel, HeadingLevel),
@@ -471,10 +945,10 @@ pub enum ProcessHeadingEventError {
 pub fn process_heading_event_of_level<'a>(
     level: &HeadingLevel,
     events: &[Event<'a>],
-) -> Result<String, ProcessHeadingEventError> {
-    // Process H{level}, then Text with the tring, then /H{level}.
-    if events.len() < 3 {
-        return Err(ProcessHeadingEventError::RequiresThreeEvents);
+) -> Result<HeadingEvents<'a>, ProcessHeadingEventError> {
+    // Process H{level}, then any inline events, then /H{level}.
+    if events.len() < 2 {
+        return Err(ProcessHeadingEventError::RequiresTwoEvents);
     }
 
     match &events[0] {
@@ -501,34 +975,21 @@ pub fn process_heading_event_of_level<'a>(
         }
     }
 
-    let heading_text = match &events[1] {
-        Event::Text(cow_str) => Ok(cow_str.to_string()),
-        _ => Err(ProcessHeadingEventError::InvalidScheme(
-            1,
-            format!("{:?}", events[1]),
-        )),
-    }?;
+    let heading = parse_heading_events(events).ok_or(ProcessHeadingEventError::MissingEndTag)?;
 
-    match &events[2] {
-        Event::End(tag) => match tag {
-            TagEnd::Heading(heading_level) => {
-                if heading_level != level {
-                    return Err(ProcessHeadingEventError::Internal(
-                        ProcessHeadingEventInternalError::ImbalancedHeadingLevels,
-                    ));
-                }
-            }
-            _ => return Err(ProcessHeadingEventError::InvalidEndTag(format!("{tag:?}"))),
-        },
-        _ => {
-            return Err(ProcessHeadingEventError::InvalidScheme(
-                2,
-                format!("{:?}", events[2]),
-            ));
-        }
+    if let Some(Event::End(TagEnd::Heading(heading_level))) = events.get(heading.event_count() - 1)
+        && heading_level != level
+    {
+        return Err(ProcessHeadingEventError::Internal(
+            ProcessHeadingEventInternalError::ImbalancedHeadingLevels,
+        ));
+    }
+
+    if heading.text.trim().is_empty() {
+        return Err(ProcessHeadingEventError::EmptyHeading);
     }
 
-    Ok(heading_text)
+    Ok(heading)
 }
 
 #[derive(Debug, Clone)]
@@ -608,9 +1069,10 @@ pub fn extract_linkable_obsidian_md_items<'a>(
 
                 let len = cow_str.chars().count();
 
+                // Keep the caret, block identifiers start with it
                 let block_identifier = cow_str
                     .chars()
-                    .skip(len - rev_caret_pos)
+                    .skip(len - rev_caret_pos - 1)
                     .join("")
                     .pipe(|s| BlockIdentifier::from_str(&s))
                     .ok()?;
@@ -0,0 +1,357 @@
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    cluster_note::{self, CategoryFoldersWithPeripheralFiles, WorkingPath},
+    common::{self as comm, ObsidianVaultPath},
+    mutation::Mutation,
+    vault_config::VaultConfig,
+};
+
+pub const CORE_NOTE_STATUS_START: &str = "%% status start %%";
+pub const CORE_NOTE_STATUS_END: &str = "%% status end %%";
+
+/// Counted for doer notes without a `status` property
+pub const NO_STATUS: &str = "none";
+
+/// A peripheral note of a doer context type, like a task or an issue
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DoerNote {
+    pub path: PathBuf,
+
+    /// Folder of the context type
+    pub context_type: String,
+    pub opt_status: Option<String>,
+    pub is_open: bool,
+    pub days_since_modified: u64,
+}
+
+impl DoerNote {
+    pub fn name(&self) -> String {
+        self.path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn status(&self) -> &str {
+        self.opt_status.as_deref().unwrap_or(NO_STATUS)
+    }
+}
+
+/// Where the doer notes of a cluster stand
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ClusterStatus {
+    pub core_note: PathBuf,
+    pub counts_by_status: BTreeMap<String, usize>,
+    pub open_notes: Vec<DoerNote>,
+
+    /// Open notes not modified for the stale period
+    pub stale_notes: Vec<DoerNote>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StatusReport {
+    pub stale_after_days: u64,
+    pub clusters: Vec<ClusterStatus>,
+}
+
+fn get_doer_note(
+    config: &VaultConfig,
+    path: &Path,
+    context_type: &str,
+    now: SystemTime,
+) -> Option<DoerNote> {
+    let content = comm::read_file_content(path)?;
+
+    let opt_status = comm::get_frontmatter_property(&content, "status");
+
+    let is_open = opt_status.as_ref().is_none_or(|status| {
+        !config
+            .closed_statuses
+            .iter()
+            .any(|closed_status| closed_status.eq_ignore_ascii_case(status))
+    });
+
+    let days_since_modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| now.duration_since(modified).ok())
+        .map(|duration| duration.as_secs() / comm::SECS_PER_DAY)
+        .unwrap_or_default();
+
+    Some(DoerNote {
+        path: path.to_path_buf(),
+        context_type: context_type.to_owned(),
+        opt_status,
+        is_open,
+        days_since_modified,
+    })
+}
+
+/// The peripheral notes of a cluster that are of a doer context type
+pub fn get_doer_notes(
+    config: &VaultConfig,
+    category_folders_with_peripheral_files: &CategoryFoldersWithPeripheralFiles,
+    now: SystemTime,
+) -> Vec<DoerNote> {
+    category_folders_with_peripheral_files
+        .iter()
+        .flat_map(|(category_folder, files)| {
+            let folder = category_folder
+                .path
+                .file_name()?
+                .to_string_lossy()
+                .to_string();
+
+            config
+                .get_context_type_by_folder(&folder)?
+                .is_doer
+                .then_some((folder, files))
+        })
+        .flat_map(|(folder, files)| {
+            files
+                .iter()
+                .flat_map(|file| get_doer_note(config, &file.path, &folder, now))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+pub fn get_cluster_status(
+    config: &VaultConfig,
+    core_note: &Path,
+    category_folders_with_peripheral_files: &CategoryFoldersWithPeripheralFiles,
+    stale_after_days: u64,
+    now: SystemTime,
+) -> ClusterStatus {
+    let doer_notes = get_doer_notes(config, category_folders_with_peripheral_files, now);
+
+    let counts_by_status = {
+        let mut mut_counts_by_status = BTreeMap::new();
+
+        for doer_note in doer_notes.iter() {
+            *mut_counts_by_status
+                .entry(doer_note.status().to_owned())
+                .or_default() += 1;
+        }
+
+        mut_counts_by_status
+    };
+
+    let open_notes = doer_notes
+        .into_iter()
+        .filter(|doer_note| doer_note.is_open)
+        .collect::<Vec<_>>();
+
+    let stale_notes = open_notes
+        .iter()
+        .filter(|doer_note| doer_note.days_since_modified >= stale_after_days)
+        .cloned()
+        .collect();
+
+    ClusterStatus {
+        core_note: core_note.to_path_buf(),
+        counts_by_status,
+        open_notes,
+        stale_notes,
+    }
+}
+
+/// The status of every cluster of the vault with doer notes
+pub fn get_status_report(vault: &ObsidianVaultPath, stale_after_days: u64) -> Option<StatusReport> {
+    let items = cluster_note::get_working_item_paths_in_vault(vault)?;
+
+    let config = &vault.config;
+
+    let now = SystemTime::now();
+
+    let clusters = items
+        .iter()
+        .flat_map(|item| match item {
+            WorkingPath::ClusterFolder {
+                core_note_file,
+                category_folders_with_peripheral_files,
+                ..
+            } => Some(get_cluster_status(
+                config,
+                &core_note_file.path,
+                category_folders_with_peripheral_files,
+                stale_after_days,
+                now,
+            )),
+            WorkingPath::Note(_) => None,
+        })
+        .filter(|cluster_status| !cluster_status.counts_by_status.is_empty())
+        .collect();
+
+    Some(StatusReport {
+        stale_after_days,
+        clusters,
+    })
+}
+
+/// The status section of a core note. Days are left out, and so are stale notes, so the section only changes when
+/// the notes do. Stale notes are in the report instead.
+pub fn render_core_note_status(cluster_status: &ClusterStatus) -> String {
+    let lines =
+        {
+            let mut mut_lines = vec!["- Status".to_owned()];
+
+            mut_lines.extend(
+                cluster_status
+                    .counts_by_status
+                    .iter()
+                    .map(|(status, count)| format!("  - {status}: {count}")),
+            );
+
+            if !cluster_status.open_notes.is_empty() {
+                mut_lines.push("- Open".to_owned());
+                mut_lines.extend(cluster_status.open_notes.iter().map(|doer_note| {
+                    format!("  - [[{}]] ({})", doer_note.name(), doer_note.status())
+                }));
+            }
+
+            mut_lines
+        };
+
+    // Blank lines keep the end marker from being read as part of the list
+    format!(
+        "{CORE_NOTE_STATUS_START}\n\n{}\n\n{CORE_NOTE_STATUS_END}",
+        lines.join("\n")
+    )
+}
+
+/// Replaces the status section of a core note, or adds one after the index, or at the end without an index
+pub fn set_core_note_status(content: &str, status: &str) -> String {
+    if let Some(new_content) = cluster_note::replace_marked_section(
+        content,
+        CORE_NOTE_STATUS_START,
+        CORE_NOTE_STATUS_END,
+        status,
+    ) {
+        return new_content;
+    }
+
+    match content
+        .find(cluster_note::CORE_NOTE_INDEX_END)
+        .map(|pos| pos + cluster_note::CORE_NOTE_INDEX_END.len())
+    {
+        Some(index_end) => format!(
+            "{}\n\n{status}{}",
+            &content[..index_end],
+            &content[index_end..]
+        ),
+        None => format!("{}\n\n{status}\n", content.trim_end()),
+    }
+}
+
+/// Removes the status section of a core note, with the blank lines before it. None if it has none.
+pub fn remove_core_note_status(content: &str) -> Option<String> {
+    let start = content.find(CORE_NOTE_STATUS_START)?;
+    let end = start + content[start..].find(CORE_NOTE_STATUS_END)? + CORE_NOTE_STATUS_END.len();
+
+    Some(format!(
+        "{}{}",
+        content[..start].trim_end(),
+        &content[end..]
+    ))
+}
+
+/// Writes the status sections of the core notes in the report, and removes those of core notes whose cluster no
+/// longer has doer notes. None if the vault can't be indexed.
+pub fn get_write_status_mutations(
+    vault: &ObsidianVaultPath,
+    report: &StatusReport,
+) -> Option<Vec<Mutation>> {
+    let core_notes = cluster_note::get_working_item_paths_in_vault(vault)?
+        .into_iter()
+        .flat_map(|item| match item {
+            WorkingPath::ClusterFolder { core_note_file, .. } => Some(core_note_file.path),
+            WorkingPath::Note(_) => None,
+        });
+
+    let mutations = core_notes
+        .flat_map(|core_note| {
+            let content = comm::read_file_content(&core_note)?;
+
+            let new_content = match report
+                .clusters
+                .iter()
+                .find(|cluster_status| cluster_status.core_note == core_note)
+            {
+                Some(cluster_status) => {
+                    set_core_note_status(&content, &render_core_note_status(cluster_status))
+                }
+                None => remove_core_note_status(&content)?,
+            };
+
+            (new_content != content).then_some(Mutation::WriteFile {
+                path: core_note,
+                opt_old_content: Some(content),
+                new_content,
+            })
+        })
+        .collect();
+
+    Some(mutations)
+}
+
+impl fmt::Display for StatusReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut mut_total_counts_by_status = BTreeMap::<&str, usize>::new();
+
+        for cluster_status in self.clusters.iter() {
+            let counts = cluster_status
+                .counts_by_status
+                .iter()
+                .map(|(status, count)| format!("{status}: {count}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            writeln!(f, "{:?}: {counts}", cluster_status.core_note)?;
+
+            for doer_note in cluster_status.stale_notes.iter() {
+                writeln!(
+                    f,
+                    "  stale {} {:?} ({}, {} days)",
+                    doer_note.context_type,
+                    doer_note.name(),
+                    doer_note.status(),
+                    doer_note.days_since_modified
+                )?;
+            }
+
+            for (status, count) in cluster_status.counts_by_status.iter() {
+                *mut_total_counts_by_status.entry(status).or_default() += count;
+            }
+        }
+
+        let open_count = self
+            .clusters
+            .iter()
+            .map(|cluster_status| cluster_status.open_notes.len())
+            .sum::<usize>();
+        let stale_count = self
+            .clusters
+            .iter()
+            .map(|cluster_status| cluster_status.stale_notes.len())
+            .sum::<usize>();
+
+        write!(
+            f,
+            "Total: {} ({open_count} open, {stale_count} stale after {} days)",
+            mut_total_counts_by_status
+                .iter()
+                .map(|(status, count)| format!("{status}: {count}"))
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.stale_after_days
+        )
+    }
+}
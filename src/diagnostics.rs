@@ -0,0 +1,133 @@
+use std::{fmt, path::PathBuf};
+
+use crate::vault_config::VaultConfig;
+
+/// A rule of the cluster layout that a path in the vault breaks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClusterRuleViolation {
+    UnknownCategoryFolder,
+    FolderInCategoryFolder,
+    SymlinkInCategoryFolder,
+}
+
+impl ClusterRuleViolation {
+    pub fn rule(&self) -> &'static str {
+        match self {
+            Self::UnknownCategoryFolder => {
+                "Folders in a cluster must be context type folders or the attachments folder"
+            }
+            Self::FolderInCategoryFolder => {
+                "Context type folders only hold flat peripheral notes and attachments, not folders"
+            }
+            Self::SymlinkInCategoryFolder => {
+                "Context type folders cannot hold symlinks that are not followed"
+            }
+        }
+    }
+}
+
+/// What is wrong with a path found while walking the vault
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VaultDiagnosticKind {
+    ClusterRule {
+        cluster_root_folder: PathBuf,
+        violation: ClusterRuleViolation,
+
+        /// The context type folders of the vault config, which an unknown category folder could be renamed to
+        context_type_folders: Vec<String>,
+    },
+
+    /// The folder, and everything under it, is left out of the index
+    UnreadableFolder(String),
+}
+
+/// A problem found while walking the vault. Traversal keeps going, leaving the offending path out of the working
+/// items.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultDiagnostic {
+    pub path: PathBuf,
+    pub kind: VaultDiagnosticKind,
+}
+
+impl VaultDiagnostic {
+    pub fn new(
+        path: PathBuf,
+        cluster_root_folder: PathBuf,
+        violation: ClusterRuleViolation,
+        config: &VaultConfig,
+    ) -> Self {
+        Self {
+            path,
+            kind: VaultDiagnosticKind::ClusterRule {
+                cluster_root_folder,
+                violation,
+                context_type_folders: config
+                    .get_context_type_folder_names()
+                    .into_iter()
+                    .map(str::to_owned)
+                    .collect(),
+            },
+        }
+    }
+
+    pub fn unreadable_folder(path: PathBuf, error: String) -> Self {
+        Self {
+            path,
+            kind: VaultDiagnosticKind::UnreadableFolder(error),
+        }
+    }
+
+    pub fn opt_violation(&self) -> Option<&ClusterRuleViolation> {
+        match &self.kind {
+            VaultDiagnosticKind::ClusterRule { violation, .. } => Some(violation),
+            VaultDiagnosticKind::UnreadableFolder(_) => None,
+        }
+    }
+
+    pub fn rule(&self) -> &'static str {
+        match &self.kind {
+            VaultDiagnosticKind::ClusterRule { violation, .. } => violation.rule(),
+            VaultDiagnosticKind::UnreadableFolder(_) => "Folders of the vault must be readable",
+        }
+    }
+
+    pub fn suggested_fix(&self) -> String {
+        match &self.kind {
+            VaultDiagnosticKind::ClusterRule {
+                cluster_root_folder,
+                violation: ClusterRuleViolation::UnknownCategoryFolder,
+                context_type_folders,
+            } => format!(
+                "Rename it to one of {} or move it out of {:?}",
+                context_type_folders.join(", "),
+                cluster_root_folder
+            ),
+            VaultDiagnosticKind::ClusterRule {
+                violation: ClusterRuleViolation::FolderInCategoryFolder,
+                ..
+            } => {
+                "Move its notes up into the context type folder, or its attachments into the attachments folder"
+                    .to_owned()
+            }
+            VaultDiagnosticKind::ClusterRule {
+                violation: ClusterRuleViolation::SymlinkInCategoryFolder,
+                ..
+            } => "Replace it with the note it points to, or follow symlinks with --symlinks".to_owned(),
+            VaultDiagnosticKind::UnreadableFolder(error) => {
+                format!("Fix its permissions, or exclude it from the vault ({error})")
+            }
+        }
+    }
+}
+
+impl fmt::Display for VaultDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?}: {}. Suggested fix: {}",
+            self.path,
+            self.rule(),
+            self.suggested_fix()
+        )
+    }
+}
@@ -1,9 +1,22 @@
 use std::path::PathBuf;
-use std::{env::args, path::Path};
+use std::{
+    cell::Cell,
+    env::args,
+    fmt::Debug,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::{
+        Once,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
 
+use itertools::Itertools;
 use log::LevelFilter;
+use thiserror::Error;
 
-use crate::{cluster_note, common::ObsidianVaultPath};
+use crate::{cluster_note, common::ObsidianVaultPath, timeline};
 
 pub fn init_logging_with_level(level: LevelFilter) {
     env_logger::builder()
@@ -21,7 +34,7 @@ pub fn get_obsidian_vault(n: usize) -> ObsidianVaultPath {
         .parse::<PathBuf>()
         .expect("Failed to parse folder as a path");
 
-    ObsidianVaultPath::new(&folder).expect("Folder passed is not a valid obsidian vault")
+    ObsidianVaultPath::new(&folder).unwrap_or_else(|e| panic!("Failed to open the vault: {e}"))
 }
 
 pub fn get_arg_note_path(n: usize) -> PathBuf {
@@ -36,12 +49,25 @@ pub fn get_opt_arg_note_path(n: usize) -> Option<PathBuf> {
     args().nth(n)?.parse::<PathBuf>().ok()
 }
 
+/// Working items of the vault. Problems found along the way are logged, since they leave notes out of processing.
+pub fn get_working_items_in_vault_logging_diagnostics(
+    vault_folder: &ObsidianVaultPath,
+) -> Vec<cluster_note::WorkingPath> {
+    let working_items = cluster_note::get_working_items_in_vault(vault_folder)
+        .expect("Failed to get working items");
+
+    for diagnostic in working_items.diagnostics.iter() {
+        log::warn!("{diagnostic}");
+    }
+
+    working_items.items
+}
+
 pub fn process_non_peripheral_markdown_files_in_vault(
     vault_folder: &ObsidianVaultPath,
     process_markdown_file: impl Fn(&Path) -> Option<()>,
 ) {
-    let working_items = cluster_note::get_working_item_paths_in_vault(vault_folder)
-        .expect("Failed to get working items");
+    let working_items = get_working_items_in_vault_logging_diagnostics(vault_folder);
 
     for item in working_items {
         match item {
@@ -52,6 +78,7 @@ pub fn process_non_peripheral_markdown_files_in_vault(
                 cluster_root_folder: _cluster_root_folder,
                 core_note_file: _core_note_file,
                 category_folders_with_peripheral_files: _category_folders_with_peripheral_files,
+                attachment_files: _attachment_files,
             } => {
                 process_markdown_file(&_core_note_file.path);
             }
@@ -63,8 +90,7 @@ pub fn process_markdown_files_in_vault(
     vault_folder: &ObsidianVaultPath,
     process_markdown_file: impl Fn(&Path) -> Option<()>,
 ) {
-    let working_items = cluster_note::get_working_item_paths_in_vault(vault_folder)
-        .expect("Failed to get working items");
+    let working_items = get_working_items_in_vault_logging_diagnostics(vault_folder);
 
     for item in working_items {
         match item {
@@ -75,6 +101,7 @@ pub fn process_markdown_files_in_vault(
                 cluster_root_folder: _cluster_root_folder,
                 core_note_file: _core_note_file,
                 category_folders_with_peripheral_files: _category_folders_with_peripheral_files,
+                attachment_files: _attachment_files,
             } => {
                 process_markdown_file(&_core_note_file.path);
                 _category_folders_with_peripheral_files
@@ -88,6 +115,221 @@ pub fn process_markdown_files_in_vault(
     }
 }
 
+/// Some markdown files are managed by obsidian extensions and should be left alone. Timeline notes are managed
+/// whatever their name, besides the per-year or per-cluster ones named after them.
+pub fn skip_processing_managed_path(vault: &ObsidianVaultPath, path: &Path) -> bool {
+    let filename = path.file_name().unwrap().to_string_lossy();
+    let pathname = format!("{path:?}");
+
+    filename.contains("excalidraw")
+        || filename.contains("Kanban")
+        || filename.contains("Summarize")
+        || filename.contains("Summary")
+        || filename.contains("Timeline")
+        || timeline::is_timeline_note(vault, path)
+        || pathname.contains("templater")
+}
+
+/// Markdown files of the vault in a deterministic order
+pub fn get_markdown_file_paths_in_vault(
+    vault_folder: &ObsidianVaultPath,
+    include_peripheral_files: bool,
+) -> Vec<PathBuf> {
+    let working_items = get_working_items_in_vault_logging_diagnostics(vault_folder);
+
+    working_items
+        .into_iter()
+        .flat_map(|item| match item {
+            cluster_note::WorkingPath::Note(normal_note_file_path) => {
+                vec![normal_note_file_path.path]
+            }
+            cluster_note::WorkingPath::ClusterFolder {
+                core_note_file,
+                category_folders_with_peripheral_files,
+                ..
+            } => {
+                let mut mut_paths = vec![core_note_file.path];
+
+                if include_peripheral_files {
+                    mut_paths.extend(
+                        category_folders_with_peripheral_files
+                            .into_iter()
+                            .flat_map(|(_, peripheral_files)| peripheral_files)
+                            .map(|peripheral_file| peripheral_file.path),
+                    );
+                }
+
+                mut_paths
+            }
+        })
+        .sorted()
+        .collect()
+}
+
+#[derive(Error, Debug)]
+pub enum ProcessFileError<E: Debug> {
+    #[error("Failed to process file: {0:?}")]
+    Failed(E),
+
+    #[error("Panicked while processing file at {}: {message}", opt_location.as_deref().unwrap_or("unknown location"))]
+    Panicked {
+        message: String,
+        opt_location: Option<String>,
+    },
+}
+
+#[derive(Debug)]
+pub struct ProcessedFile<T, E: Debug> {
+    pub path: PathBuf,
+    pub result: Result<T, ProcessFileError<E>>,
+}
+
+thread_local! {
+    static IS_PROCESSING_FILE: Cell<bool> = const { Cell::new(false) };
+    static PANIC_LOCATION: Cell<Option<String>> = const { Cell::new(None) };
+}
+
+static INSTALL_WORKER_PANIC_HOOK: Once = Once::new();
+
+/// Panics of files being processed are kept for their `ProcessFileError` rather than printed in the middle of the
+/// logs of other threads. The hook is installed once, in front of the one in place then, which still gets every
+/// other panic. It is never swapped back, so parallel runs and hooks installed later are left alone.
+fn install_worker_panic_hook() {
+    INSTALL_WORKER_PANIC_HOOK.call_once(|| {
+        let previous_hook = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| match IS_PROCESSING_FILE.get() {
+            true => PANIC_LOCATION.set(info.location().map(|location| location.to_string())),
+            false => previous_hook(info),
+        }));
+    });
+}
+
+fn process_file_catching_panics<T, E: Debug>(
+    path: &Path,
+    process_file: impl Fn(&Path) -> Result<T, E>,
+) -> Result<T, ProcessFileError<E>> {
+    install_worker_panic_hook();
+
+    PANIC_LOCATION.set(None);
+    IS_PROCESSING_FILE.set(true);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| process_file(path)));
+
+    IS_PROCESSING_FILE.set(false);
+
+    result
+        .map_err(|payload| {
+            let message = match payload.downcast_ref::<&str>() {
+                Some(s) => s.to_string(),
+                None => match payload.downcast_ref::<String>() {
+                    Some(s) => s.clone(),
+                    None => "Unknown panic payload".to_owned(),
+                },
+            };
+
+            ProcessFileError::Panicked {
+                message,
+                opt_location: PANIC_LOCATION.take(),
+            }
+        })
+        .and_then(|result| result.map_err(ProcessFileError::Failed))
+}
+
+/// Processes the files over `num_threads` threads, or the available parallelism if 0. Results are returned in the
+/// same order as the paths. A failing or panicking file does not stop the others from being processed.
+pub fn process_files_parallel<T, E>(
+    paths: Vec<PathBuf>,
+    num_threads: usize,
+    process_file: impl Fn(&Path) -> Result<T, E> + Sync,
+) -> Vec<ProcessedFile<T, E>>
+where
+    T: Send,
+    E: Debug + Send,
+{
+    let num_threads = match num_threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+    .min(paths.len().max(1));
+
+    let next_path_index = AtomicUsize::new(0);
+
+    let worker = || {
+        let mut mut_results = vec![];
+
+        loop {
+            let i = next_path_index.fetch_add(1, Ordering::Relaxed);
+
+            if i >= paths.len() {
+                break;
+            }
+
+            let result = process_file_catching_panics(&paths[i], &process_file);
+
+            mut_results.push((i, result));
+        }
+
+        mut_results
+    };
+
+    let results = thread::scope(|scope| {
+        let handles = (0..num_threads)
+            .map(|_| scope.spawn(worker))
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Panics are caught within workers"))
+            .collect::<Vec<_>>()
+    });
+
+    results
+        .into_iter()
+        .sorted_by_key(|(i, _)| *i)
+        .map(|(i, result)| ProcessedFile {
+            path: paths[i].clone(),
+            result,
+        })
+        .collect()
+}
+
+pub fn process_markdown_files_in_vault_parallel<T, E>(
+    vault_folder: &ObsidianVaultPath,
+    num_threads: usize,
+    process_markdown_file: impl Fn(&Path) -> Result<T, E> + Sync,
+) -> Vec<ProcessedFile<T, E>>
+where
+    T: Send,
+    E: Debug + Send,
+{
+    let paths = get_markdown_file_paths_in_vault(vault_folder, true);
+
+    process_files_parallel(paths, num_threads, process_markdown_file)
+}
+
+/// Logs the failures in order, followed by a summary. Returns the number of failures.
+pub fn log_processed_files<T, E: Debug>(processed_files: &[ProcessedFile<T, E>]) -> usize {
+    let failures = processed_files
+        .iter()
+        .filter(|processed_file| processed_file.result.is_err())
+        .collect::<Vec<_>>();
+
+    for failure in failures.iter() {
+        if let Err(e) = &failure.result {
+            log::error!("{:?}: {e}", failure.path);
+        }
+    }
+
+    log::info!(
+        "Processed {} files with {} failures",
+        processed_files.len(),
+        failures.len()
+    );
+
+    failures.len()
+}
+
 pub const ANSI_ESCAPE_COLOR_GREEN: &str = "\x1b[32m";
 pub const ANSI_ESCAPE_COLOR_RED: &str = "\x1b[31m";
 pub const ANSI_ESCAPE_COLOR_YELLOW: &str = "\x1b[33m";
@@ -96,18 +338,14 @@ pub const ANSI_ESCAPE_COLOR_BG_GREEN: &str = "\x1b[42m";
 pub const ANSI_ESCAPE_COLOR_BG_YELLOW: &str = "\x1b[43m";
 pub const ANSI_ESCAPE_RESET: &str = "\x1b[0m";
 
+#[derive(Default)]
 pub enum DisplayDiffFrom {
     Chars,
+    #[default]
     Words,
     Lines,
 }
 
-impl Default for DisplayDiffFrom {
-    fn default() -> Self {
-        Self::Words
-    }
-}
-
 pub fn display_diff(old: &str, new: &str, from: DisplayDiffFrom) {
     let diff = match from {
         DisplayDiffFrom::Chars => similar::TextDiff::from_chars(old, new),
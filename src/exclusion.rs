@@ -0,0 +1,269 @@
+use ignore::{
+    Match,
+    gitignore::{Gitignore, GitignoreBuilder},
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+use crate::common::{self as comm, ObsidianVaultPath};
+
+pub const TOOL_IGNORE_FILE_NAME: &str = ".migration_rsignore";
+pub const GITIGNORE_FILE_NAME: &str = ".gitignore";
+pub const OBSIDIAN_APP_CONFIG_PATH: &str = ".obsidian/app.json";
+
+/// Why a path was left out of the traversal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExclusionRule {
+    /// Hidden files and folders like `.obsidian`, `.git` and `.trash`, which obsidian does not show either
+    Hidden,
+
+    /// An entry of "Excluded files" in the obsidian settings
+    ObsidianExcludedFiles(String),
+
+    Gitignore {
+        file: PathBuf,
+        pattern: String,
+    },
+
+    ToolIgnore {
+        file: PathBuf,
+        pattern: String,
+    },
+}
+
+impl fmt::Display for ExclusionRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Hidden => write!(f, "hidden"),
+            Self::ObsidianExcludedFiles(filter) => {
+                write!(
+                    f,
+                    "obsidian excluded files {filter:?} in {OBSIDIAN_APP_CONFIG_PATH}"
+                )
+            }
+            Self::Gitignore { file, pattern } | Self::ToolIgnore { file, pattern } => {
+                write!(f, "{pattern:?} in {file:?}")
+            }
+        }
+    }
+}
+
+/// Obsidian filters are vault relative path prefixes, or regexes when wrapped in slashes
+#[derive(Debug, Clone)]
+enum ObsidianFilter {
+    Prefix(String),
+    Regex(String, Regex),
+}
+
+impl ObsidianFilter {
+    fn parse(filter: &str) -> Option<Self> {
+        if filter.len() > 2 && filter.starts_with('/') && filter.ends_with('/') {
+            return Regex::new(&filter[1..filter.len() - 1])
+                .map_err(|e| {
+                    log::warn!("Ignoring invalid obsidian excluded files regex {filter:?}: {e}")
+                })
+                .ok()
+                .map(|regex| Self::Regex(filter.to_owned(), regex));
+        }
+
+        Some(Self::Prefix(filter.to_owned()))
+    }
+
+    fn matches(&self, relative_path: &str, is_dir: bool) -> bool {
+        match self {
+            Self::Prefix(prefix) => {
+                relative_path.starts_with(prefix.as_str())
+                    || (is_dir && format!("{relative_path}/").starts_with(prefix.as_str()))
+            }
+            Self::Regex(_, regex) => regex.is_match(relative_path),
+        }
+    }
+
+    fn filter(&self) -> &str {
+        match self {
+            Self::Prefix(filter) | Self::Regex(filter, _) => filter,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ObsidianAppConfig {
+    #[serde(default, rename = "userIgnoreFilters")]
+    user_ignore_filters: Vec<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum LoadExclusionRulesError {
+    #[error("Failed to parse obsidian settings {0:?}: {1}")]
+    InvalidObsidianAppConfig(PathBuf, serde_json::Error),
+}
+
+/// The rules traversal follows to leave paths out. Hidden paths are always excluded, the rest only when loaded
+/// from the vault.
+#[derive(Debug, Clone)]
+pub struct ExclusionRules {
+    pub root: PathBuf,
+    obsidian_filters: Vec<ObsidianFilter>,
+    opt_tool_ignore: Option<Gitignore>,
+
+    /// Gitignore files by the folder they are in. A folder without one maps to None once it was looked at.
+    gitignores: BTreeMap<PathBuf, Option<Gitignore>>,
+    use_gitignores: bool,
+}
+
+fn build_gitignore(root: &Path, file: &Path) -> Option<Gitignore> {
+    if !file.is_file() {
+        return None;
+    }
+
+    let mut mut_builder = GitignoreBuilder::new(root);
+
+    if let Some(e) = mut_builder.add(file) {
+        log::warn!("Some patterns of {file:?} are ignored: {e}");
+    }
+
+    mut_builder
+        .build()
+        .map_err(|e| log::warn!("Ignoring {file:?}: {e}"))
+        .ok()
+}
+
+impl ExclusionRules {
+    pub fn hidden_only(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            obsidian_filters: vec![],
+            opt_tool_ignore: None,
+            gitignores: BTreeMap::new(),
+            use_gitignores: false,
+        }
+    }
+
+    /// Reads the obsidian excluded files setting and the tool ignore file at the root. Gitignore files are read
+    /// as traversal reaches their folders.
+    pub fn load(root: &Path) -> Result<Self, LoadExclusionRulesError> {
+        let app_config_path = root.join(OBSIDIAN_APP_CONFIG_PATH);
+
+        let obsidian_filters = match comm::read_file_content(&app_config_path) {
+            Some(content) => serde_json::from_str::<ObsidianAppConfig>(&content)
+                .map_err(|e| LoadExclusionRulesError::InvalidObsidianAppConfig(app_config_path, e))?
+                .user_ignore_filters
+                .iter()
+                .flat_map(|filter| ObsidianFilter::parse(filter))
+                .collect(),
+            None => vec![],
+        };
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            obsidian_filters,
+            opt_tool_ignore: build_gitignore(root, &root.join(TOOL_IGNORE_FILE_NAME)),
+            gitignores: BTreeMap::new(),
+            use_gitignores: true,
+        })
+    }
+
+    /// The rules the traversal options of the vault ask for
+    pub fn for_vault(vault: &ObsidianVaultPath) -> Result<Self, LoadExclusionRulesError> {
+        match vault.traversal_options.no_ignore {
+            true => Ok(Self::hidden_only(&vault.path)),
+            false => Self::load(&vault.path),
+        }
+    }
+
+    /// Reads the gitignore file of the folder, if it wasn't already
+    pub fn load_gitignore(&mut self, folder: &Path) {
+        if !self.use_gitignores || self.gitignores.contains_key(folder) {
+            return;
+        }
+
+        let opt_gitignore = build_gitignore(folder, &folder.join(GITIGNORE_FILE_NAME));
+
+        self.gitignores.insert(folder.to_path_buf(), opt_gitignore);
+    }
+
+    /// Checks a single entry, assuming its parent folders are not excluded
+    pub fn check_entry(&self, path: &Path, is_dir: bool) -> Option<ExclusionRule> {
+        let relative_path = path.strip_prefix(&self.root).ok()?;
+
+        let file_name = path.file_name()?.to_string_lossy();
+
+        if file_name.starts_with('.') {
+            return Some(ExclusionRule::Hidden);
+        }
+
+        let relative_path_str = relative_path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if let Some(filter) = self
+            .obsidian_filters
+            .iter()
+            .find(|filter| filter.matches(&relative_path_str, is_dir))
+        {
+            return Some(ExclusionRule::ObsidianExcludedFiles(
+                filter.filter().to_owned(),
+            ));
+        }
+
+        if let Some(tool_ignore) = &self.opt_tool_ignore
+            && let Match::Ignore(glob) = tool_ignore.matched(path, is_dir)
+        {
+            return Some(ExclusionRule::ToolIgnore {
+                file: glob.from().map(Path::to_path_buf).unwrap_or_default(),
+                pattern: glob.original().to_owned(),
+            });
+        }
+
+        // The closest gitignore with a matching pattern decides
+        for ancestor in path.ancestors().skip(1) {
+            if let Some(Some(gitignore)) = self.gitignores.get(ancestor) {
+                match gitignore.matched(path, is_dir) {
+                    Match::Ignore(glob) => {
+                        return Some(ExclusionRule::Gitignore {
+                            file: glob.from().map(Path::to_path_buf).unwrap_or_default(),
+                            pattern: glob.original().to_owned(),
+                        });
+                    }
+                    Match::Whitelist(_) => return None,
+                    Match::None => (),
+                }
+            }
+
+            if ancestor == self.root {
+                break;
+            }
+        }
+
+        None
+    }
+
+    /// Checks the path and every folder between it and the root, for paths that did not come from a traversal
+    pub fn check_path(&mut self, path: &Path) -> Option<ExclusionRule> {
+        let relative_path = path.strip_prefix(&self.root).ok()?.to_path_buf();
+
+        let mut mut_current = self.root.clone();
+
+        for component in relative_path.components() {
+            self.load_gitignore(&mut_current.clone());
+
+            mut_current.push(component);
+
+            let is_dir = mut_current != path || path.is_dir();
+
+            if let Some(rule) = self.check_entry(&mut_current, is_dir) {
+                return Some(rule);
+            }
+        }
+
+        None
+    }
+}
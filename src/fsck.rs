@@ -0,0 +1,389 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cluster_note::{self, ClusterRootFolderPath},
+    common::{self as comm, DirListing, ObsidianVaultPath},
+    diagnostics::{ClusterRuleViolation, VaultDiagnostic, VaultDiagnosticKind},
+    mutation::{self, Mutation},
+    vault_index::VaultIndex,
+};
+
+/// A way a cluster breaks the rules this crate assumes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsckIssue {
+    /// The only note next to the context type folders is not named after its folder, so the folder is not
+    /// recognized as a cluster
+    CoreNoteNotNamedAfterFolder,
+
+    /// A note next to the core note, which keeps the folder from being recognized as a cluster
+    StrayNoteInClusterRoot,
+
+    ClusterRule(ClusterRuleViolation),
+
+    /// The `parent` property of a peripheral note, if it has one, does not link to the core note
+    ParentNotCoreNote(Option<String>),
+
+    EmptyCategoryFolder,
+}
+
+impl fmt::Display for FsckIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CoreNoteNotNamedAfterFolder => {
+                write!(f, "Core note is not named after its cluster folder")
+            }
+            Self::StrayNoteInClusterRoot => write!(
+                f,
+                "Only the core note can be in a cluster folder, other notes go in context type folders"
+            ),
+            Self::ClusterRule(violation) => write!(f, "{}", violation.rule()),
+            Self::ParentNotCoreNote(Some(parent)) => {
+                write!(f, "Parent {parent:?} is not the core note of the cluster")
+            }
+            Self::ParentNotCoreNote(None) => write!(f, "Peripheral note has no parent property"),
+            Self::EmptyCategoryFolder => write!(f, "Context type folder is empty"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FsckProblem {
+    pub path: PathBuf,
+    pub cluster_root_folder: PathBuf,
+    pub issue: FsckIssue,
+
+    /// Empty when the problem has to be fixed by hand
+    pub fix: Vec<Mutation>,
+}
+
+impl fmt::Display for FsckProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.path, self.issue)?;
+
+        match self.fix.is_empty() {
+            true => write!(f, ". No automatic fix"),
+            false => write!(
+                f,
+                ". Fix: {}",
+                self.fix
+                    .iter()
+                    .map(|mutation| mutation.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+fn get_markdown_file_paths_in_index(index: &VaultIndex) -> Vec<PathBuf> {
+    index
+        .dirs
+        .iter()
+        .flat_map(|(folder, indexed_dir)| {
+            indexed_dir
+                .included
+                .files
+                .iter()
+                .map(|file_name| folder.join(file_name))
+        })
+        .filter(|path| cluster_note::has_markdown_extension(path))
+        .collect()
+}
+
+/// Whether the `parent` property links to the note
+fn parent_links_to_note(opt_parent: Option<&str>, note_path: &Path) -> bool {
+    opt_parent
+        .and_then(|parent| parent.strip_prefix("[[")?.strip_suffix("]]"))
+        .and_then(|link| link.split(['#', '|']).next())
+        .is_some_and(|note_link| cluster_note::note_link_matches_path(note_path, note_link))
+}
+
+fn get_cluster_rule_fix(index: &VaultIndex, diagnostic: &VaultDiagnostic) -> Vec<Mutation> {
+    let VaultDiagnosticKind::ClusterRule {
+        cluster_root_folder,
+        violation,
+        ..
+    } = &diagnostic.kind
+    else {
+        return vec![];
+    };
+
+    match violation {
+        ClusterRuleViolation::UnknownCategoryFolder => {
+            let Some(context_type_folder) = diagnostic.path.file_name().and_then(|name| {
+                index
+                    .config
+                    .find_context_type(&name.to_string_lossy())
+                    .map(|context_type| context_type.folder.as_str())
+            }) else {
+                return vec![];
+            };
+
+            let to = cluster_root_folder.join(context_type_folder);
+
+            match index.is_dir(&to) {
+                true => vec![],
+                false => vec![Mutation::Rename {
+                    from: diagnostic.path.clone(),
+                    to,
+                }],
+            }
+        }
+        ClusterRuleViolation::FolderInCategoryFolder => {
+            // Flatten the folder into the context type folder, if nothing is in the way
+            let (Some(listing), Some(category_folder)) = (
+                index.get_listing(&diagnostic.path),
+                diagnostic.path.parent(),
+            ) else {
+                return vec![];
+            };
+
+            if !listing.dirs.is_empty() || !listing.symlinks.is_empty() {
+                return vec![];
+            }
+
+            let moves = listing
+                .files
+                .iter()
+                .map(|file_name| Mutation::Rename {
+                    from: diagnostic.path.join(file_name),
+                    to: category_folder.join(file_name),
+                })
+                .collect::<Vec<_>>();
+
+            if moves.iter().any(|mutation| match mutation {
+                Mutation::Rename { to, .. } => index.is_file(to) || index.is_dir(to),
+                _ => false,
+            }) {
+                return vec![];
+            }
+
+            {
+                let mut mut_fix = moves;
+
+                mut_fix.push(Mutation::RemoveDir {
+                    path: diagnostic.path.clone(),
+                });
+
+                mut_fix
+            }
+        }
+        ClusterRuleViolation::SymlinkInCategoryFolder => vec![],
+    }
+}
+
+/// Folders with context type folders that are not clusters because of the notes next to them
+fn fsck_cluster_candidate(
+    vault: &ObsidianVaultPath,
+    index: &VaultIndex,
+    note_paths: &[PathBuf],
+    folder: &Path,
+) -> Vec<FsckProblem> {
+    let Some(listing) = index.get_listing(folder) else {
+        return vec![];
+    };
+
+    let context_type_folders = listing
+        .dirs
+        .iter()
+        .filter(|dir_name| {
+            index
+                .config
+                .is_context_type_folder_name(&dir_name.to_string_lossy())
+        })
+        .map(|dir_name| folder.join(dir_name))
+        .collect::<Vec<_>>();
+
+    let Some(core_note_file_name) = cluster_note::get_core_note_file_name(folder) else {
+        return vec![];
+    };
+
+    if context_type_folders.is_empty() {
+        return vec![];
+    }
+
+    // Plain folders can be named like context types too, so peripheral notes have to claim the note as parent
+    let is_parent_of_peripheral_note = |note_path: &Path| {
+        context_type_folders
+            .iter()
+            .flat_map(|context_type_folder| {
+                index
+                    .get_listing(context_type_folder)
+                    .map(|listing| {
+                        listing
+                            .files
+                            .iter()
+                            .map(|file_name| context_type_folder.join(file_name))
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default()
+            })
+            .filter(|path| cluster_note::has_markdown_extension(path))
+            .any(|path| {
+                comm::read_file_content(&path).is_some_and(|content| {
+                    parent_links_to_note(
+                        comm::get_frontmatter_property(&content, "parent").as_deref(),
+                        note_path,
+                    )
+                })
+            })
+    };
+
+    let notes = listing
+        .files
+        .iter()
+        .filter(|file_name| {
+            cluster_note::has_markdown_extension(file_name)
+                && !cluster_note::is_attachment_file_name(file_name)
+        })
+        .collect::<Vec<_>>();
+
+    let problem = |path: PathBuf, issue, fix| FsckProblem {
+        path,
+        cluster_root_folder: folder.to_path_buf(),
+        issue,
+        fix,
+    };
+
+    match notes.as_slice() {
+        // Without notes, or with several and none named after the folder, this is likely not meant as a cluster
+        [note]
+            if **note != core_note_file_name
+                && is_parent_of_peripheral_note(&folder.join(note)) =>
+        {
+            let from = folder.join(note);
+            let to = folder.join(&core_note_file_name);
+
+            vec![problem(
+                from.clone(),
+                FsckIssue::CoreNoteNotNamedAfterFolder,
+                mutation::get_rename_note_mutations(vault, note_paths, &from, &to),
+            )]
+        }
+        _ if notes.contains(&&core_note_file_name)
+            && is_parent_of_peripheral_note(&folder.join(&core_note_file_name)) =>
+        {
+            notes
+                .iter()
+                .filter(|note| ***note != core_note_file_name)
+                .map(|note| problem(folder.join(note), FsckIssue::StrayNoteInClusterRoot, vec![]))
+                .collect()
+        }
+        _ => vec![],
+    }
+}
+
+fn fsck_cluster(
+    index: &VaultIndex,
+    cluster_root_folder: &ClusterRootFolderPath,
+) -> Vec<FsckProblem> {
+    let Some(core_note_file) =
+        index.get_core_note_file_from_cluster_root_folder(cluster_root_folder)
+    else {
+        return vec![];
+    };
+
+    let Some((category_folders_with_peripheral_files, diagnostics)) = index
+        .get_category_folders_with_peripheral_files_from_cluster_root_folder(cluster_root_folder)
+    else {
+        return vec![];
+    };
+
+    let problem = |path: PathBuf, issue, fix| FsckProblem {
+        path,
+        cluster_root_folder: cluster_root_folder.path.clone(),
+        issue,
+        fix,
+    };
+
+    let rule_problems = diagnostics.iter().flat_map(|diagnostic| {
+        Some(problem(
+            diagnostic.path.clone(),
+            FsckIssue::ClusterRule(diagnostic.opt_violation()?.clone()),
+            get_cluster_rule_fix(index, diagnostic),
+        ))
+    });
+
+    let empty_category_problems = category_folders_with_peripheral_files
+        .iter()
+        // Excluded and hidden files still keep the folder from being removed
+        .filter(|(category_folder, _)| {
+            index
+                .get_raw_listing(&category_folder.path)
+                .is_some_and(DirListing::is_empty)
+        })
+        .map(|(category_folder, _)| {
+            problem(
+                category_folder.path.clone(),
+                FsckIssue::EmptyCategoryFolder,
+                vec![Mutation::RemoveDir {
+                    path: category_folder.path.clone(),
+                }],
+            )
+        });
+
+    let core_note_stem = core_note_file
+        .path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let parent_problems = category_folders_with_peripheral_files
+        .iter()
+        .flat_map(|(_, peripheral_files)| peripheral_files)
+        .flat_map(|peripheral_file| {
+            let content = comm::read_file_content(&peripheral_file.path)?;
+
+            let opt_parent = comm::get_frontmatter_property(&content, "parent");
+
+            if parent_links_to_note(opt_parent.as_deref(), &core_note_file.path) {
+                return None;
+            }
+
+            let new_content = comm::set_frontmatter_property(
+                &content,
+                "parent",
+                &format!("\"[[{core_note_stem}]]\""),
+            );
+
+            Some(problem(
+                peripheral_file.path.clone(),
+                FsckIssue::ParentNotCoreNote(opt_parent),
+                vec![Mutation::WriteFile {
+                    path: peripheral_file.path.clone(),
+                    opt_old_content: Some(content),
+                    new_content,
+                }],
+            ))
+        });
+
+    rule_problems
+        .chain(empty_category_problems)
+        .chain(parent_problems)
+        .collect()
+}
+
+/// Checks every cluster of the vault, and every folder that looks like it was meant to be one
+pub fn fsck_vault(vault: &ObsidianVaultPath) -> Option<Vec<FsckProblem>> {
+    let index = VaultIndex::build_for_vault(vault)
+        .map_err(|e| log::error!("Failed to index vault {:?}: {e}", vault.path))
+        .ok()?;
+
+    let note_paths = get_markdown_file_paths_in_index(&index);
+
+    let problems = index
+        .dirs
+        .keys()
+        .flat_map(
+            |folder| match ClusterRootFolderPath::new_in_index(&index, folder) {
+                Some(cluster_root_folder) => fsck_cluster(&index, &cluster_root_folder),
+                None => fsck_cluster_candidate(vault, &index, &note_paths, folder),
+            },
+        )
+        .collect();
+
+    Some(problems)
+}
@@ -0,0 +1,134 @@
+use serde::Serialize;
+use std::{
+    collections::BTreeSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cluster_note, common as comm, drivers, lint,
+    workspace::{self, Workspace, WorkspaceVault},
+};
+
+/// A note of a workspace vault
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct GraphNode {
+    pub vault_name: String,
+
+    /// Relative to the vault, with the markdown extension
+    pub relative_path: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct GraphEdge {
+    pub from: GraphNode,
+    pub to: GraphNode,
+}
+
+/// The notes of the vaults of a workspace and the links between them, within a vault and across vaults. Links
+/// that don't resolve to a note are left out, the lint reports them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LinkGraph {
+    pub nodes: BTreeSet<GraphNode>,
+    pub edges: BTreeSet<GraphEdge>,
+}
+
+fn get_graph_node(workspace_vault: &WorkspaceVault, path: &Path) -> Option<GraphNode> {
+    Some(GraphNode {
+        vault_name: workspace_vault.name.clone(),
+        relative_path: path
+            .strip_prefix(&workspace_vault.vault.path)
+            .ok()?
+            .to_owned(),
+    })
+}
+
+/// The notes a note links to, through obsidian links into its own vault and cross vault links into the others
+fn get_note_link_targets(
+    workspace: &Workspace,
+    workspace_vault: &WorkspaceVault,
+    working_items: &[cluster_note::WorkingPath],
+    path: &Path,
+) -> Option<Vec<GraphNode>> {
+    let content = comm::read_file_content(path)?;
+
+    let events = comm::parse_markdown_file(&content);
+
+    let vault_targets = comm::extract_obsidian_md_links(&events)
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|link_item| link_item.links)
+        .flat_map(|link| link.opt_file_link)
+        .filter(|note_link| !lint::is_attachment_link(note_link))
+        .flat_map(|note_link| cluster_note::note_link_to_path(working_items, &note_link))
+        .flat_map(|target_path| get_graph_node(workspace_vault, &target_path));
+
+    let cross_vault_targets = workspace::extract_markdown_link_urls(&events)
+        .into_iter()
+        .flat_map(|url| workspace.parse_cross_vault_link(&url))
+        .filter(|link| workspace.resolve_cross_vault_link(link).is_some())
+        .map(|link| GraphNode {
+            vault_name: link.vault_name,
+            relative_path: link.relative_path,
+        });
+
+    Some(vault_targets.chain(cross_vault_targets).collect())
+}
+
+pub fn get_workspace_link_graph(workspace: &Workspace) -> Option<LinkGraph> {
+    let graph = {
+        let mut mut_graph = LinkGraph::default();
+
+        for workspace_vault in workspace.vaults.iter() {
+            let working_items =
+                cluster_note::get_working_item_paths_in_vault(&workspace_vault.vault)?;
+
+            for path in drivers::get_markdown_file_paths_in_vault(&workspace_vault.vault, true) {
+                let Some(from) = get_graph_node(workspace_vault, &path) else {
+                    continue;
+                };
+
+                for to in get_note_link_targets(workspace, workspace_vault, &working_items, &path)
+                    .unwrap_or_default()
+                {
+                    mut_graph.nodes.insert(to.clone());
+                    mut_graph.edges.insert(GraphEdge {
+                        from: from.clone(),
+                        to,
+                    });
+                }
+
+                mut_graph.nodes.insert(from);
+            }
+        }
+
+        mut_graph
+    };
+
+    Some(graph)
+}
+
+impl fmt::Display for GraphNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.vault_name, self.relative_path.display())
+    }
+}
+
+/// The graph in the DOT language of graphviz
+impl fmt::Display for LinkGraph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let quoted = |node: &GraphNode| format!("{:?}", node.to_string());
+
+        writeln!(f, "digraph links {{")?;
+
+        for node in self.nodes.iter() {
+            writeln!(f, "  {};", quoted(node))?;
+        }
+
+        for edge in self.edges.iter() {
+            writeln!(f, "  {} -> {};", quoted(&edge.from), quoted(&edge.to))?;
+        }
+
+        write!(f, "}}")
+    }
+}
@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+use crate::{
+    cluster_note,
+    common::{self as comm, ObsidianVaultPath},
+};
+
+pub const INCREMENTAL_STATE_FILE_NAME: &str = "incremental_state.ron";
+
+/// What a note looked like after the last successful run of a command
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteState {
+    pub content_hash: String,
+
+    /// The file part of every obsidian link in the note, as written
+    pub link_targets: Vec<String>,
+}
+
+/// Note states per command, with note paths relative to the vault
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IncrementalState {
+    pub commands: BTreeMap<String, BTreeMap<PathBuf, NoteState>>,
+}
+
+#[derive(Error, Debug)]
+pub enum SaveIncrementalStateError {
+    #[error("Failed to serialize incremental state: {0:?}")]
+    Serialize(#[from] ron::Error),
+
+    #[error("Failed io operation: {0:?}")]
+    Io(#[from] std::io::Error),
+}
+
+impl IncrementalState {
+    pub fn path_in_vault(vault: &ObsidianVaultPath) -> PathBuf {
+        vault.tool_folder().join(INCREMENTAL_STATE_FILE_NAME)
+    }
+
+    /// Loads the incremental state of the vault, or an empty state if there was no run before
+    pub fn load(vault: &ObsidianVaultPath) -> Self {
+        let state_path = Self::path_in_vault(vault);
+
+        let Some(content) = comm::read_file_content(&state_path) else {
+            return Self::default();
+        };
+
+        ron::from_str(&content)
+            .map_err(|e| log::warn!("Ignoring unreadable incremental state {state_path:?}: {e}"))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, vault: &ObsidianVaultPath) -> Result<(), SaveIncrementalStateError> {
+        let state_path = Self::path_in_vault(vault);
+
+        let content = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+
+        fs::create_dir_all(vault.tool_folder())?;
+
+        comm::write_file_content(&content, &state_path)?;
+
+        Ok(())
+    }
+}
+
+pub fn hash_content(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+pub fn get_note_state(path: &Path) -> Option<NoteState> {
+    let content = comm::read_file_content(path)?;
+
+    let events = comm::parse_markdown_file(&content);
+
+    // Notes with links we can't parse are still tracked, they just don't get link based updates
+    let link_targets = comm::extract_obsidian_md_links(&events)
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|link_item| link_item.links)
+        .flat_map(|link| link.opt_file_link)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    Some(NoteState {
+        content_hash: hash_content(&content),
+        link_targets,
+    })
+}
+
+fn get_note_states(vault: &ObsidianVaultPath, paths: &[PathBuf]) -> BTreeMap<PathBuf, NoteState> {
+    paths
+        .iter()
+        .flat_map(|path| {
+            let relative_path = path.strip_prefix(&vault.path).ok()?.to_path_buf();
+
+            Some((relative_path, get_note_state(path)?))
+        })
+        .collect()
+}
+
+/// A run of a command that only processes the notes that changed since its last successful run, and the notes
+/// linking to them.
+#[derive(Debug)]
+pub struct IncrementalRun {
+    pub command: String,
+    pub dirty_paths: BTreeSet<PathBuf>,
+}
+
+impl IncrementalRun {
+    /// Compares the current markdown files of the vault against the state of the last successful run
+    pub fn start(vault: &ObsidianVaultPath, command: &str, paths: &[PathBuf]) -> Self {
+        let incremental_state = IncrementalState::load(vault);
+
+        let empty = BTreeMap::new();
+        let previous = incremental_state.commands.get(command).unwrap_or(&empty);
+
+        let current = get_note_states(vault, paths);
+
+        let changed = current
+            .iter()
+            .filter(|(path, note_state)| previous.get(*path) != Some(*note_state))
+            .map(|(path, _)| path.clone())
+            .collect::<BTreeSet<_>>();
+
+        let removed = previous
+            .keys()
+            .filter(|path| !current.contains_key(*path))
+            .cloned()
+            .collect::<BTreeSet<_>>();
+
+        let links_to_changed = current
+            .iter()
+            .filter(|(_, note_state)| {
+                note_state.link_targets.iter().any(|link_target| {
+                    changed
+                        .iter()
+                        .chain(removed.iter())
+                        .any(|path| cluster_note::note_link_matches_path(path, link_target))
+                })
+            })
+            .map(|(path, _)| path.clone());
+
+        let dirty_paths = changed
+            .iter()
+            .cloned()
+            .chain(links_to_changed)
+            .map(|path| vault.path.join(path))
+            .collect::<BTreeSet<_>>();
+
+        log::info!(
+            "{command}: {} of {} notes changed or link to changed notes since the last run",
+            dirty_paths.len(),
+            current.len()
+        );
+
+        Self {
+            command: command.to_owned(),
+            dirty_paths,
+        }
+    }
+
+    pub fn is_dirty(&self, path: &Path) -> bool {
+        self.dirty_paths.contains(path)
+    }
+
+    /// Records the state of the vault after the run. `paths` are the markdown files of the vault after the
+    /// run, since the command may have moved some. Failed notes are left out so they are processed again.
+    pub fn finish(
+        self,
+        vault: &ObsidianVaultPath,
+        paths: &[PathBuf],
+        failed_paths: &BTreeSet<PathBuf>,
+    ) -> Result<(), SaveIncrementalStateError> {
+        let succeeded_paths = paths
+            .iter()
+            .filter(|path| !failed_paths.contains(*path))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut mut_incremental_state = IncrementalState::load(vault);
+
+        mut_incremental_state
+            .commands
+            .insert(self.command, get_note_states(vault, &succeeded_paths));
+
+        mut_incremental_state.save(vault)
+    }
+}
@@ -1,4 +1,22 @@
+pub mod cluster_move;
 pub mod cluster_note;
 pub mod cluster_note_io;
 pub mod common;
+pub mod dashboard;
+pub mod diagnostics;
 pub mod drivers;
+pub mod exclusion;
+pub mod fsck;
+pub mod graph;
+pub mod incremental;
+pub mod lint;
+pub mod mutation;
+pub mod numbering;
+pub mod scaffold;
+pub mod stats;
+pub mod timeline;
+pub mod uncluster;
+pub mod vault_config;
+pub mod vault_index;
+pub mod watch;
+pub mod workspace;
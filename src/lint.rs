@@ -0,0 +1,131 @@
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::{
+    cluster_note::{self, WorkingPath},
+    common::{self as comm, ExtractOBsidianMdLinksError, RenderEventsToCommonMarkdownError},
+    vault_config::VaultConfig,
+    workspace::{self, Workspace},
+};
+
+#[derive(Error, Debug)]
+pub enum LintIssue {
+    #[error("Links could not be parsed: {0}")]
+    UnparsableLinks(ExtractOBsidianMdLinksError),
+
+    #[error("Note could not be rendered back to markdown: {0}")]
+    NotRenderable(RenderEventsToCommonMarkdownError),
+
+    #[error("Note is not normalized, writeback would change it")]
+    NotNormalized,
+
+    #[error("Note still has {0} old format records to extract")]
+    HasOldFormatEntries(usize),
+
+    #[error("Link does not resolve to a note in the vault: {0:?}")]
+    BrokenLink(String),
+
+    #[error("Link does not resolve to a note in the workspace vault it points to: {0:?}")]
+    BrokenCrossVaultLink(String),
+}
+
+#[derive(Error, Debug)]
+pub enum LintNoteError {
+    #[error("Failed to read note {0:?}")]
+    ReadFailed(PathBuf),
+}
+
+/// Links with a short extension other than markdown point to attachments, which are not working items
+pub fn is_attachment_link(note_link: &str) -> bool {
+    match Path::new(note_link).extension() {
+        Some(extension) => {
+            let extension = extension.to_string_lossy();
+
+            extension != "md" && extension.len() <= 5 && !extension.contains(' ')
+        }
+        None => false,
+    }
+}
+
+pub fn lint_note(path: &Path, config: &VaultConfig) -> Result<Vec<LintIssue>, LintNoteError> {
+    let content =
+        comm::read_file_content(path).ok_or(LintNoteError::ReadFailed(path.to_path_buf()))?;
+
+    let events = comm::parse_markdown_file(&content);
+
+    let issues = {
+        let mut mut_issues = vec![];
+
+        if let Err(e) = comm::extract_obsidian_md_links(&events) {
+            mut_issues.push(LintIssue::UnparsableLinks(e));
+        }
+
+        match comm::writeback_markdown_content(&content) {
+            Ok(new_content) if new_content != content => mut_issues.push(LintIssue::NotNormalized),
+            Ok(_) => (),
+            Err(e) => mut_issues.push(LintIssue::NotRenderable(e)),
+        }
+
+        let layout = cluster_note::detect_old_format_layout(&events, config);
+
+        if let Ok(old_format_entries) =
+            cluster_note::get_note_old_format_entries(&content, &events, config, &layout)
+            && !old_format_entries.is_empty()
+        {
+            mut_issues.push(LintIssue::HasOldFormatEntries(old_format_entries.len()));
+        }
+
+        mut_issues
+    };
+
+    Ok(issues)
+}
+
+pub fn check_note_links(
+    vault: &[WorkingPath],
+    path: &Path,
+) -> Result<Vec<LintIssue>, LintNoteError> {
+    let content =
+        comm::read_file_content(path).ok_or(LintNoteError::ReadFailed(path.to_path_buf()))?;
+
+    let events = comm::parse_markdown_file(&content);
+
+    // Unparsable links are reported by the lint
+    let Ok(link_items) = comm::extract_obsidian_md_links(&events) else {
+        return Ok(vec![]);
+    };
+
+    let issues = link_items
+        .into_iter()
+        .flat_map(|link_item| link_item.links)
+        .flat_map(|link| link.opt_file_link)
+        .filter(|note_link| !is_attachment_link(note_link))
+        .filter(|note_link| cluster_note::note_link_to_path(vault, note_link).is_none())
+        .map(LintIssue::BrokenLink)
+        .collect();
+
+    Ok(issues)
+}
+
+/// Checks the GitHub and `obsidian://` links of the note that point into vaults of the workspace
+pub fn check_note_cross_vault_links(
+    workspace: &Workspace,
+    path: &Path,
+) -> Result<Vec<LintIssue>, LintNoteError> {
+    let content =
+        comm::read_file_content(path).ok_or(LintNoteError::ReadFailed(path.to_path_buf()))?;
+
+    let events = comm::parse_markdown_file(&content);
+
+    let issues = workspace::extract_markdown_link_urls(&events)
+        .into_iter()
+        .filter(|url| {
+            workspace
+                .parse_cross_vault_link(url)
+                .is_some_and(|link| workspace.resolve_cross_vault_link(&link).is_none())
+        })
+        .map(LintIssue::BrokenCrossVaultLink)
+        .collect();
+
+    Ok(issues)
+}
@@ -0,0 +1,486 @@
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use thiserror::Error;
+
+use crate::{
+    cluster_move,
+    common::{self as comm, ObsidianVaultPath},
+};
+
+pub const MUTATION_JOURNAL_FILE_NAME: &str = "mutation_journal.ron";
+
+/// One line per mutation run since the journal was last saved: `+` when applied, `-` when undone
+pub const MUTATION_JOURNAL_PROGRESS_FILE_NAME: &str = "mutation_journal_progress";
+
+/// Older entries are dropped from the journal, and can no longer be undone
+pub const MAX_JOURNAL_ENTRIES: usize = 100;
+
+/// A single change to the vault. Each one carries what it expects to find, so it can be checked before it is
+/// applied and inverted to undo it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mutation {
+    CreateDir {
+        path: PathBuf,
+    },
+    RemoveDir {
+        path: PathBuf,
+    },
+    WriteFile {
+        path: PathBuf,
+        opt_old_content: Option<String>,
+        new_content: String,
+    },
+    RemoveFile {
+        path: PathBuf,
+        content: String,
+    },
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum MutationConflict {
+    #[error("{0:?} already exists")]
+    AlreadyExists(PathBuf),
+
+    #[error("{0:?} does not exist")]
+    Missing(PathBuf),
+
+    #[error("{0:?} changed since the mutation was planned")]
+    ContentChanged(PathBuf),
+}
+
+impl Mutation {
+    /// Writes a file with the given content, expecting whatever it holds now
+    pub fn write_file(path: &Path, new_content: &str) -> Self {
+        Self::WriteFile {
+            path: path.to_path_buf(),
+            opt_old_content: comm::read_file_content(path),
+            new_content: new_content.to_owned(),
+        }
+    }
+
+    pub fn inverse(&self) -> Self {
+        match self {
+            Self::CreateDir { path } => Self::RemoveDir { path: path.clone() },
+            Self::RemoveDir { path } => Self::CreateDir { path: path.clone() },
+            Self::WriteFile {
+                path,
+                opt_old_content: Some(old_content),
+                new_content,
+            } => Self::WriteFile {
+                path: path.clone(),
+                opt_old_content: Some(new_content.clone()),
+                new_content: old_content.clone(),
+            },
+            Self::WriteFile {
+                path,
+                opt_old_content: None,
+                new_content,
+            } => Self::RemoveFile {
+                path: path.clone(),
+                content: new_content.clone(),
+            },
+            Self::RemoveFile { path, content } => Self::WriteFile {
+                path: path.clone(),
+                opt_old_content: None,
+                new_content: content.clone(),
+            },
+            Self::Rename { from, to } => Self::Rename {
+                from: to.clone(),
+                to: from.clone(),
+            },
+        }
+    }
+
+    /// Checks that the vault is in the state the mutation expects
+    pub fn check(&self) -> Result<(), MutationConflict> {
+        let expect_content = |path: &Path, content: &str| match comm::read_file_content(path) {
+            Some(current) if current == content => Ok(()),
+            Some(_) => Err(MutationConflict::ContentChanged(path.to_path_buf())),
+            None => Err(MutationConflict::Missing(path.to_path_buf())),
+        };
+
+        match self {
+            Self::CreateDir { path } if path.exists() => {
+                Err(MutationConflict::AlreadyExists(path.clone()))
+            }
+            Self::RemoveDir { path } if !path.is_dir() => {
+                Err(MutationConflict::Missing(path.clone()))
+            }
+            Self::WriteFile {
+                path,
+                opt_old_content: Some(old_content),
+                ..
+            } => expect_content(path, old_content),
+            Self::WriteFile {
+                path,
+                opt_old_content: None,
+                ..
+            } if path.exists() => Err(MutationConflict::AlreadyExists(path.clone())),
+            Self::RemoveFile { path, content } => expect_content(path, content),
+            Self::Rename { from, .. } if !from.exists() => {
+                Err(MutationConflict::Missing(from.clone()))
+            }
+            Self::Rename { to, .. } if to.exists() => {
+                Err(MutationConflict::AlreadyExists(to.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn apply(&self) -> std::io::Result<()> {
+        match self {
+            Self::CreateDir { path } => fs::create_dir(path),
+            Self::RemoveDir { path } => fs::remove_dir(path),
+            Self::WriteFile {
+                path, new_content, ..
+            } => comm::write_file_content(new_content, path).map(|_| ()),
+            Self::RemoveFile { path, .. } => fs::remove_file(path),
+            Self::Rename { from, to } => fs::rename(from, to),
+        }
+    }
+}
+
+impl fmt::Display for Mutation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CreateDir { path } => write!(f, "create folder {path:?}"),
+            Self::RemoveDir { path } => write!(f, "remove folder {path:?}"),
+            Self::WriteFile {
+                path,
+                opt_old_content: Some(_),
+                ..
+            } => write!(f, "rewrite {path:?}"),
+            Self::WriteFile {
+                path,
+                opt_old_content: None,
+                ..
+            } => write!(f, "create {path:?}"),
+            Self::RemoveFile { path, .. } => write!(f, "remove {path:?}"),
+            Self::Rename { from, to } => write!(f, "move {from:?} to {to:?}"),
+        }
+    }
+}
+
+/// Rewrites obsidian links to a note for its new name. A link ends with the note, with or without its markdown
+/// extension, or goes on with a sublink or a title. Links naming the note by its folders are left to
+/// `cluster_move::redirect_folder_links`.
+pub fn rename_note_links(content: &str, old_stem: &str, new_stem: &str) -> String {
+    ["", ".md"].iter().cartesian_product(["]]", "#", "|"]).fold(
+        content.to_owned(),
+        |content, (extension, end)| {
+            content.replace(
+                &format!("[[{old_stem}{extension}{end}"),
+                &format!("[[{new_stem}{extension}{end}"),
+            )
+        },
+    )
+}
+
+/// Moves a note and rewrites the obsidian links to it in the given notes, by name or by its folders. Links are
+/// rewritten before the move, so a note linking to itself is rewritten in place.
+pub fn get_rename_note_mutations(
+    vault: &ObsidianVaultPath,
+    note_paths: &[PathBuf],
+    from: &Path,
+    to: &Path,
+) -> Vec<Mutation> {
+    let (Some(old_stem), Some(new_stem)) = (from.file_stem(), to.file_stem()) else {
+        return vec![];
+    };
+
+    let (old_stem, new_stem) = (old_stem.to_string_lossy(), new_stem.to_string_lossy());
+
+    let link_rewrites = note_paths
+        .iter()
+        .flat_map(|path| {
+            let content = comm::read_file_content(path)?;
+
+            let new_content = rename_note_links(
+                &cluster_move::redirect_folder_links(
+                    vault,
+                    &content,
+                    &[(from.to_path_buf(), to.to_path_buf())],
+                ),
+                &old_stem,
+                &new_stem,
+            );
+
+            (new_content != content).then(|| Mutation::WriteFile {
+                path: path.clone(),
+                opt_old_content: Some(content),
+                new_content,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    {
+        let mut mut_mutations = link_rewrites;
+
+        if from != to {
+            mut_mutations.push(Mutation::Rename {
+                from: from.to_path_buf(),
+                to: to.to_path_buf(),
+            });
+        }
+
+        mut_mutations
+    }
+}
+
+/// Content changes to notes, combined so each note is written once however many changes touch it
+#[derive(Debug, Default)]
+pub struct NoteEdits {
+    edits: BTreeMap<PathBuf, (String, String)>,
+}
+
+impl NoteEdits {
+    /// Changes the content of a note as edited so far. None if the note can't be read.
+    pub fn edit(&mut self, path: &Path, f: impl FnOnce(&str) -> String) -> Option<()> {
+        let (old_content, content) = match self.edits.remove(path) {
+            Some(edit) => edit,
+            None => {
+                let content = comm::read_file_content(path)?;
+
+                (content.clone(), content)
+            }
+        };
+
+        let new_content = f(&content);
+
+        self.edits
+            .insert(path.to_path_buf(), (old_content, new_content));
+
+        Some(())
+    }
+
+    pub fn into_mutations(self) -> Vec<Mutation> {
+        self.edits
+            .into_iter()
+            .filter(|(_, (old_content, new_content))| old_content != new_content)
+            .map(|(path, (old_content, new_content))| Mutation::WriteFile {
+                path,
+                opt_old_content: Some(old_content),
+                new_content,
+            })
+            .collect()
+    }
+}
+
+/// Mutations applied together by one command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub command: String,
+    pub timestamp_secs: u64,
+    pub mutations: Vec<Mutation>,
+
+    /// How many of the mutations were applied. Less than all of them if the command was interrupted.
+    pub applied: usize,
+}
+
+/// The mutations made to the vault, newest last, so they can be undone
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MutationJournal {
+    pub entries: Vec<JournalEntry>,
+}
+
+#[derive(Error, Debug)]
+pub enum MutationJournalError {
+    #[error("Failed to parse the mutation journal {0:?}: {1}")]
+    Parse(PathBuf, ron::error::SpannedError),
+
+    #[error("Failed to serialize the mutation journal: {0:?}")]
+    Serialize(#[from] ron::Error),
+
+    #[error("Failed io operation: {0:?}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Mutation {index} ({mutation}) cannot be applied: {conflict}")]
+    Conflict {
+        index: usize,
+        mutation: Mutation,
+        conflict: MutationConflict,
+    },
+}
+
+impl MutationJournal {
+    pub fn path_in_vault(vault: &ObsidianVaultPath) -> PathBuf {
+        vault.tool_folder().join(MUTATION_JOURNAL_FILE_NAME)
+    }
+
+    pub fn progress_path_in_vault(vault: &ObsidianVaultPath) -> PathBuf {
+        vault
+            .tool_folder()
+            .join(MUTATION_JOURNAL_PROGRESS_FILE_NAME)
+    }
+
+    /// Unlike other tool state, an unreadable journal is an error, since saving over it would lose the history.
+    /// Progress left by an interrupted command is counted into the last entry.
+    pub fn load(vault: &ObsidianVaultPath) -> Result<Self, MutationJournalError> {
+        let journal_path = Self::path_in_vault(vault);
+
+        let Some(content) = comm::read_file_content(&journal_path) else {
+            return Ok(Self::default());
+        };
+
+        let mut mut_journal = ron::from_str::<Self>(&content)
+            .map_err(|e| MutationJournalError::Parse(journal_path, e))?;
+
+        if let (Some(progress), Some(entry)) = (
+            comm::read_file_content(&Self::progress_path_in_vault(vault)),
+            mut_journal.entries.last_mut(),
+        ) {
+            for line in progress.lines() {
+                match line {
+                    "+" => entry.applied += 1,
+                    "-" => entry.applied = entry.applied.saturating_sub(1),
+                    _ => (),
+                }
+            }
+
+            entry.applied = entry.applied.min(entry.mutations.len());
+        }
+
+        Ok(mut_journal)
+    }
+
+    /// Saving folds in the progress, so the progress file is removed
+    pub fn save(&self, vault: &ObsidianVaultPath) -> Result<(), MutationJournalError> {
+        let content = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+
+        fs::create_dir_all(vault.tool_folder())?;
+
+        comm::write_file_content(&content, &Self::path_in_vault(vault))?;
+
+        match fs::remove_file(Self::progress_path_in_vault(vault)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e)?,
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Runs the mutations, counting each applied one in `mut_run` and appending the mark to the progress file. The
+/// journal itself is only saved by the caller, before and after.
+fn run_mutations<'a>(
+    vault: &ObsidianVaultPath,
+    mutations: impl Iterator<Item = (usize, &'a Mutation)>,
+    progress_mark: &str,
+    mut_run: &mut usize,
+) -> Result<(), MutationJournalError> {
+    let mut mut_progress_file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(MutationJournal::progress_path_in_vault(vault))?;
+
+    for (index, mutation) in mutations {
+        mutation
+            .check()
+            .map_err(|conflict| MutationJournalError::Conflict {
+                index,
+                mutation: mutation.clone(),
+                conflict,
+            })?;
+
+        mutation.apply()?;
+        *mut_run += 1;
+
+        writeln!(mut_progress_file, "{progress_mark}")?;
+    }
+
+    Ok(())
+}
+
+/// Applies the mutations in order, recording each in the journal as it is applied. Stops at the first mutation
+/// that conflicts with the vault, leaving the ones before it applied and journaled.
+pub fn apply_mutations(
+    vault: &ObsidianVaultPath,
+    command: &str,
+    mutations: Vec<Mutation>,
+) -> Result<(), MutationJournalError> {
+    if mutations.is_empty() {
+        return Ok(());
+    }
+
+    let mut mut_journal = MutationJournal::load(vault)?;
+
+    mut_journal.entries.push(JournalEntry {
+        command: command.to_owned(),
+        timestamp_secs: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default(),
+        mutations,
+        applied: 0,
+    });
+
+    let excess_entries = mut_journal
+        .entries
+        .len()
+        .saturating_sub(MAX_JOURNAL_ENTRIES);
+    mut_journal.entries.drain(..excess_entries);
+
+    mut_journal.save(vault)?;
+
+    let entry = mut_journal.entries.last_mut().unwrap();
+
+    let result = run_mutations(
+        vault,
+        entry.mutations.iter().enumerate(),
+        "+",
+        &mut entry.applied,
+    );
+
+    mut_journal.save(vault)?;
+
+    result
+}
+
+/// Undoes the applied mutations of the last journal entry, newest first. Returns the entry, or None if the
+/// journal is empty.
+pub fn undo_last_mutations(
+    vault: &ObsidianVaultPath,
+) -> Result<Option<JournalEntry>, MutationJournalError> {
+    let mut mut_journal = MutationJournal::load(vault)?;
+
+    let Some(entry) = mut_journal.entries.last().cloned() else {
+        return Ok(None);
+    };
+
+    let inverses = entry.mutations[..entry.applied]
+        .iter()
+        .map(Mutation::inverse)
+        .enumerate()
+        .rev()
+        .collect::<Vec<_>>();
+
+    let mut mut_undone = 0;
+
+    let result = run_mutations(
+        vault,
+        inverses.iter().map(|(index, mutation)| (*index, mutation)),
+        "-",
+        &mut mut_undone,
+    );
+
+    if let Err(e) = result {
+        mut_journal.entries.last_mut().unwrap().applied -= mut_undone;
+        mut_journal.save(vault)?;
+
+        return Err(e);
+    }
+
+    mut_journal.entries.pop();
+    mut_journal.save(vault)?;
+
+    Ok(Some(entry))
+}
@@ -0,0 +1,264 @@
+use itertools::Itertools;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+use crate::{
+    cluster_move,
+    cluster_note::{self, WorkingPath},
+    common::ObsidianVaultPath,
+    drivers,
+    mutation::{self, Mutation, NoteEdits},
+    vault_config::NumberingScope,
+};
+
+/// Splits a `NNN Title` note name into its number and title
+pub fn parse_numeric_prefix(stem: &str) -> Option<(u32, &str)> {
+    let (prefix, title) = stem.split_once(' ')?;
+
+    if prefix.len() != 3 || !prefix.chars().all(|c| c.is_ascii_digit()) || title.is_empty() {
+        return None;
+    }
+
+    Some((prefix.parse().ok()?, title))
+}
+
+pub fn format_numbered_name(number: u32, title: &str) -> String {
+    format!("{number:03} {title}")
+}
+
+/// The number after the highest one taken, starting at 000
+pub fn get_next_free_number(taken: impl IntoIterator<Item = u32>) -> u32 {
+    taken.into_iter().max().map_or(0, |number| number + 1)
+}
+
+/// The closest folder above the path named like a year
+fn get_year_folder(path: &Path) -> Option<&Path> {
+    path.ancestors().find(|ancestor| {
+        ancestor.file_name().is_some_and(|name| {
+            let name = name.to_string_lossy();
+
+            name.len() == 4 && name.chars().all(|c| c.is_ascii_digit())
+        })
+    })
+}
+
+/// Identifies the sequence the peripheral notes of a context type folder are numbered in. Without a year folder
+/// above it, a year scoped folder counts on its own.
+pub fn get_numbering_group(category_folder: &Path, scope: NumberingScope) -> PathBuf {
+    match (
+        scope,
+        get_year_folder(category_folder),
+        category_folder.file_name(),
+    ) {
+        (NumberingScope::Year, Some(year_folder), Some(folder_name)) => {
+            year_folder.join(folder_name)
+        }
+        _ => category_folder.to_path_buf(),
+    }
+}
+
+fn get_peripheral_notes_per_group(
+    items: &[WorkingPath],
+    scope: NumberingScope,
+) -> BTreeMap<PathBuf, Vec<PathBuf>> {
+    let mut mut_groups = BTreeMap::<PathBuf, Vec<PathBuf>>::new();
+
+    for item in items {
+        let WorkingPath::ClusterFolder {
+            category_folders_with_peripheral_files,
+            ..
+        } = item
+        else {
+            continue;
+        };
+
+        for (category_folder, files) in category_folders_with_peripheral_files {
+            mut_groups
+                .entry(get_numbering_group(&category_folder.path, scope))
+                .or_default()
+                .extend(files.iter().map(|file| file.path.clone()));
+        }
+    }
+
+    mut_groups
+}
+
+fn get_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Name for a new peripheral note in the context type folder, numbered after the notes of its sequence
+pub fn get_next_peripheral_note_name(
+    vault: &ObsidianVaultPath,
+    category_folder: &Path,
+    title: &str,
+) -> Option<String> {
+    let scope = vault.config.numbering;
+
+    let items = cluster_note::get_working_item_paths_in_vault(vault)?;
+
+    let next_number = get_peripheral_notes_per_group(&items, scope)
+        .get(&get_numbering_group(category_folder, scope))
+        .map(|paths| {
+            get_next_free_number(
+                paths
+                    .iter()
+                    .flat_map(|path| Some(parse_numeric_prefix(&get_stem(path))?.0)),
+            )
+        })
+        .unwrap_or_default();
+
+    Some(format_numbered_name(next_number, title))
+}
+
+#[derive(Error, Debug)]
+pub enum RenumberError {
+    #[error("Failed to list the clusters of vault {0:?}")]
+    ListFailed(PathBuf),
+
+    #[error("Failed to read note {0:?}")]
+    ReadFailed(PathBuf),
+
+    #[error("Cannot renumber to {0:?}, it already exists")]
+    NameTaken(PathBuf),
+}
+
+/// New names for the notes of a sequence. Notes keep their number unless another note took it first, and notes
+/// without a number or with a taken one are numbered after the highest number. Gaps are left alone.
+pub fn get_renumbered_names(stems: &[String]) -> Vec<(String, String)> {
+    get_renumbered_indices(stems)
+        .into_iter()
+        .map(|(i, new_stem)| (stems[i].clone(), new_stem))
+        .collect()
+}
+
+/// Like `get_renumbered_names`, by the position of the stem, since notes of different clusters can share one
+fn get_renumbered_indices(stems: &[String]) -> Vec<(usize, String)> {
+    let sorted_indices = {
+        let mut mut_indices = (0..stems.len()).collect::<Vec<_>>();
+
+        // Unnumbered notes come last
+        mut_indices.sort_by_key(|i| {
+            let opt_number = parse_numeric_prefix(&stems[*i]).map(|(number, _)| number);
+
+            (opt_number.is_none(), opt_number, &stems[*i])
+        });
+
+        mut_indices
+    };
+
+    let mut mut_taken = BTreeSet::new();
+
+    let unnumbered = sorted_indices
+        .into_iter()
+        .filter(|i| match parse_numeric_prefix(&stems[*i]) {
+            Some((number, _)) => !mut_taken.insert(number),
+            None => true,
+        })
+        .collect::<Vec<_>>();
+
+    let next_number = get_next_free_number(mut_taken.iter().copied());
+
+    unnumbered
+        .into_iter()
+        .zip(next_number..)
+        .map(|(i, number)| {
+            let stem = &stems[i];
+            let title = parse_numeric_prefix(stem).map_or(stem.as_str(), |(_, title)| title);
+
+            (i, format_numbered_name(number, title))
+        })
+        .collect()
+}
+
+/// Gives peripheral notes with a missing or duplicate number the next free one in their sequence, and rewrites
+/// the links to them. The scope of the vault config is used unless one is given.
+pub fn plan_renumber(
+    vault: &ObsidianVaultPath,
+    opt_scope: Option<NumberingScope>,
+) -> Result<Vec<Mutation>, RenumberError> {
+    let scope = opt_scope.unwrap_or(vault.config.numbering);
+
+    let items = cluster_note::get_working_item_paths_in_vault(vault)
+        .ok_or(RenumberError::ListFailed(vault.path.clone()))?;
+
+    let moves = get_peripheral_notes_per_group(&items, scope)
+        .into_values()
+        .flat_map(|paths| {
+            let stems = paths.iter().map(|path| get_stem(path)).collect::<Vec<_>>();
+
+            get_renumbered_indices(&stems)
+                .into_iter()
+                .map(|(i, new_stem)| {
+                    (
+                        paths[i].clone(),
+                        paths[i].with_file_name(format!("{new_stem}.md")),
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    if let Some((_, to)) = moves.iter().find(|(_, to)| to.exists()) {
+        return Err(RenumberError::NameTaken(to.clone()));
+    }
+
+    let note_paths = drivers::get_markdown_file_paths_in_vault(vault, true);
+
+    // A link by name alone is ambiguous when notes of different clusters share the name, so only links naming the
+    // folders of the note are rewritten then
+    let shared_stems = note_paths
+        .iter()
+        .map(|path| get_stem(path))
+        .counts()
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(stem, _)| stem)
+        .collect::<BTreeSet<_>>();
+
+    for (from, _) in moves.iter() {
+        if shared_stems.contains(&get_stem(from)) {
+            log::warn!(
+                "Links naming only {:?} are ambiguous and are left alone",
+                get_stem(from)
+            );
+        }
+    }
+
+    let mut mut_edits = NoteEdits::default();
+
+    for path in note_paths {
+        mut_edits
+            .edit(&path, |content| {
+                moves
+                    .iter()
+                    .filter(|(from, _)| !shared_stems.contains(&get_stem(from)))
+                    .fold(
+                        cluster_move::redirect_folder_links(vault, content, &moves),
+                        |content, (from, to)| {
+                            mutation::rename_note_links(&content, &get_stem(from), &get_stem(to))
+                        },
+                    )
+            })
+            .ok_or(RenumberError::ReadFailed(path.clone()))?;
+    }
+
+    let mutations = {
+        let mut mut_mutations = mut_edits.into_mutations();
+
+        mut_mutations.extend(
+            moves
+                .into_iter()
+                .map(|(from, to)| Mutation::Rename { from, to }),
+        );
+
+        mut_mutations
+    };
+
+    Ok(mutations)
+}
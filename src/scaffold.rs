@@ -0,0 +1,300 @@
+use regex::{Captures, Regex};
+use std::{
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::SystemTime,
+};
+use thiserror::Error;
+
+use crate::{
+    cluster_move::{self, ClusterMoveError},
+    cluster_note::{self, CoreNoteFilePath},
+    common::{self as comm, ObsidianVaultPath},
+    mutation::Mutation,
+    numbering, timeline,
+    vault_config::VaultConfig,
+    vault_index::VaultIndex,
+};
+
+pub const CORE_NOTE_TEMPLATE_FILE_NAME: &str = "cluster.md";
+pub const PERIPHERAL_NOTE_TEMPLATE_FILE_NAME: &str = "peripheral.md";
+
+/// Used for core notes when the vault has no `cluster.md` template
+pub const DEFAULT_CORE_NOTE_TEMPLATE: &str =
+    "---\ncreated: {{date}}\n---\n\n# {{title}}\n\n%% index start %%\n\n%% index end %%\n";
+
+/// Used for peripheral notes when the vault has neither a template for the context type nor a `peripheral.md` one
+pub const DEFAULT_PERIPHERAL_NOTE_TEMPLATE: &str =
+    "---\nparent: \"{{parent}}\"\ncreated: {{date}}\n---\n\n# {{title}}\n";
+
+#[derive(Error, Debug)]
+pub enum ScaffoldError {
+    #[error("Failed to index vault {0:?}: {1}")]
+    IndexFailed(PathBuf, String),
+
+    #[error("{0:?} already exists")]
+    NameTaken(PathBuf),
+
+    #[error("Folder {0:?} is not in the vault or is not a folder")]
+    NotAFolder(PathBuf),
+
+    #[error("{0:?} is not a valid note title")]
+    InvalidTitle(String),
+
+    #[error("No context type is named {0:?}")]
+    UnknownContextType(String),
+
+    #[error("Failed to list the peripheral notes of {0:?}")]
+    ListFailed(PathBuf),
+
+    #[error("Failed to update the index of the cluster: {0}")]
+    Cluster(#[from] ClusterMoveError),
+}
+
+/// Values for the `{{name}}` variables of templates
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TemplateVariables {
+    pub date: String,
+    pub title: String,
+
+    /// Note name, with the numeric prefix of peripheral notes
+    pub name: String,
+
+    /// Link to the core note, like `[[Cluster]]`
+    pub parent: String,
+
+    /// Numeric prefix of peripheral notes, like `007`
+    pub number: String,
+
+    /// A fresh `^spawn-{block_code}-0a1b2c` identifier
+    pub block_id: String,
+}
+
+static TEMPLATE_VARIABLE_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([a-z_]+)\s*\}\}").unwrap());
+
+/// The Templater commands templates of the vault already use for the same values
+static TEMPLATER_COMMAND_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"<%[*_-]?\s*tp\.(file\.title|date\.now\([^)]*\))\s*[_-]?%>").unwrap()
+});
+
+/// Fills in `{{date}}`, `{{title}}`, `{{name}}`, `{{parent}}`, `{{number}}` and `{{block_id}}`, along with the
+/// Templater commands `tp.file.title` and `tp.date.now()`. Dates are always `YYYY-MM-DD`. Other variables and
+/// commands are left for Templater.
+pub fn render_template(template: &str, variables: &TemplateVariables) -> String {
+    let content = TEMPLATE_VARIABLE_PATTERN.replace_all(template, |caps: &Captures| {
+        match &caps[1] {
+            "date" => &variables.date,
+            "title" => &variables.title,
+            "name" => &variables.name,
+            "parent" => &variables.parent,
+            "number" => &variables.number,
+            "block_id" => &variables.block_id,
+            _ => &caps[0],
+        }
+        .to_owned()
+    });
+
+    TEMPLATER_COMMAND_PATTERN
+        .replace_all(&content, |caps: &Captures| {
+            match caps[1].starts_with("file.title") {
+                true => variables.name.clone(),
+                false => variables.date.clone(),
+            }
+        })
+        .to_string()
+}
+
+/// The first template of the vault found, or the default
+fn get_template(
+    vault: &ObsidianVaultPath,
+    config: &VaultConfig,
+    file_names: &[&str],
+    default: &str,
+) -> String {
+    let templates_folder = vault.path.join(&config.templates_folder);
+
+    file_names
+        .iter()
+        .find_map(|file_name| comm::read_file_content(&templates_folder.join(file_name)))
+        .unwrap_or_else(|| default.to_owned())
+}
+
+fn get_note_title(title: &str) -> Result<String, ScaffoldError> {
+    let note_title = cluster_move::get_note_title_of_heading(title);
+
+    match note_title.is_empty() {
+        true => Err(ScaffoldError::InvalidTitle(title.to_owned())),
+        false => Ok(note_title),
+    }
+}
+
+fn build_index(vault: &ObsidianVaultPath) -> Result<VaultIndex, ScaffoldError> {
+    VaultIndex::build_for_vault(vault)
+        .map_err(|e| ScaffoldError::IndexFailed(vault.path.clone(), e.to_string()))
+}
+
+/// Creates a cluster named after the title in the folder, with a core note from the `cluster.md` template
+pub fn plan_new_cluster(
+    vault: &ObsidianVaultPath,
+    folder: &Path,
+    title: &str,
+    now: SystemTime,
+) -> Result<Vec<Mutation>, ScaffoldError> {
+    let index = build_index(vault)?;
+
+    let folder = vault.resolve_path(folder);
+
+    if !index.is_dir(&folder) {
+        return Err(ScaffoldError::NotAFolder(folder));
+    }
+
+    let name = get_note_title(title)?;
+
+    let cluster_root = folder.join(&name);
+
+    if cluster_root.exists() {
+        return Err(ScaffoldError::NameTaken(cluster_root));
+    }
+
+    let variables = TemplateVariables {
+        date: comm::format_date(now),
+        title: title.to_owned(),
+        name: name.clone(),
+        ..Default::default()
+    };
+
+    let template = get_template(
+        vault,
+        &index.config,
+        &[CORE_NOTE_TEMPLATE_FILE_NAME],
+        DEFAULT_CORE_NOTE_TEMPLATE,
+    );
+
+    Ok(vec![
+        Mutation::CreateDir {
+            path: cluster_root.clone(),
+        },
+        Mutation::WriteFile {
+            path: cluster_root.join(format!("{name}.md")),
+            opt_old_content: None,
+            new_content: render_template(&template, &variables),
+        },
+    ])
+}
+
+/// Creates a numbered peripheral note of the context type in a cluster, given by its core note or root folder, from
+/// the template of the context type folder, like `tasks.md`, or `peripheral.md`. The core note index lists it, and
+/// the Timeline note logs it.
+pub fn plan_new_peripheral_note(
+    vault: &ObsidianVaultPath,
+    cluster: &Path,
+    context_type_name: &str,
+    title: &str,
+    now: SystemTime,
+) -> Result<Vec<Mutation>, ScaffoldError> {
+    let index = build_index(vault)?;
+
+    let cluster = vault.resolve_path(cluster);
+
+    let core_note_path = match index.is_dir(&cluster) {
+        true => cluster.join(cluster_note::get_core_note_file_name(&cluster).unwrap_or_default()),
+        false => cluster,
+    };
+
+    let core_note = CoreNoteFilePath::new_in_index(&index, &core_note_path)
+        .ok_or(ClusterMoveError::NotACoreNote(core_note_path.clone()))?;
+
+    let context_type = index.config.find_context_type(context_type_name).ok_or(
+        ScaffoldError::UnknownContextType(context_type_name.to_owned()),
+    )?;
+
+    let (Some(cluster_root), Some(core_note_stem)) =
+        (core_note.path.parent(), core_note.path.file_stem())
+    else {
+        return Err(ClusterMoveError::NotACoreNote(core_note.path.clone()).into());
+    };
+
+    let core_note_stem = core_note_stem.to_string_lossy().to_string();
+
+    let category_folder = cluster_root.join(&context_type.folder);
+
+    let note_title = get_note_title(title)?;
+
+    let name = numbering::get_next_peripheral_note_name(vault, &category_folder, &note_title)
+        .ok_or(ScaffoldError::ListFailed(category_folder.clone()))?;
+
+    let note_path = category_folder.join(format!("{name}.md"));
+
+    if note_path.exists() {
+        return Err(ScaffoldError::NameTaken(note_path));
+    }
+
+    let variables = TemplateVariables {
+        date: comm::format_date(now),
+        title: title.to_owned(),
+        number: numbering::parse_numeric_prefix(&name)
+            .map(|(number, _)| format!("{number:03}"))
+            .unwrap_or_default(),
+        parent: format!("[[{core_note_stem}]]"),
+        block_id: cluster_note::new_spawn_block_identifier(&context_type.block_code, &name),
+        name: name.clone(),
+    };
+
+    let template = get_template(
+        vault,
+        &index.config,
+        &[
+            &format!("{}.md", context_type.folder),
+            PERIPHERAL_NOTE_TEMPLATE_FILE_NAME,
+        ],
+        DEFAULT_PERIPHERAL_NOTE_TEMPLATE,
+    );
+
+    let content = render_template(&template, &variables);
+
+    // A note that uses its block identifier gets the spawn marker it points to in the core note
+    let spawn_markers = match content.contains(&variables.block_id) {
+        true => vec![format!("Spawn [[{name}]] {}", variables.block_id)],
+        false => vec![],
+    };
+
+    let mutations = {
+        let mut mut_mutations = cluster_move::plan_core_note_index_with_new_note(
+            &index,
+            &core_note.path,
+            &context_type.folder,
+            &name,
+            &spawn_markers,
+        )?;
+
+        if !index.is_dir(&category_folder) {
+            mut_mutations.push(Mutation::CreateDir {
+                path: category_folder,
+            });
+        }
+
+        let timeline_texts = {
+            let mut mut_texts = vec![timeline::render_created_entry(&name, &core_note_stem)];
+
+            mut_texts.extend(
+                comm::get_frontmatter_property(&content, "status")
+                    .map(|status| timeline::render_status_entry(&name, &status)),
+            );
+
+            mut_texts
+        };
+
+        mut_mutations.push(Mutation::WriteFile {
+            path: note_path,
+            opt_old_content: None,
+            new_content: content,
+        });
+
+        mut_mutations.extend(timeline::plan_timeline_entries(vault, &timeline_texts, now));
+
+        mut_mutations
+    };
+
+    Ok(mutations)
+}
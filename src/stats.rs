@@ -0,0 +1,186 @@
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use crate::{
+    cluster_note::{self, SpawnMetadata, WorkingPath},
+    common::{self as comm, ObsidianVaultPath},
+    drivers, workspace,
+};
+
+/// How far along the migration of a vault is
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VaultStats {
+    pub normal_notes: usize,
+    pub clusters: usize,
+
+    /// Keyed by context type folder
+    pub peripheral_notes_per_category: BTreeMap<String, usize>,
+    pub attachments: usize,
+
+    /// Notes skipped as managed by obsidian plugins, like drawings and kanban boards
+    pub managed_notes: usize,
+
+    pub notes_with_old_format_entries: usize,
+    pub old_format_entries: usize,
+    pub notes_with_unparsable_old_format: usize,
+
+    /// `Spawn [[note]] ^spawn-...` markers
+    pub spawning_markers: usize,
+
+    /// `From [[#^spawn-...]] in [[note]]` markers
+    pub spawned_markers: usize,
+
+    pub obsidian_links: usize,
+    pub markdown_links: usize,
+    pub notes_with_unparsable_links: usize,
+
+    pub unreadable_notes: usize,
+    pub diagnostics: usize,
+}
+
+impl VaultStats {
+    pub fn peripheral_notes(&self) -> usize {
+        self.peripheral_notes_per_category.values().sum()
+    }
+
+    fn add_note_content(&mut self, vault: &ObsidianVaultPath, path: &Path) {
+        if drivers::skip_processing_managed_path(vault, path) {
+            self.managed_notes += 1;
+            return;
+        }
+
+        let Some(content) = comm::read_file_content(path) else {
+            self.unreadable_notes += 1;
+            return;
+        };
+
+        let events = comm::parse_markdown_file(&content);
+
+        let layout = cluster_note::detect_old_format_layout(&events, &vault.config);
+
+        match cluster_note::get_note_old_format_entries(&content, &events, &vault.config, &layout) {
+            Ok(old_format_entries) if !old_format_entries.is_empty() => {
+                self.notes_with_old_format_entries += 1;
+                self.old_format_entries += old_format_entries.len();
+            }
+            Ok(_) => (),
+            Err(_) => self.notes_with_unparsable_old_format += 1,
+        }
+
+        self.markdown_links += workspace::extract_markdown_link_urls(&events).len();
+
+        let Ok(links) = comm::extract_obsidian_md_links(&events) else {
+            self.notes_with_unparsable_links += 1;
+            return;
+        };
+
+        self.obsidian_links += links.iter().map(|link| link.links.len()).sum::<usize>();
+
+        let linkables = comm::extract_linkable_obsidian_md_items(&events);
+
+        for spawn_metadata in
+            cluster_note::extract_spawn_metadata_from_old_format(&linkables, &links)
+        {
+            match spawn_metadata {
+                SpawnMetadata::Spawning { .. } => self.spawning_markers += 1,
+                SpawnMetadata::Spawned { .. } => self.spawned_markers += 1,
+            }
+        }
+    }
+}
+
+pub fn get_vault_stats(vault: &ObsidianVaultPath) -> Option<VaultStats> {
+    let working_items = cluster_note::get_working_items_in_vault(vault)?;
+
+    let stats = {
+        let mut mut_stats = VaultStats {
+            diagnostics: working_items.diagnostics.len(),
+            ..Default::default()
+        };
+
+        for item in working_items.items.iter() {
+            match item {
+                // Managed notes are counted apart, not as normal notes
+                WorkingPath::Note(normal_note_file_path)
+                    if drivers::skip_processing_managed_path(
+                        vault,
+                        &normal_note_file_path.path,
+                    ) =>
+                {
+                    mut_stats.managed_notes += 1;
+                }
+                WorkingPath::Note(normal_note_file_path) => {
+                    mut_stats.normal_notes += 1;
+                    mut_stats.add_note_content(vault, &normal_note_file_path.path);
+                }
+                WorkingPath::ClusterFolder {
+                    core_note_file,
+                    category_folders_with_peripheral_files,
+                    attachment_files,
+                    ..
+                } => {
+                    mut_stats.clusters += 1;
+                    mut_stats.attachments += attachment_files.len();
+                    mut_stats.add_note_content(vault, &core_note_file.path);
+
+                    for (category_folder, peripheral_files) in
+                        category_folders_with_peripheral_files
+                    {
+                        let category = category_folder
+                            .path
+                            .file_name()
+                            .map(|name| name.to_string_lossy().to_string())
+                            .unwrap_or_default();
+
+                        *mut_stats
+                            .peripheral_notes_per_category
+                            .entry(category)
+                            .or_default() += peripheral_files.len();
+
+                        for peripheral_file in peripheral_files {
+                            mut_stats.add_note_content(vault, &peripheral_file.path);
+                        }
+                    }
+                }
+            }
+        }
+
+        mut_stats
+    };
+
+    Some(stats)
+}
+
+impl fmt::Display for VaultStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Normal notes: {}", self.normal_notes)?;
+        writeln!(f, "Clusters: {}", self.clusters)?;
+        writeln!(f, "Peripheral notes: {}", self.peripheral_notes())?;
+
+        for (category, count) in self.peripheral_notes_per_category.iter() {
+            writeln!(f, "  {category}: {count}")?;
+        }
+
+        writeln!(f, "Attachments: {}", self.attachments)?;
+        writeln!(f, "Managed notes skipped: {}", self.managed_notes)?;
+        writeln!(
+            f,
+            "Notes with old format entries: {} ({} entries, {} unparsable notes)",
+            self.notes_with_old_format_entries,
+            self.old_format_entries,
+            self.notes_with_unparsable_old_format
+        )?;
+        writeln!(
+            f,
+            "Spawn markers: {} spawning, {} spawned",
+            self.spawning_markers, self.spawned_markers
+        )?;
+        writeln!(
+            f,
+            "Links: {} obsidian, {} markdown ({} notes with unparsable links)",
+            self.obsidian_links, self.markdown_links, self.notes_with_unparsable_links
+        )?;
+        writeln!(f, "Unreadable notes: {}", self.unreadable_notes)?;
+        write!(f, "Diagnostics: {}", self.diagnostics)
+    }
+}
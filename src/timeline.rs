@@ -0,0 +1,205 @@
+use regex::Regex;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::SystemTime,
+};
+use thiserror::Error;
+
+use crate::{
+    cluster_note::{self, WorkingPath},
+    common::{self as comm, ObsidianVaultPath},
+    dashboard,
+    mutation::Mutation,
+};
+
+/// The Timeline note is kept by a plugin, so the writeback leaves it alone, but its links are kept up to date
+pub fn is_timeline_note(vault: &ObsidianVaultPath, path: &Path) -> bool {
+    path == get_timeline_note_path(vault)
+}
+
+/// A dated list item of a Timeline note
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelineEntry {
+    /// Line of the list item, from 0
+    pub line: usize,
+
+    /// As `YYYY-MM-DD`
+    pub date: String,
+
+    /// The list item without its date
+    pub text: String,
+
+    /// Names of the linked notes, without sublinks or titles
+    pub links: Vec<String>,
+}
+
+/// A date, written plainly or as a link to a daily note, followed by a separator
+static DATE_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:\[\[)?(\d{4}-\d{2}-\d{2})(?:\]\])?(?:\s*[:\-–]\s*|\s+|$)").unwrap()
+});
+
+static HEADING_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^#{1,6}\s+(.*)$").unwrap());
+
+static LIST_ITEM_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[-*+]\s+(?:\[.\]\s+)?(.*)$").unwrap());
+
+static LINK_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[\[([^\[\]|#]*)[^\[\]]*\]\]").unwrap());
+
+static STATUS_ENTRY_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[\[([^\[\]|#]+)[^\[\]]*\]\] status: (.+)$").unwrap());
+
+fn split_date(text: &str) -> Option<(&str, &str)> {
+    let caps = DATE_PATTERN.captures(text)?;
+
+    Some((caps.get(1)?.as_str(), &text[caps.get(0)?.end()..]))
+}
+
+/// Reads the entries of a Timeline note. Entries are top level list items, dated at their start or by the closest
+/// heading above that starts with a date. Undated items, and items nested under an entry, are not entries.
+pub fn parse_timeline(content: &str) -> Vec<TimelineEntry> {
+    let mut mut_heading_date = None;
+    let mut mut_entries = vec![];
+
+    for (line_index, line) in content.lines().enumerate() {
+        if let Some(caps) = HEADING_PATTERN.captures(line) {
+            mut_heading_date = split_date(caps[1].trim()).map(|(date, _)| date.to_owned());
+
+            continue;
+        }
+
+        let Some(caps) = LIST_ITEM_PATTERN.captures(line) else {
+            continue;
+        };
+
+        let item = caps[1].trim();
+
+        let (date, text) = match (split_date(item), &mut_heading_date) {
+            (Some((date, text)), _) => (date.to_owned(), text),
+            (None, Some(heading_date)) => (heading_date.clone(), item),
+            (None, None) => continue,
+        };
+
+        mut_entries.push(TimelineEntry {
+            line: line_index,
+            date,
+            text: text.to_owned(),
+            links: LINK_PATTERN
+                .captures_iter(text)
+                .map(|caps| caps[1].trim().to_owned())
+                .filter(|link| !link.is_empty())
+                .collect(),
+        });
+    }
+
+    mut_entries
+}
+
+pub fn render_created_entry(note_name: &str, core_note_stem: &str) -> String {
+    format!("Created [[{note_name}]] in [[{core_note_stem}]]")
+}
+
+pub fn render_status_entry(note_name: &str, status: &str) -> String {
+    format!("[[{note_name}]] status: {status}")
+}
+
+/// The last status each note was recorded with
+pub fn get_recorded_statuses(entries: &[TimelineEntry]) -> BTreeMap<String, String> {
+    entries
+        .iter()
+        .flat_map(|entry| {
+            let caps = STATUS_ENTRY_PATTERN.captures(&entry.text)?;
+
+            Some((caps[1].trim().to_owned(), caps[2].trim().to_owned()))
+        })
+        .collect()
+}
+
+/// Adds entries of the date at the end of a Timeline note
+pub fn append_timeline_entries(content: &str, date: &str, texts: &[String]) -> String {
+    let lines = texts
+        .iter()
+        .map(|text| format!("- {date} {text}\n"))
+        .collect::<String>();
+
+    match content.trim_end() {
+        "" => lines,
+        content => format!("{content}\n{lines}"),
+    }
+}
+
+pub fn get_timeline_note_path(vault: &ObsidianVaultPath) -> PathBuf {
+    vault.path.join(&vault.config.timeline_note)
+}
+
+/// Logs the entries to the Timeline note of the vault. Vaults without one are left alone.
+pub fn plan_timeline_entries(
+    vault: &ObsidianVaultPath,
+    texts: &[String],
+    now: SystemTime,
+) -> Option<Mutation> {
+    let timeline_note = get_timeline_note_path(vault);
+
+    let content = comm::read_file_content(&timeline_note)?;
+
+    (!texts.is_empty()).then(|| {
+        Mutation::write_file(
+            &timeline_note,
+            &append_timeline_entries(&content, &comm::format_date(now), texts),
+        )
+    })
+}
+
+#[derive(Error, Debug)]
+pub enum TimelineError {
+    #[error("No Timeline note at {0:?}")]
+    Missing(PathBuf),
+
+    #[error("Failed to list the clusters of vault {0:?}")]
+    ListFailed(PathBuf),
+}
+
+/// Logs the doer notes whose status differs from the last one the Timeline note recorded for them
+pub fn plan_record_status_changes(
+    vault: &ObsidianVaultPath,
+    now: SystemTime,
+) -> Result<Vec<Mutation>, TimelineError> {
+    let timeline_note = get_timeline_note_path(vault);
+
+    let content = comm::read_file_content(&timeline_note)
+        .ok_or(TimelineError::Missing(timeline_note.clone()))?;
+
+    let recorded_statuses = get_recorded_statuses(&parse_timeline(&content));
+
+    let items = cluster_note::get_working_item_paths_in_vault(vault)
+        .ok_or(TimelineError::ListFailed(vault.path.clone()))?;
+
+    let texts = items
+        .iter()
+        .flat_map(|item| match item {
+            WorkingPath::ClusterFolder {
+                category_folders_with_peripheral_files,
+                ..
+            } => dashboard::get_doer_notes(
+                &vault.config,
+                category_folders_with_peripheral_files,
+                now,
+            ),
+            WorkingPath::Note(_) => vec![],
+        })
+        .flat_map(|doer_note| {
+            let status = doer_note.opt_status.as_ref()?;
+
+            let name = doer_note.name();
+
+            (recorded_statuses.get(&name) != Some(status))
+                .then(|| render_status_entry(&name, status))
+        })
+        .collect::<Vec<_>>();
+
+    Ok(plan_timeline_entries(vault, &texts, now)
+        .into_iter()
+        .collect())
+}
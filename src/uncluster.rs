@@ -0,0 +1,565 @@
+use itertools::Itertools;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::{
+    cluster_move,
+    cluster_note::{self, ClusterRootFolderPath, CoreNoteFilePath, OldFormatLayout},
+    common::{self as comm, ObsidianVaultPath},
+    drivers,
+    mutation::{self, Mutation},
+    vault_config::{ContextType, VaultConfig},
+    vault_index::VaultIndex,
+};
+
+#[derive(Error, Debug)]
+pub enum UnclusterError {
+    #[error("Failed to index vault {0:?}: {1}")]
+    IndexFailed(PathBuf, String),
+
+    #[error("Not the core note of a cluster: {0:?}")]
+    NotACoreNote(PathBuf),
+
+    #[error("Failed to list the peripheral notes of cluster {0:?}")]
+    ListFailed(PathBuf),
+
+    #[error("Failed to read note {0:?}")]
+    ReadFailed(PathBuf),
+
+    #[error("Cannot move the core note out of its cluster, {0:?} already exists")]
+    TargetExists(PathBuf),
+}
+
+/// A cluster collapsed back into one note
+#[derive(Debug, Clone)]
+pub struct UnclusterPlan {
+    /// Where the core note ends up, next to the cluster folder
+    pub note_path: PathBuf,
+
+    /// The core note with the peripheral notes folded in
+    pub content: String,
+    pub mutations: Vec<Mutation>,
+
+    /// Left in the cluster folder, which stays as a plain folder if there are any
+    pub attachment_files: Vec<PathBuf>,
+}
+
+/// A peripheral note to fold into the core note
+#[derive(Debug, Clone)]
+pub struct FoldedNote {
+    pub stem: String,
+    pub content: String,
+}
+
+fn shift_headings(body: &str, shift: isize) -> String {
+    body.lines()
+        .zip(comm::get_atx_heading_lines(body))
+        .map(|(line, opt_heading)| match opt_heading {
+            Some((level, _)) => {
+                let new_level = (level as isize + shift).clamp(1, 6) as usize;
+
+                format!("{}{}", "#".repeat(new_level), &line[level..])
+            }
+            None => line.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Pushes headings down by the given number of levels, so they nest under the section a note is folded into
+pub fn demote_headings(body: &str, levels: usize) -> String {
+    shift_headings(body, levels as isize)
+}
+
+/// Pulls headings up by the given number of levels, the opposite of `demote_headings`
+pub fn promote_headings(body: &str, levels: usize) -> String {
+    shift_headings(body, -(levels as isize))
+}
+
+/// Splits a note into its frontmatter properties other than `parent`, and the body after the frontmatter
+pub fn split_folded_note_properties(content: &str) -> (Vec<&str>, String) {
+    let lines = content.lines().collect::<Vec<_>>();
+
+    match (lines.first() == Some(&"---"))
+        .then(|| lines.iter().skip(1).position(|line| *line == "---"))
+        .flatten()
+    {
+        Some(pos) => (
+            lines[1..pos + 1]
+                .iter()
+                .filter(|line| {
+                    line.split_once(':')
+                        .is_none_or(|(key, _)| key.trim() != "parent")
+                })
+                .copied()
+                .collect(),
+            lines[pos + 2..].join("\n"),
+        ),
+        None => (vec![], content.to_owned()),
+    }
+}
+
+/// The section of an old format record for a peripheral note, with a heading of the given level. Properties other
+/// than `parent` are kept as lines, since a section cannot have frontmatter.
+pub fn render_folded_note_section(folded_note: &FoldedNote, level: usize) -> String {
+    let (properties, body) = split_folded_note_properties(&folded_note.content);
+
+    [
+        format!("{} {}", "#".repeat(level), folded_note.stem),
+        properties.join("\n"),
+        demote_headings(body.trim(), level),
+    ]
+    .into_iter()
+    .filter(|part| !part.is_empty())
+    .collect::<Vec<_>>()
+    .join("\n\n")
+}
+
+/// Points links to folded notes at their sections in the core note, whether they name a note by its folders or
+/// with its markdown extension. Block identifiers move along with the content, and spawned markers point back at
+/// the core note, as they did before extraction.
+pub fn redirect_links_to_folded_notes(
+    vault: &ObsidianVaultPath,
+    content: &str,
+    core_note_stem: &str,
+    folded_note_paths: &[PathBuf],
+) -> String {
+    let folded_note_stems = folded_note_paths
+        .iter()
+        .flat_map(|path| path.file_stem())
+        .map(|stem| stem.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+
+    // Links naming a folded note by its folders are written by its name first
+    let by_name_moves = folded_note_paths
+        .iter()
+        .zip(folded_note_stems.iter())
+        .map(|(path, stem)| (path.clone(), vault.path.join(format!("{stem}.md"))))
+        .collect::<Vec<_>>();
+
+    let content = cluster_move::redirect_folder_links(vault, content, &by_name_moves);
+
+    let trailing_newline = if content.ends_with('\n') { "\n" } else { "" };
+
+    let lines = content
+        .lines()
+        .map(|line| {
+            folded_note_stems
+                .iter()
+                .cartesian_product(["", ".md"])
+                .fold(line.to_owned(), |line, (stem, extension)| {
+                    let line = match line.contains("From [[#^spawn") {
+                        true => line.replace(
+                            &format!("in [[{stem}{extension}]]"),
+                            &format!("in [[{core_note_stem}]]"),
+                        ),
+                        false => line,
+                    };
+
+                    line.replace(
+                        &format!("[[{stem}{extension}]]"),
+                        &format!("[[{core_note_stem}#{stem}]]"),
+                    )
+                    .replace(
+                        &format!("[[{stem}{extension}|"),
+                        &format!("[[{core_note_stem}#{stem}|"),
+                    )
+                })
+        })
+        .collect::<Vec<_>>();
+
+    // What is left are sublinks, which now name the core note
+    folded_note_stems.iter().fold(
+        format!("{}{trailing_newline}", lines.join("\n")),
+        |content, stem| mutation::rename_note_links(&content, stem, core_note_stem),
+    )
+}
+
+/// Puts folded notes back in place of their `Spawn [[note]] ^spawn-...` markers in the core note, under the heading
+/// the marker is in, and drops their `From` line. Returns the content and the notes that had no marker.
+fn replace_spawn_markers_with_folded_notes(
+    content: &str,
+    core_note_stem: &str,
+    folded_notes: &[FoldedNote],
+    layout: &OldFormatLayout,
+) -> (String, Vec<FoldedNote>) {
+    folded_notes.iter().fold(
+        (content.to_owned(), vec![]),
+        |(content, mut mut_unplaced), folded_note| {
+            let block_identifiers = cluster_move::get_spawned_from_block_identifiers(
+                &folded_note.content,
+                core_note_stem,
+            );
+
+            let lines = content.lines().collect::<Vec<_>>();
+
+            let opt_marker = lines.iter().enumerate().find_map(|(i, line)| {
+                let block_identifier =
+                    cluster_move::BLOCK_IDENTIFIER_PATTERN.captures(line)?[1].to_owned();
+
+                (line.trim_start().starts_with("Spawn ")
+                    && line.contains(&format!("[[{}", folded_note.stem))
+                    && block_identifiers.contains(&block_identifier))
+                .then_some((i, block_identifier))
+            });
+
+            let Some((marker_index, block_identifier)) = opt_marker else {
+                mut_unplaced.push(folded_note.clone());
+                return (content, mut_unplaced);
+            };
+
+            // The section goes one level under the heading the marker is in
+            let level = comm::get_atx_heading_lines(&content)[..marker_index]
+                .iter()
+                .rev()
+                .find_map(|opt_heading| opt_heading.map(|(level, _)| (level + 1).min(6)))
+                .unwrap_or(layout.entry_level as usize);
+
+            let spawned_from = format!("From [[#{block_identifier}]] in [[{core_note_stem}]]");
+
+            let section = render_folded_note_section(
+                &FoldedNote {
+                    stem: folded_note.stem.clone(),
+                    content: folded_note
+                        .content
+                        .lines()
+                        .filter(|line| line.trim() != spawned_from)
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                },
+                level,
+            );
+
+            let content = [
+                lines[..marker_index].join("\n").trim_end().to_owned(),
+                section,
+                lines[marker_index + 1..].join("\n").trim_start().to_owned(),
+            ]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+            (content, mut_unplaced)
+        },
+    )
+}
+
+/// The core note without its index, with the folded notes in place of their spawn markers, and the others in a
+/// category section per context type at the levels of the layout. Context types whose heading is not one of the old
+/// format headings are folded too, but are not extracted again.
+pub fn render_unclustered_note(
+    core_note_content: &str,
+    core_note_stem: &str,
+    folded_notes_per_context_type: &[(&ContextType, Vec<FoldedNote>)],
+    layout: &OldFormatLayout,
+) -> String {
+    let without_index = cluster_note::replace_core_note_index(core_note_content, "")
+        .unwrap_or_else(|| core_note_content.to_owned());
+
+    let category_level = layout.category_level as usize;
+    let entry_level = layout.entry_level as usize;
+
+    let (content, unplaced_notes_per_context_type) = folded_notes_per_context_type.iter().fold(
+        (without_index.trim().to_owned(), vec![]),
+        |(content, mut mut_unplaced_notes_per_context_type), (context_type, folded_notes)| {
+            let (content, unplaced_notes) = replace_spawn_markers_with_folded_notes(
+                &content,
+                core_note_stem,
+                folded_notes,
+                layout,
+            );
+
+            mut_unplaced_notes_per_context_type.push((*context_type, unplaced_notes));
+
+            (content, mut_unplaced_notes_per_context_type)
+        },
+    );
+
+    // Records that were never extracted keep their category section, and the folded notes join them
+    let content = unplaced_notes_per_context_type
+        .iter()
+        .filter(|(_, folded_notes)| !folded_notes.is_empty())
+        .fold(content, |content, (context_type, folded_notes)| {
+            let heading = format!(
+                "{} {}",
+                "#".repeat(category_level),
+                context_type.heading_plural
+            );
+
+            let sections = folded_notes
+                .iter()
+                .map(|folded_note| render_folded_note_section(folded_note, entry_level))
+                .collect::<Vec<_>>()
+                .join("\n\n");
+
+            let lines = content.lines().collect::<Vec<_>>();
+
+            // Lines in fenced code only look like headings
+            let heading_lines = comm::get_atx_heading_lines(&content);
+
+            match heading_lines.iter().position(|opt_heading| {
+                *opt_heading == Some((category_level, context_type.heading_plural.as_str()))
+            }) {
+                Some(pos) => {
+                    let end = heading_lines
+                        .iter()
+                        .skip(pos + 1)
+                        .position(|opt_heading| {
+                            opt_heading.is_some_and(|(level, _)| level <= category_level)
+                        })
+                        .map_or(lines.len(), |offset| pos + 1 + offset);
+
+                    [
+                        lines[..end].join("\n").trim_end().to_owned(),
+                        sections,
+                        lines[end..].join("\n"),
+                    ]
+                    .into_iter()
+                    .filter(|part| !part.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+                }
+                None if content.is_empty() => format!("{heading}\n\n{sections}"),
+                None => format!("{content}\n\n{heading}\n\n{sections}"),
+            }
+        });
+
+    // Removing the index can leave a run of blank lines behind
+    let content = {
+        let mut mut_content = content;
+
+        while mut_content.contains("\n\n\n") {
+            mut_content = mut_content.replace("\n\n\n", "\n\n");
+        }
+
+        mut_content
+    };
+
+    format!("{content}\n")
+}
+
+fn get_folded_notes_per_context_type<'a>(
+    config: &'a VaultConfig,
+    category_folders_with_peripheral_files: &cluster_note::CategoryFoldersWithPeripheralFiles,
+) -> Result<Vec<(&'a ContextType, Vec<FoldedNote>)>, UnclusterError> {
+    config
+        .context_types
+        .iter()
+        .map(|context_type| {
+            let mut mut_folded_notes = category_folders_with_peripheral_files
+                .iter()
+                .filter(|(category_folder, _)| category_folder.path.ends_with(&context_type.folder))
+                .flat_map(|(_, files)| files)
+                .map(|file| {
+                    let content = comm::read_file_content(&file.path)
+                        .ok_or(UnclusterError::ReadFailed(file.path.clone()))?;
+
+                    Ok(FoldedNote {
+                        stem: file
+                            .path
+                            .file_stem()
+                            .map(|stem| stem.to_string_lossy().to_string())
+                            .unwrap_or_default(),
+                        content,
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            mut_folded_notes.sort_by(|a, b| a.stem.cmp(&b.stem));
+
+            Ok((context_type, mut_folded_notes))
+        })
+        .collect()
+}
+
+/// Plans folding a cluster back into its core note, the way notes were before extraction. The core note moves
+/// next to its cluster folder, links to the peripheral notes are redirected to its sections, and folders left
+/// empty are removed.
+pub fn plan_uncluster(
+    vault: &ObsidianVaultPath,
+    core_note_path: &Path,
+) -> Result<UnclusterPlan, UnclusterError> {
+    let index = VaultIndex::build_for_vault(vault)
+        .map_err(|e| UnclusterError::IndexFailed(vault.path.clone(), e.to_string()))?;
+
+    let core_note = CoreNoteFilePath::new_in_index(&index, &vault.resolve_path(core_note_path))
+        .ok_or(UnclusterError::NotACoreNote(core_note_path.to_path_buf()))?;
+
+    let cluster_root_folder = core_note
+        .path
+        .parent()
+        .and_then(|parent| ClusterRootFolderPath::new_in_index(&index, parent))
+        .ok_or(UnclusterError::NotACoreNote(core_note.path.clone()))?;
+
+    let (category_folders_with_peripheral_files, _diagnostics) = index
+        .get_category_folders_with_peripheral_files_from_cluster_root_folder(&cluster_root_folder)
+        .ok_or(UnclusterError::ListFailed(cluster_root_folder.path.clone()))?;
+
+    let note_path = match (
+        cluster_root_folder.path.parent(),
+        core_note.path.file_name(),
+    ) {
+        (Some(parent), Some(file_name)) => parent.join(file_name),
+        _ => return Err(UnclusterError::NotACoreNote(core_note.path.clone())),
+    };
+
+    if note_path.exists() {
+        return Err(UnclusterError::TargetExists(note_path));
+    }
+
+    let core_note_content = comm::read_file_content(&core_note.path)
+        .ok_or(UnclusterError::ReadFailed(core_note.path.clone()))?;
+
+    let core_note_stem = core_note
+        .path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let folded_notes_per_context_type =
+        get_folded_notes_per_context_type(&index.config, &category_folders_with_peripheral_files)?;
+
+    let peripheral_files = category_folders_with_peripheral_files
+        .iter()
+        .flat_map(|(_, files)| files)
+        .collect::<Vec<_>>();
+
+    let folded_note_paths = peripheral_files
+        .iter()
+        .map(|file| file.path.clone())
+        .collect::<Vec<_>>();
+
+    // Links naming the core note by its folders follow it out of the cluster folder
+    let core_note_moves = [(core_note.path.clone(), note_path.clone())];
+
+    let redirect_links = |content: &str| {
+        redirect_links_to_folded_notes(
+            vault,
+            &cluster_move::redirect_folder_links(vault, content, &core_note_moves),
+            &core_note_stem,
+            &folded_note_paths,
+        )
+    };
+
+    let layout = cluster_note::detect_old_format_layout(
+        &comm::parse_markdown_file(&core_note_content),
+        &index.config,
+    );
+
+    let content = redirect_links(&render_unclustered_note(
+        &core_note_content,
+        &core_note_stem,
+        &folded_notes_per_context_type,
+        &layout,
+    ));
+
+    let link_rewrites = drivers::get_markdown_file_paths_in_vault(vault, true)
+        .into_iter()
+        .filter(|path| {
+            *path != core_note.path && peripheral_files.iter().all(|file| file.path != *path)
+        })
+        .flat_map(|path| {
+            let old_content = comm::read_file_content(&path)?;
+
+            let new_content = redirect_links(&old_content);
+
+            (new_content != old_content).then_some(Mutation::WriteFile {
+                path,
+                opt_old_content: Some(old_content),
+                new_content,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let peripheral_removals = peripheral_files
+        .iter()
+        .map(|file| {
+            let content = comm::read_file_content(&file.path)
+                .ok_or(UnclusterError::ReadFailed(file.path.clone()))?;
+
+            Ok(Mutation::RemoveFile {
+                path: file.path.clone(),
+                content,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Category folders holding anything besides peripheral notes stay, with the cluster folder around them
+    let emptied_category_folders = category_folders_with_peripheral_files
+        .iter()
+        .filter(|(category_folder, files)| {
+            index
+                .get_listing(&category_folder.path)
+                .is_some_and(|listing| {
+                    listing.dirs.is_empty()
+                        && listing.symlinks.is_empty()
+                        && listing.files.len() == files.len()
+                })
+        })
+        .map(|(category_folder, _)| category_folder.path.clone())
+        .collect::<Vec<_>>();
+
+    let attachment_files = index
+        .dirs
+        .range(cluster_root_folder.path.clone()..)
+        .take_while(|(folder, _)| folder.starts_with(&cluster_root_folder.path))
+        .flat_map(|(folder, indexed_dir)| {
+            indexed_dir
+                .included
+                .files
+                .iter()
+                .map(|file_name| folder.join(file_name))
+                .collect::<Vec<_>>()
+        })
+        .filter(|path| {
+            *path != core_note.path && peripheral_files.iter().all(|file| file.path != *path)
+        })
+        .collect::<Vec<_>>();
+
+    let cluster_root_folder_is_emptied =
+        index
+            .get_listing(&cluster_root_folder.path)
+            .is_some_and(|listing| {
+                listing.files.len() == 1
+                    && listing.symlinks.is_empty()
+                    && listing.dirs.iter().all(|dir_name| {
+                        emptied_category_folders.contains(&cluster_root_folder.path.join(dir_name))
+                    })
+            });
+
+    let mutations = {
+        let mut mut_mutations = link_rewrites;
+
+        mut_mutations.push(Mutation::WriteFile {
+            path: core_note.path.clone(),
+            opt_old_content: Some(core_note_content),
+            new_content: content.clone(),
+        });
+        mut_mutations.extend(peripheral_removals);
+        mut_mutations.extend(
+            emptied_category_folders
+                .into_iter()
+                .map(|path| Mutation::RemoveDir { path }),
+        );
+        mut_mutations.push(Mutation::Rename {
+            from: core_note.path.clone(),
+            to: note_path.clone(),
+        });
+
+        if cluster_root_folder_is_emptied {
+            mut_mutations.push(Mutation::RemoveDir {
+                path: cluster_root_folder.path.clone(),
+            });
+        }
+
+        mut_mutations
+    };
+
+    Ok(UnclusterPlan {
+        note_path,
+        content,
+        mutations,
+        attachment_files,
+    })
+}
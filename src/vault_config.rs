@@ -0,0 +1,226 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use thiserror::Error;
+
+use crate::common::{self as comm, TOOL_FOLDER_NAME};
+
+pub const VAULT_CONFIG_FILE_NAME: &str = "config.ron";
+
+/// A kind of peripheral note, which gets its own folder in clusters
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextType {
+    pub folder: String,
+
+    /// Used in block identifiers like `^spawn-{block_code}-0a1b2c`
+    pub block_code: String,
+    pub heading_singular: String,
+    pub heading_plural: String,
+
+    /// Doers track work to be done, like tasks and issues, rather than records of what happened
+    pub is_doer: bool,
+}
+
+impl ContextType {
+    fn new(
+        folder: &str,
+        block_code: &str,
+        heading_singular: &str,
+        heading_plural: &str,
+        is_doer: bool,
+    ) -> Self {
+        Self {
+            folder: folder.to_owned(),
+            block_code: block_code.to_owned(),
+            heading_singular: heading_singular.to_owned(),
+            heading_plural: heading_plural.to_owned(),
+            is_doer,
+        }
+    }
+}
+
+/// Which peripheral notes share a sequence of `NNN ` name prefixes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NumberingScope {
+    /// Each context type folder of each cluster counts on its own
+    #[default]
+    CategoryFolder,
+
+    /// Notes of the same context type count together across the clusters in a year folder, like `2025`
+    Year,
+}
+
+#[derive(Error, Debug)]
+pub enum NumberingScopeFromStrError {
+    #[error("Invalid numbering scope provided: {0:?}")]
+    InvalidScope(String),
+}
+
+impl FromStr for NumberingScope {
+    type Err = NumberingScopeFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "folder" => Ok(Self::CategoryFolder),
+            "year" => Ok(Self::Year),
+            _ => Err(NumberingScopeFromStrError::InvalidScope(s.to_string())),
+        }
+    }
+}
+
+/// Per vault settings, read from `.migration_rs/config.ron`. Vaults without one use the defaults.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VaultConfig {
+    pub context_types: Vec<ContextType>,
+
+    /// Category headings whose entry sections are records of the old format, like `# Tasks` or `## Tasks` under a
+    /// title. Each is the plural heading of the context type its entries are extracted to.
+    pub old_format_headings: Vec<String>,
+
+    pub numbering: NumberingScope,
+
+    /// `status` values of doer notes that are no longer open
+    pub closed_statuses: Vec<String>,
+
+    /// Vault folder with the templates of new notes, `cluster.md` for core notes and `{folder}.md` or
+    /// `peripheral.md` for peripheral notes
+    pub templates_folder: String,
+
+    /// Vault path of the Timeline note that new peripheral notes and status changes are logged to, if it exists
+    pub timeline_note: String,
+}
+
+impl Default for VaultConfig {
+    fn default() -> Self {
+        Self {
+            context_types: vec![
+                ContextType::new("entries", "entry", "Entry", "Entries", false),
+                ContextType::new("howtos", "howto", "HowTo", "HowTos", true),
+                ContextType::new("ideas", "idea", "Idea", "Ideas", false),
+                ContextType::new("inferences", "infer", "Inference", "Inferences", false),
+                ContextType::new(
+                    "investigations",
+                    "invst",
+                    "Investigation",
+                    "Investigations",
+                    true,
+                ),
+                ContextType::new("issues", "issue", "Issue", "Issues", true),
+                ContextType::new("tasks", "task", "Task", "Tasks", true),
+            ],
+            old_format_headings: ["Tasks", "Issues", "HowTos", "Investigations", "Ideas"]
+                .map(str::to_owned)
+                .to_vec(),
+            numbering: NumberingScope::default(),
+            closed_statuses: ["done", "closed", "cancelled", "resolved"]
+                .map(str::to_owned)
+                .to_vec(),
+            templates_folder: "templater".to_owned(),
+            timeline_note: "Timeline.md".to_owned(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum LoadVaultConfigError {
+    #[error("Failed to parse vault config {0:?}: {1}")]
+    Parse(PathBuf, ron::error::SpannedError),
+
+    #[error("Vault config {0:?} has no context types")]
+    NoContextTypes(PathBuf),
+
+    #[error("Vault config {0:?} uses {1:?} for more than one context type")]
+    DuplicateContextType(PathBuf, String),
+}
+
+impl VaultConfig {
+    pub fn path_in_vault(vault_root: &Path) -> PathBuf {
+        vault_root
+            .join(TOOL_FOLDER_NAME)
+            .join(VAULT_CONFIG_FILE_NAME)
+    }
+
+    pub fn load(vault_root: &Path) -> Result<Self, LoadVaultConfigError> {
+        let config_path = Self::path_in_vault(vault_root);
+
+        let Some(content) = comm::read_file_content(&config_path) else {
+            return Ok(Self::default());
+        };
+
+        let config = ron::from_str::<Self>(&content)
+            .map_err(|e| LoadVaultConfigError::Parse(config_path.clone(), e))?;
+
+        if config.context_types.is_empty() {
+            return Err(LoadVaultConfigError::NoContextTypes(config_path));
+        }
+
+        // Folders and block codes identify context types, so they can't be shared
+        for names in [
+            config
+                .context_types
+                .iter()
+                .map(|ct| &ct.folder)
+                .collect::<Vec<_>>(),
+            config
+                .context_types
+                .iter()
+                .map(|ct| &ct.block_code)
+                .collect::<Vec<_>>(),
+        ] {
+            let mut mut_seen = BTreeSet::new();
+
+            if let Some(name) = names.into_iter().find(|name| !mut_seen.insert(*name)) {
+                return Err(LoadVaultConfigError::DuplicateContextType(
+                    config_path,
+                    name.clone(),
+                ));
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn get_context_type_by_folder(&self, folder_name: &str) -> Option<&ContextType> {
+        self.context_types
+            .iter()
+            .find(|context_type| context_type.folder == folder_name)
+    }
+
+    /// The context type of an old format category heading, like `Tasks`
+    pub fn get_context_type_by_heading(&self, heading: &str) -> Option<&ContextType> {
+        self.context_types
+            .iter()
+            .find(|context_type| context_type.heading_plural == heading)
+    }
+
+    /// Context types named another way, like `Tasks`, `Task` or `task`
+    pub fn find_context_type(&self, name: &str) -> Option<&ContextType> {
+        let name = name.to_lowercase();
+
+        self.context_types.iter().find(|context_type| {
+            [
+                &context_type.folder,
+                &context_type.heading_singular,
+                &context_type.heading_plural,
+                &context_type.block_code,
+            ]
+            .iter()
+            .any(|context_type_name| context_type_name.to_lowercase() == name)
+        })
+    }
+
+    pub fn is_context_type_folder_name(&self, folder_name: &str) -> bool {
+        self.get_context_type_by_folder(folder_name).is_some()
+    }
+
+    pub fn get_context_type_folder_names(&self) -> Vec<&str> {
+        self.context_types
+            .iter()
+            .map(|context_type| context_type.folder.as_str())
+            .collect()
+    }
+}
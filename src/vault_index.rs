@@ -0,0 +1,761 @@
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::SystemTime,
+};
+use thiserror::Error;
+
+use crate::{
+    cluster_note::{self, *},
+    common::{self as comm, DirListing, GetAndCategorizeDirEntriesError, ObsidianVaultPath},
+    diagnostics::{ClusterRuleViolation, VaultDiagnostic},
+    exclusion::{self, ExclusionRule, ExclusionRules, LoadExclusionRulesError},
+    vault_config::{LoadVaultConfigError, VaultConfig},
+};
+
+pub const VAULT_INDEX_CACHE_FILE_NAME: &str = "vault_index.ron";
+
+/// What to do with symlinks found while walking the vault
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    #[default]
+    Skip,
+    Follow,
+
+    /// Only follow symlinks whose target is inside the vault
+    FollowInsideVault,
+}
+
+#[derive(Error, Debug)]
+pub enum SymlinkPolicyFromStrError {
+    #[error("Invalid symlink policy provided: {0:?}")]
+    InvalidPolicy(String),
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = SymlinkPolicyFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "follow" => Ok(Self::Follow),
+            "follow-inside-vault" => Ok(Self::FollowInsideVault),
+            _ => Err(SymlinkPolicyFromStrError::InvalidPolicy(s.to_string())),
+        }
+    }
+}
+
+/// Why a symlink was not followed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkippedSymlinkReason {
+    Policy,
+    OutsideVault(PathBuf),
+    Cycle(PathBuf),
+    Broken,
+}
+
+impl fmt::Display for SkippedSymlinkReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Policy => write!(f, "symlink not followed"),
+            Self::OutsideVault(target) => write!(f, "symlink to {target:?} outside the vault"),
+            Self::Cycle(target) => write!(f, "symlink to {target:?} forms a cycle"),
+            Self::Broken => write!(f, "broken symlink"),
+        }
+    }
+}
+
+enum ResolvedSymlink {
+    Dir(PathBuf),
+    File,
+    Skipped(SkippedSymlinkReason),
+}
+
+/// Followed symlinks keep their own path in the index, since that is the path obsidian shows and links resolve to.
+/// `canonical_ancestors` are the real folders from the root down to the one holding the symlink.
+fn resolve_symlink(
+    path: &Path,
+    policy: SymlinkPolicy,
+    canonical_ancestors: &[PathBuf],
+) -> ResolvedSymlink {
+    if policy == SymlinkPolicy::Skip {
+        return ResolvedSymlink::Skipped(SkippedSymlinkReason::Policy);
+    }
+
+    let Ok(target) = fs::canonicalize(path) else {
+        return ResolvedSymlink::Skipped(SkippedSymlinkReason::Broken);
+    };
+
+    if policy == SymlinkPolicy::FollowInsideVault
+        && let Some(canonical_root) = canonical_ancestors.first()
+        && !target.starts_with(canonical_root)
+    {
+        return ResolvedSymlink::Skipped(SkippedSymlinkReason::OutsideVault(target));
+    }
+
+    if target.is_file() {
+        return ResolvedSymlink::File;
+    }
+
+    if canonical_ancestors.contains(&target) {
+        return ResolvedSymlink::Skipped(SkippedSymlinkReason::Cycle(target));
+    }
+
+    ResolvedSymlink::Dir(target)
+}
+
+/// Options for how the vault is walked to find working items
+#[derive(Debug, Clone, Default)]
+pub struct TraversalOptions {
+    /// Keep the vault index in the tool folder of the vault, and only list directories whose mtime changed since
+    pub cache_index: bool,
+
+    /// Walk paths excluded by obsidian settings, gitignore files and the tool ignore file. Hidden paths are
+    /// still left out.
+    pub no_ignore: bool,
+
+    pub symlink_policy: SymlinkPolicy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedDir {
+    pub mtime: Option<SystemTime>,
+
+    /// Everything in the directory, as on disk
+    pub listing: DirListing,
+
+    /// What is left of the listing after exclusions, with followed symlinks as files and dirs. Rules can change
+    /// without the directory changing, so this is worked out on every build.
+    #[serde(skip)]
+    pub included: DirListing,
+}
+
+/// Listings of every directory under a root, built in one walk. This answers the classification questions
+/// of `cluster_note` without going back to the file system.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultIndex {
+    pub root: PathBuf,
+    pub dirs: BTreeMap<PathBuf, IndexedDir>,
+
+    /// Paths left out of the walk, with the rule that excluded them
+    #[serde(skip)]
+    pub excluded: BTreeMap<PathBuf, ExclusionRule>,
+
+    #[serde(skip)]
+    pub skipped_symlinks: BTreeMap<PathBuf, SkippedSymlinkReason>,
+
+    /// Folders that vanished or could not be read, with why. They are left out with everything under them.
+    #[serde(skip)]
+    pub unreadable_dirs: BTreeMap<PathBuf, String>,
+
+    /// The config of the vault, which decides what the context type folders are
+    #[serde(skip)]
+    pub config: Arc<VaultConfig>,
+}
+
+#[derive(Error, Debug)]
+pub enum BuildVaultIndexError {
+    #[error("Failed to list directory {0:?}: {1}")]
+    ListFailed(PathBuf, GetAndCategorizeDirEntriesError),
+
+    #[error("Failed to load exclusion rules: {0}")]
+    ExclusionRules(#[from] LoadExclusionRulesError),
+
+    #[error("Failed to load the vault config: {0}")]
+    Config(#[from] LoadVaultConfigError),
+
+    #[error("Failed io operation: {0:?}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum SaveVaultIndexCacheError {
+    #[error("Failed to serialize vault index: {0:?}")]
+    Serialize(#[from] ron::Error),
+
+    #[error("Failed io operation: {0:?}")]
+    Io(#[from] std::io::Error),
+}
+
+impl VaultIndex {
+    pub fn build(root: &Path) -> Result<Self, BuildVaultIndexError> {
+        Self::build_reusing(root, None)
+    }
+
+    /// Builds the index, reusing the listing of any directory from `opt_cached` whose mtime did not change.
+    /// Directory mtimes change when entries are added, removed or renamed, which is all the listings hold.
+    pub fn build_reusing(
+        root: &Path,
+        opt_cached: Option<&VaultIndex>,
+    ) -> Result<Self, BuildVaultIndexError> {
+        Self::build_with_rules(
+            root,
+            opt_cached,
+            ExclusionRules::load(root)?,
+            Arc::new(VaultConfig::load(root)?),
+            SymlinkPolicy::default(),
+        )
+    }
+
+    pub fn build_with_rules(
+        root: &Path,
+        opt_cached: Option<&VaultIndex>,
+        rules: ExclusionRules,
+        config: Arc<VaultConfig>,
+        symlink_policy: SymlinkPolicy,
+    ) -> Result<Self, BuildVaultIndexError> {
+        let opt_cached = opt_cached.filter(|cached| cached.root == root);
+
+        let mut mut_rules = rules;
+        let mut mut_excluded = BTreeMap::new();
+        let mut mut_skipped_symlinks = BTreeMap::new();
+        let mut mut_unreadable_dirs = BTreeMap::new();
+
+        let dirs = {
+            let mut mut_dirs = BTreeMap::new();
+            let mut mut_pending = vec![(root.to_path_buf(), vec![fs::canonicalize(root)?])];
+
+            while let Some((dir, canonical_ancestors)) = mut_pending.pop() {
+                let list_dir = || {
+                    let mtime = fs::metadata(&dir)?.modified().ok();
+
+                    let opt_cached_listing = opt_cached
+                        .and_then(|cached| cached.dirs.get(&dir))
+                        .filter(|cached_dir| mtime.is_some() && cached_dir.mtime == mtime)
+                        .map(|cached_dir| cached_dir.listing.clone());
+
+                    let listing = match opt_cached_listing {
+                        Some(listing) => listing,
+                        None => comm::get_dir_listing(&dir)
+                            .map_err(|e| BuildVaultIndexError::ListFailed(dir.clone(), e))?,
+                    };
+
+                    Ok::<_, BuildVaultIndexError>((mtime, listing))
+                };
+
+                // Only the root failing stops the build
+                let (mtime, listing) = match list_dir() {
+                    Ok(listed) => listed,
+                    Err(e) if dir == root => return Err(e),
+                    Err(e) => {
+                        log::warn!("Leaving out unreadable folder {dir:?}: {e}");
+                        mut_unreadable_dirs.insert(dir, e.to_string());
+                        continue;
+                    }
+                };
+
+                if listing
+                    .files
+                    .iter()
+                    .any(|name| name == exclusion::GITIGNORE_FILE_NAME)
+                {
+                    mut_rules.load_gitignore(&dir);
+                }
+
+                let mut mut_canonical_dirs = BTreeMap::new();
+
+                let (files, dirs, symlinks) = {
+                    let mut mut_files = listing.files.clone();
+                    let mut mut_dirs = listing.dirs.clone();
+                    let mut mut_symlinks = vec![];
+
+                    for name in listing.symlinks.iter() {
+                        let path = dir.join(name);
+
+                        match resolve_symlink(&path, symlink_policy, &canonical_ancestors) {
+                            ResolvedSymlink::Dir(target) => {
+                                mut_canonical_dirs.insert(name.clone(), target);
+                                mut_dirs.push(name.clone());
+                            }
+                            ResolvedSymlink::File => mut_files.push(name.clone()),
+                            ResolvedSymlink::Skipped(reason) => {
+                                if matches!(reason, SkippedSymlinkReason::Cycle(_)) {
+                                    log::warn!("Not following {path:?}: {reason}");
+                                }
+
+                                mut_skipped_symlinks.insert(path, reason);
+                                mut_symlinks.push(name.clone());
+                            }
+                        }
+                    }
+
+                    mut_files.sort();
+                    mut_dirs.sort();
+
+                    (mut_files, mut_dirs, mut_symlinks)
+                };
+
+                let included = {
+                    let mut mut_keep = |names: Vec<PathBuf>, is_dir: bool| {
+                        names
+                            .into_iter()
+                            .filter(|name| {
+                                let path = dir.join(name);
+
+                                match mut_rules.check_entry(&path, is_dir) {
+                                    Some(rule) => {
+                                        mut_excluded.insert(path, rule);
+                                        false
+                                    }
+                                    None => true,
+                                }
+                            })
+                            .collect::<Vec<_>>()
+                    };
+
+                    DirListing {
+                        files: mut_keep(files, false),
+                        dirs: mut_keep(dirs, true),
+                        symlinks: mut_keep(symlinks, false),
+                    }
+                };
+
+                mut_pending.extend(included.dirs.iter().map(|name| {
+                    let canonical_dir = match mut_canonical_dirs.remove(name) {
+                        Some(target) => target,
+                        None => canonical_ancestors
+                            .last()
+                            .expect("The root is always there")
+                            .join(name),
+                    };
+
+                    let mut mut_canonical_ancestors = canonical_ancestors.clone();
+                    mut_canonical_ancestors.push(canonical_dir);
+
+                    (dir.join(name), mut_canonical_ancestors)
+                }));
+
+                mut_dirs.insert(
+                    dir,
+                    IndexedDir {
+                        mtime,
+                        listing,
+                        included,
+                    },
+                );
+            }
+
+            // Folders are listed before what is in them, so the unreadable ones are dropped from their parents here
+            for unreadable_dir in mut_unreadable_dirs.keys() {
+                if let (Some(parent), Some(name)) =
+                    (unreadable_dir.parent(), unreadable_dir.file_name())
+                    && let Some(indexed_dir) = mut_dirs.get_mut(parent)
+                {
+                    indexed_dir
+                        .included
+                        .dirs
+                        .retain(|dir_name| dir_name != name);
+                }
+            }
+
+            mut_dirs
+        };
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            dirs,
+            excluded: mut_excluded,
+            skipped_symlinks: mut_skipped_symlinks,
+            unreadable_dirs: mut_unreadable_dirs,
+            config,
+        })
+    }
+
+    /// Builds the index of the vault, going through the on-disk cache if the vault is configured for it.
+    pub fn build_for_vault(vault: &ObsidianVaultPath) -> Result<Self, BuildVaultIndexError> {
+        let rules = ExclusionRules::for_vault(vault)?;
+
+        if !vault.traversal_options.cache_index {
+            return Self::build_with_rules(
+                &vault.path,
+                None,
+                rules,
+                vault.config.clone(),
+                vault.traversal_options.symlink_policy,
+            );
+        }
+
+        let cache_path = vault.tool_folder().join(VAULT_INDEX_CACHE_FILE_NAME);
+
+        let opt_cached = Self::load_cache(&cache_path);
+
+        let index = Self::build_with_rules(
+            &vault.path,
+            opt_cached.as_ref(),
+            rules,
+            vault.config.clone(),
+            vault.traversal_options.symlink_policy,
+        )?;
+
+        if let Err(e) = index.save_cache(&cache_path) {
+            log::warn!("Failed to save vault index cache to {cache_path:?}: {e}");
+        }
+
+        Ok(index)
+    }
+
+    pub fn load_cache(cache_path: &Path) -> Option<Self> {
+        let content = comm::read_file_content(cache_path)?;
+
+        ron::from_str(&content)
+            .map_err(|e| log::warn!("Ignoring unreadable vault index cache {cache_path:?}: {e}"))
+            .ok()
+    }
+
+    pub fn save_cache(&self, cache_path: &Path) -> Result<(), SaveVaultIndexCacheError> {
+        let content = ron::to_string(self)?;
+
+        if let Some(parent) = cache_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        comm::write_file_content(&content, cache_path)?;
+
+        Ok(())
+    }
+
+    pub fn get_listing(&self, folder: &Path) -> Option<&DirListing> {
+        self.dirs
+            .get(folder)
+            .map(|indexed_dir| &indexed_dir.included)
+    }
+
+    /// Everything in the folder as on disk, including what exclusions leave out
+    pub fn get_raw_listing(&self, folder: &Path) -> Option<&DirListing> {
+        self.dirs
+            .get(folder)
+            .map(|indexed_dir| &indexed_dir.listing)
+    }
+
+    pub fn is_dir(&self, path: &Path) -> bool {
+        self.dirs.contains_key(path)
+    }
+
+    pub fn is_file(&self, path: &Path) -> bool {
+        let inner_fn = || {
+            let listing = self.get_listing(path.parent()?)?;
+
+            let file_name = path.file_name()?;
+
+            Some(listing.files.iter().any(|name| name == file_name))
+        };
+
+        inner_fn().unwrap_or_default()
+    }
+
+    pub fn get_folder_child_file_count_non_recursive(&self, folder: &Path) -> Option<usize> {
+        Some(self.get_listing(folder)?.file_count())
+    }
+
+    pub fn folder_has_file_of_same_name(&self, folder: &Path) -> Option<bool> {
+        Some(
+            self.get_listing(folder)?
+                .has_file_of_same_name(folder.file_name()?),
+        )
+    }
+
+    pub fn is_cluster_root_folder(&self, folder: &Path) -> Option<bool> {
+        cluster_note::is_cluster_root_folder_listing(folder, self.get_listing(folder)?)
+    }
+
+    pub fn is_cluster_category_folder(&self, folder: &Path) -> Option<bool> {
+        let folder_name = folder.file_name()?.to_str()?;
+
+        if !self.is_dir(folder) || !self.config.is_context_type_folder_name(folder_name) {
+            return Some(false);
+        }
+
+        // The parent may be outside of the index if it is the root
+        let parent = folder.parent()?;
+
+        if !self.is_dir(parent) || !self.is_cluster_root_folder(parent)? {
+            return Some(false);
+        }
+
+        Some(true)
+    }
+
+    pub fn is_markdown_file_path(&self, path: &Path) -> bool {
+        self.is_file(path) && cluster_note::has_markdown_extension(path)
+    }
+
+    pub fn is_cluster_attachments_folder(&self, folder: &Path) -> Option<bool> {
+        if !self.is_dir(folder) || !cluster_note::is_cluster_attachments_folder_name(folder) {
+            return Some(false);
+        }
+
+        let parent = folder.parent()?;
+
+        if !self.is_dir(parent) || !self.is_cluster_root_folder(parent)? {
+            return Some(false);
+        }
+
+        Some(true)
+    }
+
+    pub fn is_cluster_core_file_path(&self, path: &Path) -> Option<bool> {
+        if !self.is_markdown_file_path(path) || cluster_note::is_attachment_file_name(path) {
+            return Some(false);
+        }
+
+        if !self.is_cluster_root_folder(path.parent()?)? {
+            return Some(false);
+        }
+
+        Some(true)
+    }
+
+    pub fn is_cluster_peripheral_file_path(&self, path: &Path) -> Option<bool> {
+        if !self.is_markdown_file_path(path) || cluster_note::is_attachment_file_name(path) {
+            return Some(false);
+        }
+
+        if !self.is_cluster_category_folder(path.parent()?)? {
+            return Some(false);
+        }
+
+        Some(true)
+    }
+
+    pub fn is_normal_markdown_file_path(&self, path: &Path) -> Option<bool> {
+        if !self.is_markdown_file_path(path) {
+            return Some(false);
+        }
+
+        if self.is_cluster_core_file_path(path)?
+            || self.is_cluster_peripheral_file_path(path)?
+            || self.is_cluster_attachment_file_path(path)?
+        {
+            return Some(false);
+        }
+
+        Some(true)
+    }
+
+    pub fn is_cluster_attachment_file_path(&self, path: &Path) -> Option<bool> {
+        if !self.is_file(path) {
+            return Some(false);
+        }
+
+        let parent = path.parent()?;
+
+        if cluster_note::is_attachment_file_name(path)
+            && (self.is_cluster_root_folder(parent)? || self.is_cluster_category_folder(parent)?)
+        {
+            return Some(true);
+        }
+
+        // Folders above the root are not indexed
+        for ancestor in parent
+            .ancestors()
+            .take_while(|ancestor| self.is_dir(ancestor))
+        {
+            if self.is_cluster_attachments_folder(ancestor)? {
+                return Some(true);
+            }
+        }
+
+        Some(false)
+    }
+
+    pub fn get_core_note_file_from_cluster_root_folder(
+        &self,
+        cluster_root_folder: &ClusterRootFolderPath,
+    ) -> Option<CoreNoteFilePath> {
+        let core_note_file_name = cluster_note::get_core_note_file_name(&cluster_root_folder.path)?;
+
+        CoreNoteFilePath::new_in_index(self, &cluster_root_folder.path.join(core_note_file_name))
+    }
+
+    /// Attachment files of a cluster, next to its notes and under its attachments folder, sorted by path
+    pub fn get_attachment_files_from_cluster_root_folder(
+        &self,
+        cluster_root_folder: &ClusterRootFolderPath,
+    ) -> Option<Vec<AttachmentFilePath>> {
+        let attachments_folder = cluster_root_folder
+            .path
+            .join(cluster_note::CLUSTER_ATTACHMENTS_FOLDER);
+
+        let attachment_files = self
+            .dirs
+            .range(cluster_root_folder.path.clone()..)
+            .take_while(|(folder, _)| folder.starts_with(&cluster_root_folder.path))
+            .filter(|(folder, _)| {
+                *folder == &cluster_root_folder.path
+                    || folder.starts_with(&attachments_folder)
+                    || self.is_cluster_category_folder(folder).unwrap_or_default()
+            })
+            .flat_map(|(folder, indexed_dir)| {
+                indexed_dir.included.files.iter().flat_map(|file_name| {
+                    AttachmentFilePath::new_in_index(self, &folder.join(file_name))
+                })
+            })
+            .sorted_by(|a, b| a.path.cmp(&b.path))
+            .collect();
+
+        Some(attachment_files)
+    }
+
+    /// The category folders of a cluster with their peripheral notes, and diagnostics for the paths breaking the
+    /// cluster rules. None only if the cluster is not indexed.
+    pub fn get_category_folders_with_peripheral_files_from_cluster_root_folder(
+        &self,
+        cluster_root_folder: &ClusterRootFolderPath,
+    ) -> Option<(CategoryFoldersWithPeripheralFiles, Vec<VaultDiagnostic>)> {
+        let cluster_listing = self.get_listing(&cluster_root_folder.path)?;
+
+        let mut mut_diagnostics = vec![];
+
+        let mut mut_diagnose = |path: PathBuf, violation| {
+            mut_diagnostics.push(VaultDiagnostic::new(
+                path,
+                cluster_root_folder.path.clone(),
+                violation,
+                &self.config,
+            ))
+        };
+
+        let category_folders_and_periphal_files = {
+            let mut mut_category_folders_and_periphal_files = vec![];
+
+            for dir_name in cluster_listing.dirs.iter() {
+                let dir_path = cluster_root_folder.path.join(dir_name);
+
+                if cluster_note::is_cluster_attachments_folder_name(&dir_path) {
+                    continue;
+                }
+
+                let Some(category_folder_path) =
+                    ClusterCategoryFolderPath::new_in_index(self, &dir_path)
+                else {
+                    mut_diagnose(dir_path, ClusterRuleViolation::UnknownCategoryFolder);
+                    continue;
+                };
+
+                let category_listing = self.get_listing(&category_folder_path.path)?;
+
+                // A category folder just has flat files in it. There shouldn't be anything else
+                for name in category_listing.dirs.iter() {
+                    mut_diagnose(
+                        category_folder_path.path.join(name),
+                        ClusterRuleViolation::FolderInCategoryFolder,
+                    );
+                }
+
+                for name in category_listing.symlinks.iter() {
+                    mut_diagnose(
+                        category_folder_path.path.join(name),
+                        ClusterRuleViolation::SymlinkInCategoryFolder,
+                    );
+                }
+
+                // Anything that is not a peripheral note is an attachment
+                let peripheral_note_files = category_listing
+                    .files
+                    .iter()
+                    .flat_map(|file_name| {
+                        PeripheralNoteFilePath::new_in_index(
+                            self,
+                            &category_folder_path.path.join(file_name),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                mut_category_folders_and_periphal_files
+                    .push((category_folder_path, peripheral_note_files));
+            }
+
+            mut_category_folders_and_periphal_files
+        };
+
+        Some((category_folders_and_periphal_files, mut_diagnostics))
+    }
+
+    pub fn get_working_item_paths(&self, folder: &Path) -> Option<Vec<WorkingPath>> {
+        Some(self.get_working_items(folder)?.items)
+    }
+
+    /// Working items under the folder, with diagnostics for the paths breaking the cluster rules and the folders
+    /// that could not be read
+    pub fn get_working_items(&self, folder: &Path) -> Option<WorkingItems> {
+        let working_items = {
+            let mut mut_working_items = self.get_working_items_recursive(folder)?;
+
+            mut_working_items.diagnostics.extend(
+                self.unreadable_dirs
+                    .iter()
+                    .filter(|(path, _)| path.starts_with(folder))
+                    .map(|(path, error)| {
+                        VaultDiagnostic::unreadable_folder(path.clone(), error.clone())
+                    }),
+            );
+
+            mut_working_items
+        };
+
+        Some(working_items)
+    }
+
+    fn get_working_items_recursive(&self, folder: &Path) -> Option<WorkingItems> {
+        let listing = self.get_listing(folder)?;
+
+        let working_items = {
+            let mut mut_working_items = WorkingItems::default();
+
+            for dir_name in listing.dirs.iter() {
+                let dir_path = folder.join(dir_name);
+
+                match ClusterRootFolderPath::new_in_index(self, &dir_path) {
+                    Some(cluster_root_folder) => {
+                        let core_note_file =
+                            self.get_core_note_file_from_cluster_root_folder(&cluster_root_folder)?;
+                        let (category_folders_with_peripheral_files, diagnostics) = self
+                            .get_category_folders_with_peripheral_files_from_cluster_root_folder(
+                                &cluster_root_folder,
+                            )?;
+                        let attachment_files = self
+                            .get_attachment_files_from_cluster_root_folder(&cluster_root_folder)?;
+
+                        mut_working_items.items.push(WorkingPath::ClusterFolder {
+                            cluster_root_folder,
+                            core_note_file,
+                            category_folders_with_peripheral_files,
+                            attachment_files,
+                        });
+                        mut_working_items.diagnostics.extend(diagnostics);
+                    }
+                    None => {
+                        // Keep looking!
+                        let sub_working_items = self.get_working_items_recursive(&dir_path)?;
+
+                        mut_working_items.items.extend(sub_working_items.items);
+                        mut_working_items
+                            .diagnostics
+                            .extend(sub_working_items.diagnostics);
+                    }
+                }
+            }
+
+            for file_name in listing.files.iter() {
+                // Found outside a note cluster, so it should be a normal markdown file,
+                // or just ignore it if it's an unrelated file.
+                if let Some(normal_note_file) =
+                    NormalNoteFilePath::new_in_index(self, &folder.join(file_name))
+                {
+                    mut_working_items
+                        .items
+                        .push(WorkingPath::Note(normal_note_file));
+                }
+            }
+
+            mut_working_items
+        };
+
+        Some(working_items)
+    }
+}
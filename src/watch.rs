@@ -0,0 +1,382 @@
+use notify::{EventKind, RecursiveMode, Watcher, event::ModifyKind};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::{self, RecvTimeoutError},
+    time::{Duration, Instant, SystemTime},
+};
+use tap::prelude::*;
+use thiserror::Error;
+
+use crate::{
+    cluster_note::{self, WorkingPath},
+    cluster_note_io,
+    common::{self as comm, ObsidianVaultPath},
+    drivers,
+    exclusion::{ExclusionRules, LoadExclusionRulesError},
+    incremental,
+    lint::{self, LintIssue},
+    vault_config::VaultConfig,
+    vault_index::VaultIndex,
+};
+
+/// The steps of the watch pipeline. They run in this order so writes happen before anything is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WatchStep {
+    Writeback,
+    Index,
+    Lint,
+    LinkCheck,
+}
+
+pub const WATCH_STEPS_ALL: [WatchStep; 4] = [
+    WatchStep::Writeback,
+    WatchStep::Index,
+    WatchStep::Lint,
+    WatchStep::LinkCheck,
+];
+
+#[derive(Error, Debug)]
+pub enum WatchStepFromStrError {
+    #[error("Invalid watch step provided: {0:?}")]
+    InvalidStep(String),
+}
+
+impl FromStr for WatchStep {
+    type Err = WatchStepFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "writeback" => Ok(Self::Writeback),
+            "index" => Ok(Self::Index),
+            "lint" => Ok(Self::Lint),
+            "links" => Ok(Self::LinkCheck),
+            _ => Err(WatchStepFromStrError::InvalidStep(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    pub steps: Vec<WatchStep>,
+
+    /// How long a note has to go without changes before the pipeline runs on it
+    pub debounce: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            steps: WATCH_STEPS_ALL.to_vec(),
+            debounce: Duration::from_millis(500),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum WatchVaultError {
+    #[error("Failed to watch the vault: {0:?}")]
+    Notify(#[from] notify::Error),
+
+    #[error("Failed to load exclusion rules: {0}")]
+    ExclusionRules(#[from] LoadExclusionRulesError),
+
+    #[error("The watcher stopped sending events")]
+    Disconnected,
+}
+
+/// Hidden files and folders, like the tool folder and `.obsidian`, are not part of the notes of the vault
+fn is_hidden_path(vault: &ObsidianVaultPath, path: &Path) -> bool {
+    path.strip_prefix(&vault.path)
+        .map_or(true, |relative_path| {
+            relative_path
+                .components()
+                .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+        })
+}
+
+/// Only markdown notes are watched. Hidden files and folders are left out, and so are the temporary files editors
+/// write before renaming them into place.
+pub fn is_watched_path(vault: &ObsidianVaultPath, path: &Path) -> bool {
+    !is_hidden_path(vault, path) && cluster_note::has_markdown_extension(path)
+}
+
+/// Events that add, remove or move entries change what the vault index lists. Content changes do not.
+fn is_structural_event(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_))
+    )
+}
+
+/// A file is still being written if it was modified within the debounce window or its size changed since its
+/// last event
+fn is_file_being_written(path: &Path, debounce: Duration, opt_size_at_event: Option<u64>) -> bool {
+    let Ok(metadata) = fs::metadata(path) else {
+        return false;
+    };
+
+    let recently_modified = metadata
+        .modified()
+        .ok()
+        .and_then(|mtime| SystemTime::now().duration_since(mtime).ok())
+        .is_some_and(|age| age < debounce);
+
+    let size_changed = opt_size_at_event.is_some_and(|size| size != metadata.len());
+
+    recently_modified || size_changed
+}
+
+#[derive(Debug, Default)]
+struct OwnWrites {
+    content_hashes: HashMap<PathBuf, String>,
+}
+
+impl OwnWrites {
+    fn record(&mut self, path: &Path) {
+        if let Some(content) = comm::read_file_content(path) {
+            self.content_hashes
+                .insert(path.to_path_buf(), incremental::hash_content(&content));
+        }
+    }
+
+    /// Our own writes come back as events. They are recognized by content, so a later edit by the user to the
+    /// same note is not mistaken for one.
+    fn is_own_write(&self, path: &Path) -> bool {
+        let Some(content) = comm::read_file_content(path) else {
+            return false;
+        };
+
+        self.content_hashes.get(path) == Some(&incremental::hash_content(&content))
+    }
+}
+
+/// The pipeline and what it keeps between notes. The working items the link check resolves links against are
+/// built once, and only rebuilt after notes come and go, reusing the listings of unchanged folders.
+#[derive(Debug)]
+pub struct WatchPipeline {
+    steps: Vec<WatchStep>,
+    opt_index: Option<VaultIndex>,
+    opt_working_items: Option<Vec<WorkingPath>>,
+    own_writes: OwnWrites,
+}
+
+impl WatchPipeline {
+    pub fn new(steps: &[WatchStep]) -> Self {
+        let steps = {
+            let mut mut_steps = steps.to_vec();
+            mut_steps.sort();
+            mut_steps.dedup();
+            mut_steps
+        };
+
+        Self {
+            steps,
+            opt_index: None,
+            opt_working_items: None,
+            own_writes: OwnWrites::default(),
+        }
+    }
+
+    pub fn steps(&self) -> &[WatchStep] {
+        &self.steps
+    }
+
+    /// Notes or folders were added, removed or moved, or the vault config changed
+    pub fn invalidate_working_items(&mut self) {
+        self.opt_working_items = None;
+    }
+
+    pub fn is_own_write(&self, path: &Path) -> bool {
+        self.own_writes.is_own_write(path)
+    }
+
+    fn get_working_items(&mut self, vault: &ObsidianVaultPath) -> Option<&[WorkingPath]> {
+        if self.opt_working_items.is_none() {
+            let index = ExclusionRules::for_vault(vault)
+                .map_err(|e| log::error!("Failed to load exclusion rules: {e}"))
+                .ok()?
+                .pipe(|rules| {
+                    VaultIndex::build_with_rules(
+                        &vault.path,
+                        self.opt_index.as_ref(),
+                        rules,
+                        vault.config.clone(),
+                        vault.traversal_options.symlink_policy,
+                    )
+                })
+                .map_err(|e| log::error!("Failed to index vault {:?}: {e}", vault.path))
+                .ok()?;
+
+            self.opt_working_items = index.get_working_item_paths(&vault.path);
+            self.opt_index = Some(index);
+        }
+
+        self.opt_working_items.as_deref()
+    }
+
+    /// Runs the steps on a note that was saved, created or removed. Returns the issues the lint and link check
+    /// found in it.
+    pub fn run(&mut self, vault: &ObsidianVaultPath, path: &Path) -> Vec<LintIssue> {
+        let exists = path.is_file();
+
+        let mut mut_issues = vec![];
+
+        for step in self.steps.clone() {
+            match step {
+                WatchStep::Writeback
+                    if exists && !drivers::skip_processing_managed_path(vault, path) =>
+                {
+                    match comm::writeback_markdown_file(path) {
+                        Ok(true) => self.own_writes.record(path),
+                        Ok(false) => (),
+                        Err(e) => log::error!("{path:?}: {e}"),
+                    }
+                }
+                WatchStep::Index => {
+                    // Notes coming and going in a cluster change its index too
+                    let Some(cluster_root_folder) =
+                        cluster_note::get_cluster_root_folder_of_note_path(path, &vault.config)
+                    else {
+                        continue;
+                    };
+
+                    let Some(core_note) = cluster_note::get_core_note_file_from_cluster_root_folder(
+                        &cluster_root_folder,
+                    ) else {
+                        continue;
+                    };
+
+                    match cluster_note_io::generate_index_for_core_note(&core_note, &vault.config) {
+                        Ok(true) => {
+                            log::info!("Regenerated the index of {:?}", core_note.path);
+                            self.own_writes.record(&core_note.path);
+                        }
+                        Ok(false) => (),
+                        Err(e) => log::error!("{:?}: {e}", core_note.path),
+                    }
+                }
+                WatchStep::Lint if exists => match lint::lint_note(path, &vault.config) {
+                    Ok(issues) => mut_issues.extend(issues),
+                    Err(e) => log::error!("{path:?}: {e}"),
+                },
+                WatchStep::LinkCheck if exists => {
+                    let Some(working_items) = self.get_working_items(vault) else {
+                        continue;
+                    };
+
+                    match lint::check_note_links(working_items, path) {
+                        Ok(issues) => mut_issues.extend(issues),
+                        Err(e) => log::error!("{path:?}: {e}"),
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        mut_issues
+    }
+}
+
+/// Runs the pipeline on every note saved in the vault, once it has settled. Exclusion rules are read once, when
+/// watching starts, while the vault config is read again whenever it changes. This only returns on error.
+pub fn watch_vault(
+    vault: &mut ObsidianVaultPath,
+    options: &WatchOptions,
+) -> Result<(), WatchVaultError> {
+    let mut mut_pipeline = WatchPipeline::new(&options.steps);
+
+    let mut mut_rules = ExclusionRules::for_vault(vault)?;
+
+    let config_path = VaultConfig::path_in_vault(&vault.path);
+
+    let (tx, rx) = mpsc::channel();
+
+    let mut mut_watcher = notify::recommended_watcher(tx)?;
+    mut_watcher.watch(&vault.path, RecursiveMode::Recursive)?;
+
+    log::info!(
+        "Watching {:?} with steps {:?}",
+        vault.path,
+        mut_pipeline.steps()
+    );
+
+    // The last time each note was seen changing, and its size then
+    let mut mut_pending = BTreeMap::<PathBuf, (Instant, Option<u64>)>::new();
+
+    loop {
+        let timeout = mut_pending
+            .values()
+            .map(|(last_change, _)| last_change)
+            .min()
+            .map(|last_change| options.debounce.saturating_sub(last_change.elapsed()))
+            .unwrap_or(options.debounce);
+
+        match rx.recv_timeout(timeout) {
+            Ok(Ok(event)) => {
+                if matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                ) {
+                    let is_structural = is_structural_event(&event.kind);
+
+                    for path in event.paths {
+                        if path == config_path {
+                            // A broken config is reported, and the last one that loaded stays in use
+                            match vault.reload_config() {
+                                Ok(()) => log::info!("Reloaded the vault config"),
+                                Err(e) => log::error!("Keeping the previous vault config: {e}"),
+                            }
+
+                            mut_pipeline.invalidate_working_items();
+                            continue;
+                        }
+
+                        if is_structural && !is_hidden_path(vault, &path) {
+                            mut_pipeline.invalidate_working_items();
+                        }
+
+                        if is_watched_path(vault, &path) && mut_rules.check_path(&path).is_none() {
+                            let opt_size = fs::metadata(&path).ok().map(|metadata| metadata.len());
+
+                            mut_pending.insert(path, (Instant::now(), opt_size));
+                        }
+                    }
+                }
+            }
+            Ok(Err(e)) => log::warn!("Watch error: {e}"),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return Err(WatchVaultError::Disconnected),
+        }
+
+        let settled_paths = mut_pending
+            .iter()
+            .filter(|(_, (last_change, _))| last_change.elapsed() >= options.debounce)
+            .map(|(path, (_, opt_size))| (path.clone(), *opt_size))
+            .collect::<Vec<_>>();
+
+        for (path, opt_size) in settled_paths {
+            if is_file_being_written(&path, options.debounce, opt_size) {
+                let opt_size = fs::metadata(&path).ok().map(|metadata| metadata.len());
+
+                mut_pending.insert(path, (Instant::now(), opt_size));
+                continue;
+            }
+
+            mut_pending.remove(&path);
+
+            if mut_pipeline.is_own_write(&path) {
+                log::debug!("Ignoring our own write to {path:?}");
+                continue;
+            }
+
+            log::info!("Processing {path:?}");
+
+            for issue in mut_pipeline.run(vault, &path) {
+                log::warn!("{path:?}: {issue}");
+            }
+        }
+    }
+}
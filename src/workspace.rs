@@ -0,0 +1,569 @@
+use pulldown_cmark::{Event, Tag};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tap::prelude::*;
+use thiserror::Error;
+
+use crate::{
+    common::{self as comm, ObsidianVaultPath, OpenObsidianVaultError},
+    drivers,
+    mutation::Mutation,
+};
+
+pub const OBSIDIAN_URI_PREFIX: &str = "obsidian://open?";
+
+/// A vault of the workspace as written in the config. Paths are relative to the config file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceVaultConfig {
+    /// The name obsidian knows the vault by, which `obsidian://` links use
+    pub name: String,
+    pub path: PathBuf,
+
+    /// Where notes of the vault are browsed on GitHub, like `https://github.com/owner/repo/blob/main`
+    #[serde(default)]
+    pub opt_github_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkspaceConfig {
+    pub vaults: Vec<WorkspaceVaultConfig>,
+}
+
+#[derive(Debug)]
+pub struct WorkspaceVault {
+    pub name: String,
+    pub vault: ObsidianVaultPath,
+    pub opt_github_url: Option<String>,
+}
+
+/// Several vaults whose notes link to each other through URLs
+#[derive(Debug)]
+pub struct Workspace {
+    pub vaults: Vec<WorkspaceVault>,
+}
+
+#[derive(Error, Debug)]
+pub enum LoadWorkspaceError {
+    #[error("Failed to read workspace config {0:?}")]
+    ReadFailed(PathBuf),
+
+    #[error("Failed to parse workspace config {0:?}: {1}")]
+    Parse(PathBuf, ron::error::SpannedError),
+
+    #[error("Vault {0:?} of the workspace could not be opened: {1}")]
+    OpenVault(String, Box<OpenObsidianVaultError>),
+
+    #[error("Vault name {0:?} is used more than once in the workspace")]
+    DuplicateName(String),
+}
+
+/// The two forms a link to a note of another vault can take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrossVaultLinkForm {
+    GithubWebview,
+    ObsidianUri,
+}
+
+#[derive(Error, Debug)]
+pub enum CrossVaultLinkFormFromStrError {
+    #[error("Invalid cross vault link form provided: {0:?}")]
+    InvalidForm(String),
+}
+
+impl FromStr for CrossVaultLinkForm {
+    type Err = CrossVaultLinkFormFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "github" => Ok(Self::GithubWebview),
+            "obsidian" => Ok(Self::ObsidianUri),
+            _ => Err(CrossVaultLinkFormFromStrError::InvalidForm(s.to_string())),
+        }
+    }
+}
+
+/// A link to a note of a workspace vault, independent of the form it was written in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrossVaultLink {
+    pub vault_name: String,
+
+    /// Relative to the vault, with the markdown extension
+    pub relative_path: PathBuf,
+    pub opt_fragment: Option<String>,
+}
+
+fn is_unreserved_url_byte(byte: u8, keep_slash: bool) -> bool {
+    byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) || (keep_slash && byte == b'/')
+}
+
+pub fn percent_encode(s: &str, keep_slash: bool) -> String {
+    s.bytes()
+        .map(|byte| match is_unreserved_url_byte(byte, keep_slash) {
+            true => (byte as char).to_string(),
+            false => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Invalid escapes are kept as they are
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+
+    let decoded = {
+        let mut mut_decoded = Vec::with_capacity(bytes.len());
+        let mut mut_i = 0;
+
+        while mut_i < bytes.len() {
+            let opt_escaped = (bytes[mut_i] == b'%')
+                .then(|| s.get(mut_i + 1..mut_i + 3))
+                .flatten()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+            match opt_escaped {
+                Some(byte) => {
+                    mut_decoded.push(byte);
+                    mut_i += 3;
+                }
+                None => {
+                    mut_decoded.push(bytes[mut_i]);
+                    mut_i += 1;
+                }
+            }
+        }
+
+        mut_decoded
+    };
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn with_markdown_extension(path: &str) -> PathBuf {
+    match path.ends_with(".md") {
+        true => PathBuf::from(path),
+        false => PathBuf::from(format!("{path}.md")),
+    }
+}
+
+fn relative_path_to_url_path(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The anchor GitHub gives a heading: lowercased, without punctuation, and with hyphens for spaces. Of nested
+/// obsidian headings like `A#B`, only the last one is kept.
+pub fn get_github_heading_slug(heading: &str) -> String {
+    let heading = heading.rsplit('#').next().unwrap_or(heading);
+
+    heading
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect::<String>()
+        .pipe_ref(|slug| percent_encode(slug, false))
+}
+
+/// The first heading of the note with the given GitHub anchor
+fn get_heading_of_github_slug(note_path: &Path, slug: &str) -> Option<String> {
+    let content = comm::read_file_content(note_path)?;
+
+    let slug = percent_encode(slug, false);
+
+    comm::get_atx_heading_lines(&content)
+        .into_iter()
+        .flatten()
+        .find(|(_, heading)| get_github_heading_slug(heading) == slug)
+        .map(|(_, heading)| heading.to_owned())
+}
+
+impl Workspace {
+    pub fn load(config_path: &Path) -> Result<Self, LoadWorkspaceError> {
+        let content = comm::read_file_content(config_path)
+            .ok_or(LoadWorkspaceError::ReadFailed(config_path.to_path_buf()))?;
+
+        let config = ron::from_str::<WorkspaceConfig>(&content)
+            .map_err(|e| LoadWorkspaceError::Parse(config_path.to_path_buf(), e))?;
+
+        let config_folder = config_path.parent().unwrap_or(Path::new("."));
+
+        Self::from_config(config_folder, &config)
+    }
+
+    pub fn from_config(
+        config_folder: &Path,
+        config: &WorkspaceConfig,
+    ) -> Result<Self, LoadWorkspaceError> {
+        let mut mut_names = BTreeSet::new();
+
+        let vaults = config
+            .vaults
+            .iter()
+            .map(|vault_config| {
+                if !mut_names.insert(vault_config.name.clone()) {
+                    return Err(LoadWorkspaceError::DuplicateName(vault_config.name.clone()));
+                }
+
+                let path = config_folder.join(&vault_config.path);
+
+                let vault = ObsidianVaultPath::new(&path).map_err(|e| {
+                    LoadWorkspaceError::OpenVault(vault_config.name.clone(), Box::new(e))
+                })?;
+
+                Ok(WorkspaceVault {
+                    name: vault_config.name.clone(),
+                    vault,
+                    opt_github_url: vault_config
+                        .opt_github_url
+                        .as_ref()
+                        .map(|url| url.trim_end_matches('/').to_owned()),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { vaults })
+    }
+
+    pub fn get_vault(&self, name: &str) -> Option<&WorkspaceVault> {
+        self.vaults.iter().find(|vault| vault.name == name)
+    }
+
+    pub fn get_vault_of_path(&self, path: &Path) -> Option<&WorkspaceVault> {
+        self.vaults
+            .iter()
+            .find(|vault| path.starts_with(&vault.vault.path))
+    }
+
+    /// Parses GitHub webview URLs and `obsidian://open` URIs that point into a vault of the workspace
+    pub fn parse_cross_vault_link(&self, url: &str) -> Option<CrossVaultLink> {
+        if let Some(query) = url.strip_prefix(OBSIDIAN_URI_PREFIX) {
+            let param = |key: &str| {
+                query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix(&format!("{key}=")))
+                    .map(percent_decode)
+            };
+
+            let vault = self.get_vault(&param("vault")?)?;
+
+            let file = param("file")?;
+
+            let (path, opt_fragment) = match file.split_once('#') {
+                Some((path, fragment)) => (path.to_owned(), Some(fragment.to_owned())),
+                None => (file, None),
+            };
+
+            return Some(CrossVaultLink {
+                vault_name: vault.name.clone(),
+                relative_path: with_markdown_extension(&path),
+                opt_fragment,
+            });
+        }
+
+        self.vaults.iter().find_map(|vault| {
+            let url_path = url
+                .strip_prefix(vault.opt_github_url.as_ref()?)?
+                .strip_prefix('/')?;
+
+            let (path, opt_fragment) = match url_path.split_once('#') {
+                Some((path, fragment)) => (path, Some(percent_decode(fragment))),
+                None => (url_path, None),
+            };
+
+            let relative_path = PathBuf::from(percent_decode(path));
+
+            // GitHub anchors are slugs of headings, while obsidian links to headings by their text
+            let opt_fragment = opt_fragment.map(|fragment| {
+                get_heading_of_github_slug(&vault.vault.path.join(&relative_path), &fragment)
+                    .unwrap_or(fragment)
+            });
+
+            Some(CrossVaultLink {
+                vault_name: vault.name.clone(),
+                relative_path,
+                opt_fragment,
+            })
+        })
+    }
+
+    /// The link in the given form. GitHub links need the target vault to have a GitHub URL.
+    pub fn format_cross_vault_link(
+        &self,
+        link: &CrossVaultLink,
+        form: CrossVaultLinkForm,
+    ) -> Option<String> {
+        let vault = self.get_vault(&link.vault_name)?;
+
+        let url_path = relative_path_to_url_path(&link.relative_path);
+
+        match form {
+            CrossVaultLinkForm::GithubWebview => {
+                let github_url = vault.opt_github_url.as_ref()?;
+
+                let fragment = match &link.opt_fragment {
+                    Some(fragment) if fragment.starts_with('^') => {
+                        format!("#{}", percent_encode(fragment, false))
+                    }
+                    Some(fragment) => format!("#{}", get_github_heading_slug(fragment)),
+                    None => String::new(),
+                };
+
+                Some(format!(
+                    "{github_url}/{}{fragment}",
+                    percent_encode(&url_path, true)
+                ))
+            }
+            CrossVaultLinkForm::ObsidianUri => {
+                let file = {
+                    let mut mut_file = url_path.strip_suffix(".md").unwrap_or(&url_path).to_owned();
+
+                    if let Some(fragment) = &link.opt_fragment {
+                        mut_file.push('#');
+                        mut_file.push_str(fragment);
+                    }
+
+                    mut_file
+                };
+
+                Some(format!(
+                    "{OBSIDIAN_URI_PREFIX}vault={}&file={}",
+                    percent_encode(&vault.name, false),
+                    percent_encode(&file, false)
+                ))
+            }
+        }
+    }
+
+    /// The note the link points to, if it exists
+    pub fn resolve_cross_vault_link(&self, link: &CrossVaultLink) -> Option<PathBuf> {
+        let vault = self.get_vault(&link.vault_name)?;
+
+        let path = vault.vault.path.join(&link.relative_path);
+
+        path.is_file().then_some(path)
+    }
+}
+
+/// Destinations of the markdown links and autolinks of a note
+pub fn extract_markdown_link_urls(events: &[Event<'_>]) -> Vec<String> {
+    events
+        .iter()
+        .flat_map(|event| match event {
+            Event::Start(Tag::Link { dest_url, .. }) => Some(dest_url.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Replaces the destinations of the markdown links and autolinks of a note by their byte ranges. Destinations the
+/// function returns None for are left alone, as are those of reference links, which are defined elsewhere.
+/// Returns the new content and how many destinations were replaced.
+fn rewrite_markdown_link_urls(
+    content: &str,
+    f: impl Fn(&str) -> Option<String>,
+) -> (String, usize) {
+    let replacements = comm::parse_markdown_file(content)
+        .iter()
+        .zip(comm::get_markdown_event_offsets(content))
+        .flat_map(|(event, range)| {
+            let Event::Start(Tag::Link { dest_url, .. }) = event else {
+                return None;
+            };
+
+            let link_source = &content[range.clone()];
+
+            // The destination follows the opening paren or angle bracket, after the text of the link
+            let (dest_offset, _) = link_source
+                .match_indices(dest_url.as_ref())
+                .filter(|(offset, _)| link_source[..*offset].trim_end().ends_with(['(', '<']))
+                .last()?;
+
+            let dest_start = range.start + dest_offset;
+
+            Some((dest_start..dest_start + dest_url.len(), f(dest_url)?))
+        })
+        .collect::<Vec<_>>();
+
+    let new_content =
+        replacements
+            .iter()
+            .rev()
+            .fold(content.to_owned(), |mut mut_content, (range, new_url)| {
+                mut_content.replace_range(range.clone(), new_url);
+                mut_content
+            });
+
+    (new_content, replacements.len())
+}
+
+/// Rewrites the cross vault links of a note to the given form. Links that cannot take the form are left alone.
+/// Returns the new content and how many links were rewritten.
+pub fn rewrite_cross_vault_links(
+    workspace: &Workspace,
+    content: &str,
+    form: CrossVaultLinkForm,
+) -> (String, usize) {
+    rewrite_markdown_link_urls(content, |url| {
+        let link = workspace.parse_cross_vault_link(url)?;
+        let new_url = workspace.format_cross_vault_link(&link, form)?;
+
+        (new_url != url).then_some(new_url)
+    })
+}
+
+/// Plans rewriting the cross vault links of the notes of a vault of the workspace to the given form
+pub fn plan_rewrite_cross_vault_links(
+    workspace: &Workspace,
+    workspace_vault: &WorkspaceVault,
+    form: CrossVaultLinkForm,
+) -> Vec<Mutation> {
+    drivers::get_markdown_file_paths_in_vault(&workspace_vault.vault, true)
+        .into_iter()
+        .flat_map(|path| {
+            let content = comm::read_file_content(&path)?;
+
+            let (new_content, count) = rewrite_cross_vault_links(workspace, &content, form);
+
+            (count > 0).then_some(Mutation::WriteFile {
+                path,
+                opt_old_content: Some(content),
+                new_content,
+            })
+        })
+        .collect()
+}
+
+/// Where a note of the vault ends up after the renames of the planned mutations, relative to the vault. None if
+/// no rename moves it or a folder it is in.
+fn get_moved_relative_path(
+    vault: &ObsidianVaultPath,
+    mutations: &[Mutation],
+    relative_path: &Path,
+) -> Option<PathBuf> {
+    let moved_path =
+        mutations.iter().fold(
+            vault.path.join(relative_path),
+            |path, mutation| match mutation {
+                Mutation::Rename { from, to } if &path == from => to.clone(),
+                Mutation::Rename { from, to } => match path.strip_prefix(from) {
+                    Ok(rest) => to.join(rest),
+                    Err(_) => path,
+                },
+                _ => path,
+            },
+        );
+
+    moved_path
+        .strip_prefix(&vault.path)
+        .ok()
+        .filter(|moved_relative_path| *moved_relative_path != relative_path)
+        .map(Path::to_path_buf)
+}
+
+/// Redirects the cross vault links to notes that the planned mutations of the vault move, in the notes of every
+/// vault of the workspace. Each link keeps its form. Notes the plan already writes are rewritten within the plan,
+/// and the others are written before anything moves. The plan is returned as is if the vault is not part of the
+/// workspace.
+pub fn redirect_cross_vault_links(
+    workspace: &Workspace,
+    vault: &ObsidianVaultPath,
+    mutations: Vec<Mutation>,
+) -> Vec<Mutation> {
+    let is_same_folder = |a: &Path, b: &Path| match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    };
+
+    let Some(moved_vault) = workspace
+        .vaults
+        .iter()
+        .find(|workspace_vault| is_same_folder(&workspace_vault.vault.path, &vault.path))
+    else {
+        return mutations;
+    };
+
+    let redirect_links = |content: &str| {
+        rewrite_markdown_link_urls(content, |url| {
+            let link = workspace.parse_cross_vault_link(url)?;
+
+            if link.vault_name != moved_vault.name {
+                return None;
+            }
+
+            let relative_path = get_moved_relative_path(vault, &mutations, &link.relative_path)?;
+
+            let form = match url.starts_with(OBSIDIAN_URI_PREFIX) {
+                true => CrossVaultLinkForm::ObsidianUri,
+                false => CrossVaultLinkForm::GithubWebview,
+            };
+
+            workspace.format_cross_vault_link(
+                &CrossVaultLink {
+                    relative_path,
+                    ..link
+                },
+                form,
+            )
+        })
+    };
+
+    let planned_writes = mutations
+        .iter()
+        .flat_map(|mutation| match mutation {
+            Mutation::WriteFile { path, .. } => Some(path.clone()),
+            _ => None,
+        })
+        .collect::<BTreeSet<_>>();
+
+    // Notes of the moved vault are listed through the given handle, so their paths match those of the plan
+    let note_paths = workspace.vaults.iter().flat_map(|workspace_vault| {
+        match workspace_vault.name == moved_vault.name {
+            true => drivers::get_markdown_file_paths_in_vault(vault, true),
+            false => drivers::get_markdown_file_paths_in_vault(&workspace_vault.vault, true),
+        }
+    });
+
+    let link_rewrites = note_paths
+        .filter(|path| !planned_writes.contains(path))
+        .flat_map(|path| {
+            let content = comm::read_file_content(&path)?;
+
+            let (new_content, count) = redirect_links(&content);
+
+            (count > 0).then_some(Mutation::WriteFile {
+                path,
+                opt_old_content: Some(content),
+                new_content,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let planned_mutations = mutations
+        .iter()
+        .cloned()
+        .map(|mutation| match mutation {
+            Mutation::WriteFile {
+                path,
+                opt_old_content,
+                new_content,
+            } => Mutation::WriteFile {
+                path,
+                opt_old_content,
+                new_content: redirect_links(&new_content).0,
+            },
+            mutation => mutation,
+        })
+        .collect::<Vec<_>>();
+
+    link_rewrites.into_iter().chain(planned_mutations).collect()
+}
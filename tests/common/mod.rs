@@ -0,0 +1,59 @@
+//! Helpers shared by the integration tests
+
+#![allow(dead_code)]
+
+use migration_rs::{
+    common::{self as comm, ObsidianVaultPath},
+    vault_config::VaultConfig,
+};
+use std::{fs, path::Path};
+use tempfile::TempDir;
+
+pub fn write_note(path: &Path, content: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    comm::write_file_content(content, path).unwrap();
+}
+
+pub fn read(path: &Path) -> String {
+    comm::read_file_content(path).unwrap()
+}
+
+/// Makes the folder one obsidian would open as a vault
+pub fn create_vault(path: &Path) {
+    fs::create_dir_all(path.join(".obsidian")).unwrap();
+}
+
+/// A vault in a temporary folder, removed once dropped
+pub struct TestVault {
+    tmp: TempDir,
+}
+
+impl TestVault {
+    pub fn new() -> Self {
+        let tmp = tempfile::tempdir().unwrap();
+
+        create_vault(tmp.path());
+
+        Self { tmp }
+    }
+
+    /// Adds a note at a path relative to the vault
+    pub fn with_note(self, relative_path: &str, content: &str) -> Self {
+        write_note(&self.path().join(relative_path), content);
+        self
+    }
+
+    /// Adds a `.migration_rs/config.ron`
+    pub fn with_config(self, content: &str) -> Self {
+        write_note(&VaultConfig::path_in_vault(self.path()), content);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        self.tmp.path()
+    }
+
+    pub fn vault_path(&self) -> ObsidianVaultPath {
+        ObsidianVaultPath::new(self.path()).unwrap()
+    }
+}
@@ -0,0 +1,301 @@
+//! Testing that peripheral notes move between clusters and that clusters merge, with links and indexes updated
+
+mod common;
+
+use common::{TestVault, read, write_note};
+use migration_rs::*;
+
+fn index_of(names: &str) -> String {
+    format!("%% index start %%\n\n{names}\n\n%% index end %%")
+}
+
+fn setup_vault() -> TestVault {
+    TestVault::new()
+        .with_note(
+            "A/A.md",
+            &format!(
+                "# Goal\n\nA\n\n{}\n",
+                index_of("- Ideas\n  - [[Idea]]\n- Tasks\n  - [[Task]]")
+            ),
+        )
+        .with_note(
+            "A/tasks/Task.md",
+            "---\nparent: \"[[A]]\"\n---\n\nSpawn [[Other]] ^spawn-task-0a1b2c\n",
+        )
+        .with_note("A/ideas/Idea.md", "---\nparent: \"[[A]]\"\n---\n\nIdea\n")
+        .with_note(
+            "B/B.md",
+            &format!("B\n\n{}\n", index_of("- Tasks\n  - [[Other Task]]")),
+        )
+        .with_note(
+            "B/tasks/Other Task.md",
+            "---\nparent: \"[[B]]\"\n---\n\nOther task\n",
+        )
+        .with_note(
+            "Ref.md",
+            "[[A/tasks/Task]] [[Task]] [[A#Goal]]\n\nFrom [[#^spawn-idea-0a1b2c]] in [[A]]\n",
+        )
+}
+
+#[test]
+fn test_move_peripheral_note() {
+    let test_vault = setup_vault();
+    let vault = test_vault.path();
+
+    let vault_path = test_vault.vault_path();
+
+    let mutations = cluster_move::plan_move_peripheral_note(
+        &vault_path,
+        &vault.join("A/tasks/Task.md"),
+        &vault.join("B/B.md"),
+    )
+    .unwrap();
+
+    mutation::apply_mutations(&vault_path, "move_peripheral", mutations).unwrap();
+
+    assert!(!vault.join("A/tasks").exists());
+    assert_eq!(
+        read(&vault.join("B/tasks/Task.md")),
+        "---\nparent: \"[[B]]\"\n---\n\nSpawn [[Other]] ^spawn-task-0a1b2c\n"
+    );
+    assert_eq!(
+        read(&vault.join("A/A.md")),
+        format!("# Goal\n\nA\n\n{}\n", index_of("- Ideas\n  - [[Idea]]"))
+    );
+    assert_eq!(
+        read(&vault.join("B/B.md")),
+        format!(
+            "B\n\n{}\n",
+            index_of("- Tasks\n  - [[Other Task]]\n  - [[Task]]")
+        )
+    );
+    assert!(read(&vault.join("Ref.md")).starts_with("[[B/tasks/Task]] [[Task]]"));
+
+    // Moving to the cluster it is in is refused
+    assert!(
+        cluster_move::plan_move_peripheral_note(
+            &vault_path,
+            &vault.join("B/tasks/Task.md"),
+            &vault.join("B/B.md"),
+        )
+        .is_err()
+    );
+
+    mutation::undo_last_mutations(&vault_path).unwrap().unwrap();
+
+    assert!(vault.join("A/tasks/Task.md").is_file());
+    assert!(!vault.join("B/tasks/Task.md").exists());
+}
+
+#[test]
+fn test_move_peripheral_note_with_its_spawn_marker() {
+    let test_vault = TestVault::new()
+        .with_note(
+            "A/A.md",
+            &format!(
+                "A\n\nSpawn [[Task]] ^spawn-task-0a1b2c\n\nMore\n\n{}\n",
+                index_of("- Tasks\n  - [[Task]]")
+            ),
+        )
+        .with_note(
+            "A/tasks/Task.md",
+            "---\nparent: \"[[A]]\"\n---\n\nFrom [[#^spawn-task-0a1b2c]] in [[A]]\n",
+        )
+        .with_note("B/B.md", &format!("B\n\n{}\n", index_of("")))
+        .with_note("Ref.md", "[[A#^spawn-task-0a1b2c]]\n");
+    let vault = test_vault.path();
+
+    let vault_path = test_vault.vault_path();
+
+    let mutations = cluster_move::plan_move_peripheral_note(
+        &vault_path,
+        &vault.join("A/tasks/Task.md"),
+        &vault.join("B/B.md"),
+    )
+    .unwrap();
+
+    mutation::apply_mutations(&vault_path, "move_peripheral", mutations).unwrap();
+
+    // The marker moves from the old core note to the new one, and the back-reference follows it
+    assert_eq!(
+        read(&vault.join("B/tasks/Task.md")),
+        "---\nparent: \"[[B]]\"\n---\n\nFrom [[#^spawn-task-0a1b2c]] in [[B]]\n"
+    );
+    assert!(!read(&vault.join("A/A.md")).contains("^spawn-task-0a1b2c"));
+    assert!(read(&vault.join("A/A.md")).starts_with("A\n\nMore\n\n"));
+    assert!(read(&vault.join("B/B.md")).starts_with("B\n\nSpawn [[Task]] ^spawn-task-0a1b2c\n\n"));
+    assert_eq!(read(&vault.join("Ref.md")), "[[B#^spawn-task-0a1b2c]]\n");
+}
+
+#[test]
+fn test_merge_clusters() {
+    let test_vault = setup_vault();
+    let vault = test_vault.path();
+
+    let vault_path = test_vault.vault_path();
+
+    let mutations = cluster_move::plan_merge_clusters(
+        &vault_path,
+        &vault.join("A/A.md"),
+        &vault.join("B/B.md"),
+    )
+    .unwrap();
+
+    mutation::apply_mutations(&vault_path, "merge_clusters", mutations).unwrap();
+
+    assert!(!vault.join("A").exists());
+    assert_eq!(
+        read(&vault.join("B/B.md")),
+        format!(
+            "B\n\n{}\n\n# A\n\n## Goal\n\nA\n",
+            index_of("- Ideas\n  - [[Idea]]\n- Tasks\n  - [[Other Task]]\n  - [[Task]]")
+        )
+    );
+    assert_eq!(
+        read(&vault.join("B/ideas/Idea.md")),
+        "---\nparent: \"[[B]]\"\n---\n\nIdea\n"
+    );
+    assert_eq!(
+        read(&vault.join("Ref.md")),
+        "[[B/tasks/Task]] [[Task]] [[B#Goal]]\n\nFrom [[#^spawn-idea-0a1b2c]] in [[B]]\n"
+    );
+
+    mutation::undo_last_mutations(&vault_path).unwrap().unwrap();
+
+    assert!(vault.join("A/A.md").is_file());
+    assert!(vault.join("A/ideas/Idea.md").is_file());
+    assert!(!vault.join("B/ideas").exists());
+}
+
+#[test]
+fn test_promote_peripheral_note() {
+    let test_vault = setup_vault();
+    let vault = test_vault.path();
+
+    let vault_path = test_vault.vault_path();
+
+    let mutations = cluster_move::plan_promote_peripheral_note(
+        &vault_path,
+        &vault.join("A/tasks/Task.md"),
+        false,
+    )
+    .unwrap();
+
+    mutation::apply_mutations(&vault_path, "promote", mutations).unwrap();
+
+    assert!(!vault.join("A/tasks").exists());
+    assert_eq!(
+        read(&vault.join("Task.md")),
+        "---\nspawned_by: \"[[A]]\"\n---\n\nSpawn [[Other]] ^spawn-task-0a1b2c\n"
+    );
+    assert_eq!(
+        read(&vault.join("A/A.md")),
+        format!("# Goal\n\nA\n\n{}\n", index_of("- Ideas\n  - [[Idea]]"))
+    );
+    assert!(read(&vault.join("Ref.md")).starts_with("[[Task]] [[Task]]"));
+
+    mutation::undo_last_mutations(&vault_path).unwrap().unwrap();
+
+    // As a new cluster, the note becomes its core note
+    let mutations = cluster_move::plan_promote_peripheral_note(
+        &vault_path,
+        &vault.join("A/ideas/Idea.md"),
+        true,
+    )
+    .unwrap();
+
+    mutation::apply_mutations(&vault_path, "promote", mutations).unwrap();
+
+    assert!(cluster_note::CoreNoteFilePath::new(&vault.join("Idea/Idea.md")).is_some());
+}
+
+#[test]
+fn test_split_heading_section() {
+    let test_vault = setup_vault();
+    let vault = test_vault.path();
+
+    write_note(
+        &vault.join("Plain.md"),
+        "# Intro\n\nSee [[#Deploy]]\n\n## Deploy: prod\n\nSteps ^steps\n\n### Check\n\nBack to [[#Intro]]\n\n## After\n\nDone\n",
+    );
+    write_note(
+        &vault.join("Links.md"),
+        "[[Plain#Deploy: prod]] [[Plain#Check|check]] [[Plain#^steps]]\n",
+    );
+
+    let vault_path = test_vault.vault_path();
+
+    // A normal note becomes a cluster
+    let mutations = cluster_move::plan_split_heading_section(
+        &vault_path,
+        &vault.join("Plain.md"),
+        "Deploy: prod",
+        "task",
+        false,
+    )
+    .unwrap();
+
+    mutation::apply_mutations(&vault_path, "split", mutations).unwrap();
+
+    let new_note = read(&vault.join("Plain/tasks/000 Deploy prod.md"));
+    let spawn_block_identifier = new_note
+        .split("From [[#")
+        .nth(1)
+        .and_then(|rest| rest.split("]]").next())
+        .unwrap()
+        .to_owned();
+
+    assert!(spawn_block_identifier.starts_with("^spawn-task-"));
+    assert_eq!(
+        new_note,
+        format!(
+            "---\nparent: \"[[Plain]]\"\n---\n\nFrom [[#{spawn_block_identifier}]] in [[Plain]]\n\n\
+             Steps ^steps\n\n# Check\n\nBack to [[Plain#Intro]]\n"
+        )
+    );
+    assert_eq!(
+        read(&vault.join("Plain/Plain.md")),
+        format!(
+            "# Intro\n\nSee [[#Deploy]]\n\nSpawn [[000 Deploy prod]] {spawn_block_identifier}\n\n## After\n\nDone\n"
+        )
+    );
+    assert_eq!(
+        read(&vault.join("Links.md")),
+        "[[000 Deploy prod]] [[000 Deploy prod#Check|check]] [[000 Deploy prod#^steps]]\n"
+    );
+
+    // A core note keeps its cluster, and its index lists the new note
+    let mutations = cluster_move::plan_split_heading_section(
+        &vault_path,
+        &vault.join("A/A.md"),
+        "Goal",
+        "Ideas",
+        true,
+    )
+    .unwrap();
+
+    mutation::apply_mutations(&vault_path, "split", mutations).unwrap();
+
+    assert_eq!(
+        read(&vault.join("A/A.md")),
+        format!(
+            "![[000 Goal]]\n\n{}\n",
+            index_of("- Ideas\n  - [[000 Goal]]\n  - [[Idea]]\n- Tasks\n  - [[Task]]")
+        )
+    );
+    assert_eq!(
+        read(&vault.join("A/ideas/000 Goal.md")),
+        "---\nparent: \"[[A]]\"\n---\n\nA\n"
+    );
+
+    assert!(
+        cluster_move::plan_split_heading_section(
+            &vault_path,
+            &vault.join("A/A.md"),
+            "Missing",
+            "task",
+            false
+        )
+        .is_err()
+    );
+}
@@ -0,0 +1,65 @@
+//! Testing that the index section of core notes is regenerated from the peripheral notes of the cluster
+
+mod common;
+
+use common::{TestVault, write_note};
+use migration_rs::{cluster_note::*, common as comm, *};
+
+#[test]
+fn test_generate_index_for_core_note() {
+    let test_vault = TestVault::new();
+    let cluster = test_vault.path().join("000 Cluster");
+    let core_note_path = cluster.join("000 Cluster.md");
+
+    write_note(
+        &core_note_path,
+        &format!("# Objective\n\nText\n\n{CORE_NOTE_INDEX_START}\n{CORE_NOTE_INDEX_END}\n\nAfter"),
+    );
+    write_note(&cluster.join("tasks/001 Second.md"), "Task");
+    write_note(&cluster.join("tasks/000 First.md"), "Task");
+    write_note(&cluster.join("entries/000 Log.md"), "Entry");
+
+    let core_note = CoreNoteFilePath::new(&core_note_path).unwrap();
+    let config = test_vault.vault_path().config;
+
+    assert!(cluster_note_io::generate_index_for_core_note(&core_note, &config).unwrap());
+
+    let content = comm::read_file_content(&core_note_path).unwrap();
+
+    assert_eq!(
+        content,
+        [
+            "# Objective",
+            "",
+            "Text",
+            "",
+            CORE_NOTE_INDEX_START,
+            "",
+            "- Entries",
+            "  - [[000 Log]]",
+            "- Tasks",
+            "  - [[000 First]]",
+            "  - [[001 Second]]",
+            "",
+            CORE_NOTE_INDEX_END,
+            "",
+            "After",
+        ]
+        .join("\n")
+    );
+
+    // Regenerating is stable, and writeback leaves the index alone
+    assert!(!cluster_note_io::generate_index_for_core_note(&core_note, &config).unwrap());
+    assert_eq!(comm::writeback_markdown_content(&content).unwrap(), content);
+
+    // Notes without an index section are left alone
+    let other_cluster = test_vault.path().join("001 Other");
+    let other_core_note_path = other_cluster.join("001 Other.md");
+
+    write_note(&other_core_note_path, "No index");
+    write_note(&other_cluster.join("tasks/000 Task.md"), "Task");
+
+    let other_core_note = CoreNoteFilePath::new(&other_core_note_path).unwrap();
+
+    assert!(!cluster_note_io::generate_index_for_core_note(&other_core_note, &config).unwrap());
+}
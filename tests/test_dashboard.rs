@@ -0,0 +1,132 @@
+//! Testing the status summary of doer notes, per cluster and written into core notes
+
+mod common;
+
+use common::{TestVault, write_note};
+use migration_rs::{common as comm, *};
+use std::{
+    fs,
+    time::{Duration, SystemTime},
+};
+
+#[test]
+fn test_status_report() {
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+
+    let cluster = vault.join("Cluster");
+
+    write_note(
+        &cluster.join("Cluster.md"),
+        "Core\n\n%% index start %%\n\n%% index end %%\n",
+    );
+    write_note(
+        &cluster.join("tasks/000 Old.md"),
+        "---\nstatus: todo\n---\n",
+    );
+    write_note(
+        &cluster.join("tasks/001 New.md"),
+        "---\nstatus: \"doing\"\n---\n",
+    );
+    write_note(
+        &cluster.join("issues/000 Fixed.md"),
+        "---\nstatus: Done\n---\n",
+    );
+    write_note(&cluster.join("issues/001 Untracked.md"), "No status");
+
+    // Ideas are not doers
+    write_note(
+        &cluster.join("ideas/000 Idea.md"),
+        "---\nstatus: todo\n---\n",
+    );
+
+    fs::File::options()
+        .write(true)
+        .open(cluster.join("tasks/000 Old.md"))
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(40 * 24 * 60 * 60))
+        .unwrap();
+
+    let vault_path = test_vault.vault_path();
+
+    let report = dashboard::get_status_report(&vault_path, 30).unwrap();
+
+    assert_eq!(report.clusters.len(), 1);
+
+    let cluster_status = &report.clusters[0];
+
+    assert_eq!(
+        cluster_status
+            .counts_by_status
+            .iter()
+            .map(|(status, count)| (status.as_str(), *count))
+            .collect::<Vec<_>>(),
+        [("Done", 1), ("doing", 1), ("none", 1), ("todo", 1)]
+    );
+    assert_eq!(
+        cluster_status
+            .open_notes
+            .iter()
+            .map(|doer_note| doer_note.name())
+            .collect::<Vec<_>>(),
+        ["001 Untracked", "000 Old", "001 New"]
+    );
+    assert_eq!(
+        cluster_status
+            .stale_notes
+            .iter()
+            .map(|doer_note| doer_note.name())
+            .collect::<Vec<_>>(),
+        ["000 Old"]
+    );
+
+    mutation::apply_mutations(
+        &vault_path,
+        "status",
+        dashboard::get_write_status_mutations(&vault_path, &report).unwrap(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        comm::read_file_content(&cluster.join("Cluster.md")).unwrap(),
+        "Core\n\n%% index start %%\n\n%% index end %%\n\n%% status start %%\n\n\
+         - Status\n  - Done: 1\n  - doing: 1\n  - none: 1\n  - todo: 1\n\
+         - Open\n  - [[001 Untracked]] (none)\n  - [[000 Old]] (todo)\n  - [[001 New]] (doing)\n\n\
+         %% status end %%\n"
+    );
+
+    // Writing again changes nothing
+    let report = dashboard::get_status_report(&vault_path, 30).unwrap();
+
+    assert!(
+        dashboard::get_write_status_mutations(&vault_path, &report)
+            .unwrap()
+            .is_empty()
+    );
+
+    // Once the last doer note leaves, the section goes
+    for doer_note in [
+        "tasks/000 Old.md",
+        "tasks/001 New.md",
+        "issues/000 Fixed.md",
+        "issues/001 Untracked.md",
+    ] {
+        fs::remove_file(cluster.join(doer_note)).unwrap();
+    }
+
+    let report = dashboard::get_status_report(&vault_path, 30).unwrap();
+
+    assert!(report.clusters.is_empty());
+
+    mutation::apply_mutations(
+        &vault_path,
+        "status",
+        dashboard::get_write_status_mutations(&vault_path, &report).unwrap(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        comm::read_file_content(&cluster.join("Cluster.md")).unwrap(),
+        "Core\n\n%% index start %%\n\n%% index end %%\n"
+    );
+}
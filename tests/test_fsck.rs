@@ -0,0 +1,102 @@
+//! Testing that fsck finds clusters breaking the rules and that its fixes can be undone through the journal
+
+mod common;
+
+use common::{TestVault, write_note};
+use migration_rs::{common as comm, fsck::FsckIssue, *};
+use std::fs;
+
+#[test]
+fn test_fsck_fix_and_undo() {
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+
+    let cluster = vault.join("000 Cluster");
+
+    write_note(&cluster.join("000 Cluster.md"), "Core");
+    write_note(&cluster.join("tasks/000 Task.md"), "No parent");
+    fs::create_dir_all(cluster.join("issues")).unwrap();
+
+    // Only holds a hidden file, so it is not empty
+    write_note(&cluster.join("entries/.gitkeep"), "");
+
+    let misnamed = vault.join("001 Other");
+
+    write_note(&misnamed.join("Misnamed.md"), "Core");
+    write_note(
+        &misnamed.join("tasks/000 Task.md"),
+        "---\nparent: \"[[Misnamed]]\"\n---\n\nTask",
+    );
+    write_note(
+        &vault.join("Ref.md"),
+        "See [[Misnamed|other]], [[001 Other/Misnamed]] and [[Misnamed.md#Heading]]",
+    );
+
+    // A plain folder named like a context type is not mistaken for a cluster
+    write_note(&vault.join("projects/Projects.md"), "Projects");
+    write_note(&vault.join("projects/tasks/Todo.md"), "Todo");
+
+    let vault_path = test_vault.vault_path();
+
+    let problems = fsck::fsck_vault(&vault_path).unwrap();
+
+    let issues = problems
+        .iter()
+        .map(|problem| {
+            (
+                problem.path.strip_prefix(vault).unwrap().to_path_buf(),
+                problem.issue.clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        issues,
+        [
+            ("000 Cluster/issues".into(), FsckIssue::EmptyCategoryFolder),
+            (
+                "000 Cluster/tasks/000 Task.md".into(),
+                FsckIssue::ParentNotCoreNote(None)
+            ),
+            (
+                "001 Other/Misnamed.md".into(),
+                FsckIssue::CoreNoteNotNamedAfterFolder
+            ),
+        ]
+    );
+
+    for problem in problems.iter() {
+        mutation::apply_mutations(&vault_path, "fsck", problem.fix.clone()).unwrap();
+    }
+
+    assert!(fsck::fsck_vault(&vault_path).unwrap().is_empty());
+    assert!(!cluster.join("issues").exists());
+    assert_eq!(
+        comm::get_file_frontmatter_note_property(&cluster.join("tasks/000 Task.md"), "parent"),
+        Some("000 Cluster".to_owned())
+    );
+    assert!(misnamed.join("001 Other.md").is_file());
+    assert_eq!(
+        comm::read_file_content(&vault.join("Ref.md")).unwrap(),
+        "See [[001 Other|other]], [[001 Other/001 Other]] and [[001 Other.md#Heading]]"
+    );
+
+    for _ in 0..problems.len() {
+        assert!(
+            mutation::undo_last_mutations(&vault_path)
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    assert!(
+        mutation::undo_last_mutations(&vault_path)
+            .unwrap()
+            .is_none()
+    );
+    assert_eq!(fsck::fsck_vault(&vault_path).unwrap().len(), problems.len());
+    assert_eq!(
+        comm::read_file_content(&vault.join("Ref.md")).unwrap(),
+        "See [[Misnamed|other]], [[001 Other/Misnamed]] and [[Misnamed.md#Heading]]"
+    );
+}
@@ -0,0 +1,48 @@
+//! Testing that incremental runs pick up changed notes and the notes linking to them
+
+mod common;
+
+use common::{TestVault, write_note};
+use migration_rs::{common::ObsidianVaultPath, incremental::IncrementalRun, *};
+use std::collections::BTreeSet;
+
+#[test]
+fn test_incremental_run_dirty_notes() {
+    let test_vault = TestVault::new();
+    let vault_path = test_vault.path();
+
+    let target = vault_path.join("lan/000 Target.md");
+    let linking = vault_path.join("lan/001 Linking.md");
+    let unrelated = vault_path.join("lan/002 Unrelated.md");
+
+    write_note(&target, "Target\n");
+    write_note(&linking, "See [[000 Target#Heading|the target]]\n");
+    write_note(&unrelated, "Unrelated\n");
+
+    let vault = ObsidianVaultPath::new(vault_path).unwrap();
+    let paths = drivers::get_markdown_file_paths_in_vault(&vault, true);
+
+    // Everything is dirty on the first run
+    let run = IncrementalRun::start(&vault, "test", &paths);
+    assert_eq!(run.dirty_paths.len(), 3);
+    run.finish(&vault, &paths, &BTreeSet::from([unrelated.clone()]))
+        .unwrap();
+
+    // Failed notes stay dirty
+    let run = IncrementalRun::start(&vault, "test", &paths);
+    assert_eq!(run.dirty_paths, BTreeSet::from([unrelated.clone()]));
+    run.finish(&vault, &paths, &BTreeSet::new()).unwrap();
+
+    let run = IncrementalRun::start(&vault, "test", &paths);
+    assert!(run.dirty_paths.is_empty());
+
+    // Changing a note also dirties the notes linking to it
+    write_note(&target, "Target changed\n");
+
+    let run = IncrementalRun::start(&vault, "test", &paths);
+    assert_eq!(run.dirty_paths, BTreeSet::from([target, linking]));
+
+    // State is kept per command
+    let run = IncrementalRun::start(&vault, "other", &paths);
+    assert_eq!(run.dirty_paths.len(), 3);
+}
@@ -0,0 +1,108 @@
+//! Testing the mutation journal: what it records, how it recovers an interrupted command and how long it grows
+
+mod common;
+
+use common::{TestVault, read, write_note};
+use migration_rs::mutation::*;
+use std::fs;
+
+#[test]
+fn test_interrupted_mutations_are_undone() {
+    let test_vault = TestVault::new();
+    let vault_path = test_vault.vault_path();
+    let note = test_vault.path().join("Note.md");
+
+    write_note(&note, "Old");
+
+    // As if the command stopped right after the first of its mutations
+    let mutations = vec![
+        Mutation::write_file(&note, "New"),
+        Mutation::write_file(&test_vault.path().join("Other.md"), "Other"),
+    ];
+
+    MutationJournal {
+        entries: vec![JournalEntry {
+            command: "move".to_owned(),
+            timestamp_secs: 0,
+            mutations,
+            applied: 0,
+        }],
+    }
+    .save(&vault_path)
+    .unwrap();
+
+    write_note(&note, "New");
+    write_note(&MutationJournal::progress_path_in_vault(&vault_path), "+\n");
+
+    assert_eq!(
+        MutationJournal::load(&vault_path).unwrap().entries[0].applied,
+        1
+    );
+
+    let entry = undo_last_mutations(&vault_path).unwrap().unwrap();
+
+    assert_eq!(entry.applied, 1);
+    assert_eq!(read(&note), "Old");
+    assert!(!MutationJournal::progress_path_in_vault(&vault_path).exists());
+    assert!(
+        MutationJournal::load(&vault_path)
+            .unwrap()
+            .entries
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_journal_is_pruned() {
+    let test_vault = TestVault::new();
+    let vault_path = test_vault.vault_path();
+
+    for index in 0..MAX_JOURNAL_ENTRIES + 2 {
+        apply_mutations(
+            &vault_path,
+            &format!("command {index}"),
+            vec![Mutation::write_file(
+                &test_vault.path().join(format!("{index}.md")),
+                "Note",
+            )],
+        )
+        .unwrap();
+    }
+
+    let journal = MutationJournal::load(&vault_path).unwrap();
+
+    assert_eq!(journal.entries.len(), MAX_JOURNAL_ENTRIES);
+    assert_eq!(journal.entries[0].command, "command 2");
+    assert!(journal.entries.iter().all(|entry| entry.applied == 1));
+    assert!(!MutationJournal::progress_path_in_vault(&vault_path).exists());
+
+    // A conflict stops the command with the mutations before it journaled
+    let note = test_vault.path().join("0.md");
+
+    let result = apply_mutations(
+        &vault_path,
+        "conflicting",
+        vec![
+            Mutation::write_file(&test_vault.path().join("New.md"), "New"),
+            Mutation::RemoveFile {
+                path: note.clone(),
+                content: "Changed".to_owned(),
+            },
+        ],
+    );
+
+    assert!(matches!(
+        result,
+        Err(MutationJournalError::Conflict { index: 1, .. })
+    ));
+    assert_eq!(
+        MutationJournal::load(&vault_path)
+            .unwrap()
+            .entries
+            .last()
+            .unwrap()
+            .applied,
+        1
+    );
+    assert!(fs::exists(&note).unwrap());
+}
@@ -0,0 +1,151 @@
+//! Testing numeric prefixes of peripheral notes and renumbering them with links updated
+
+mod common;
+
+use common::{TestVault, write_note};
+use migration_rs::{common as comm, vault_config::NumberingScope, *};
+use std::path::Path;
+
+#[test]
+fn test_renumbered_names() {
+    assert_eq!(
+        numbering::parse_numeric_prefix("007 Title"),
+        Some((7, "Title"))
+    );
+    assert_eq!(numbering::parse_numeric_prefix("2025 Title"), None);
+    assert_eq!(numbering::parse_numeric_prefix("Title"), None);
+
+    let stems = ["000 A", "000 B", "003 C", "D"].map(str::to_owned);
+
+    assert_eq!(
+        numbering::get_renumbered_names(&stems),
+        [
+            ("000 B".to_owned(), "004 B".to_owned()),
+            ("D".to_owned(), "005 D".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn test_renumber_vault() {
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+
+    for cluster in ["2025/000 A", "2025/001 B"] {
+        let name = Path::new(cluster).file_name().unwrap().to_str().unwrap();
+
+        write_note(&vault.join(cluster).join(format!("{name}.md")), "Core");
+    }
+
+    write_note(&vault.join("2025/000 A/tasks/000 First.md"), "First");
+    write_note(&vault.join("2025/000 A/tasks/Unnumbered.md"), "Second");
+    write_note(&vault.join("2025/001 B/tasks/000 Other.md"), "Other");
+    write_note(
+        &vault.join("Ref.md"),
+        "[[Unnumbered]] [[2025/001 B/tasks/000 Other|other]]",
+    );
+
+    let vault_path = test_vault.vault_path();
+
+    // Per folder, only the unnumbered note needs a number
+    let mutations = numbering::plan_renumber(&vault_path, None).unwrap();
+
+    mutation::apply_mutations(&vault_path, "renumber", mutations).unwrap();
+
+    assert!(vault.join("2025/000 A/tasks/001 Unnumbered.md").is_file());
+    assert_eq!(
+        comm::read_file_content(&vault.join("Ref.md")).unwrap(),
+        "[[001 Unnumbered]] [[2025/001 B/tasks/000 Other|other]]"
+    );
+
+    assert_eq!(
+        numbering::get_next_peripheral_note_name(
+            &vault_path,
+            &vault.join("2025/000 A/tasks"),
+            "New"
+        ),
+        Some("002 New".to_owned())
+    );
+
+    // Per year, the notes of both clusters share one sequence
+    let mutations = numbering::plan_renumber(&vault_path, Some(NumberingScope::Year)).unwrap();
+
+    mutation::apply_mutations(&vault_path, "renumber", mutations).unwrap();
+
+    assert!(vault.join("2025/001 B/tasks/002 Other.md").is_file());
+    assert_eq!(
+        comm::read_file_content(&vault.join("Ref.md")).unwrap(),
+        "[[001 Unnumbered]] [[2025/001 B/tasks/002 Other|other]]"
+    );
+
+    mutation::undo_last_mutations(&vault_path).unwrap().unwrap();
+
+    assert!(vault.join("2025/001 B/tasks/000 Other.md").is_file());
+}
+
+#[test]
+fn test_renumber_year_with_shared_names() {
+    let test_vault = TestVault::new()
+        .with_note("2025/A/A.md", "A")
+        .with_note("2025/A/tasks/000 Setup.md", "Setup A")
+        .with_note("2025/B/B.md", "B")
+        .with_note("2025/B/tasks/000 Setup.md", "Setup B")
+        .with_note(
+            "Ref.md",
+            "[[2025/A/tasks/000 Setup|a]] [[2025/B/tasks/000 Setup|b]] [[000 Setup]]",
+        );
+    let vault = test_vault.path();
+    let vault_path = test_vault.vault_path();
+
+    let mutations = numbering::plan_renumber(&vault_path, Some(NumberingScope::Year)).unwrap();
+
+    mutation::apply_mutations(&vault_path, "renumber", mutations).unwrap();
+
+    // Only the second note moves, and links by name alone could mean either, so they stay
+    assert!(vault.join("2025/A/tasks/000 Setup.md").is_file());
+    assert!(vault.join("2025/B/tasks/001 Setup.md").is_file());
+    assert_eq!(
+        comm::read_file_content(&vault.join("Ref.md")).unwrap(),
+        "[[2025/A/tasks/000 Setup|a]] [[2025/B/tasks/001 Setup|b]] [[000 Setup]]"
+    );
+}
+
+#[test]
+fn test_extracted_entries_are_numbered() {
+    let test_vault = TestVault::new()
+        .with_note("A/A.md", "A\n\n# Tasks\n\n## Fix it\n\nBody\n")
+        .with_note("A/tasks/000 First.md", "First");
+    let vault = test_vault.path();
+    let vault_path = test_vault.vault_path();
+
+    let note_path = vault.join("A/A.md");
+    let content = comm::read_file_content(&note_path).unwrap();
+    let events = comm::parse_markdown_file(&content);
+    let entries = cluster_note::get_note_old_format_entries(
+        &content,
+        &events,
+        &vault_path.config,
+        &cluster_note::detect_old_format_layout(&events, &vault_path.config),
+    )
+    .unwrap();
+
+    let mutations = cluster_note_io::create_new_peripheral_note_from_old_format_entry(
+        &vault_path,
+        &note_path,
+        &entries[0],
+    )
+    .unwrap();
+
+    mutation::apply_mutations(&vault_path, "extract", mutations).unwrap();
+
+    assert!(
+        comm::read_file_content(&vault.join("A/tasks/001 Fix it.md"))
+            .unwrap()
+            .contains("Body")
+    );
+    assert!(
+        !comm::read_file_content(&note_path)
+            .unwrap()
+            .contains("Body")
+    );
+}
@@ -1,9 +1,15 @@
 //! Testing that our obsidian patching of rendered markdown works correctly
+//!
+//! Test cases are read from `tests/fixtures/writeback`. See the README there for how to add and bless them.
 
+use itertools::Itertools;
 use migration_rs::*;
-use tap::prelude::*;
 
-use std::sync::Once;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::Once,
+};
 
 static G_INIT_ONCE: Once = Once::new();
 
@@ -13,419 +19,122 @@ pub fn init() {
     });
 }
 
+pub const FIXTURES_FOLDER: &str = "tests/fixtures/writeback";
+
 #[derive(Debug)]
-enum TestData<'a> {
-    Identical {
-        name: &'a str,
-        data: String,
-    },
-    Different {
-        name: &'a str,
-        data: String,
-        expected: String,
-    },
+struct Fixture {
+    name: String,
+    input_path: PathBuf,
+    expected_path: PathBuf,
 }
 
-fn get_test_data<'a>() -> Vec<TestData<'a>> {
-    vec![
-        TestData::Identical {
-            name: "frontmatter-000",
-            data: r#"
-                    @ ---
-                    @ status: todo
-                    @ ---
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-        },
-
-        TestData::Identical {
-            name: "frontmatter-001",
-            data: r#"
-                    @ ---
-                    @ parent: "[[000 Implement the Event Accumulator]]"
-                    @ spawned_by: "[[000 Implement the Event Accumulator]]"
-                    @ context_type: entry
-                    @ ---
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-        },
-
-        TestData::Identical {
-            name: "table-000",
-            data: r#"
-                    @ | Simple | Table | Example |
-                    @ | ------ | ----- | ------- |
-                    @ | 0      | 1     | 2       |
-                    @ | A      | B     | C       |
-                    @ | `D`    | `E`   | `F`     |
-                    @ | ⊕      | ☆     | ◯       |
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-        },
-
-        TestData::Identical {
-            name: "table-001",
-            data: r#"
-                    @ | Heading        | Meaning                                                                                                                                                                                                                                                                                                                                                                                     |
-                    @ | -------------- | ------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------- |
-                    @ | Objective      | Scope and purpose of the entire note file                                                                                                                                                                                                                                                                                                                                                   |
-                    @ | Journal        | Mostly sequential logs of progress towards our objective                                                                                                                                                                                                                                                                                                                                    |
-                    @ | Tasks          | Entries with a clear scope and measure of completion. Think "do X". They're operative.                                                                                                                                                                                                                                                                                                      |
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-        },
-
-        TestData::Identical {
-            name: "table-002",
-            data: r#"
-                    @ | Week | Day | Date           | 💫                                  | Highlight                                                                |
-                    @ | ---- | --- | -------------- | ----------------------------------- | ------------------------------------------------------------------------ |
-                    @ | 36   | Mon | 2025-09-01     | [[Summary-2025-09-01\|🕸️ </>]]     | Learn about wasmer                                                       |
-                    @ | 36   | Tue | [[2025-09-02]] | [[Summary-2025-09-02\|🕸️ </>]]<br> | Deploy obsidian notes to web                                             |
-                    @ | 36   | Wed | 2025-09-03     | ` `                                 |                                                                          |
-                    @ | 36   | Thu | [[2025-09-04]] | 🕹️ `</>`                           | Writing and research                                                     |
-                    @ | 36   | Fri | [[2025-09-05]] | <br>🕹️ `</>`                       | Learn about rust db ORM [diesel](https://docs.rs/diesel/latest/diesel/). |
-                    @ | 36   | Sat | [[2025-09-06]] | [[Summary-2025-09-06\|📚📈🎲]]      | Study probability theory                                                 |
-                    @ | 36   | Sun | 2025-09-07     | ` `                                 |                                                                          |
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-        },
-
-        TestData::Different {
-            name: "list-000",
-            data: r#"
-                    @ There is some text here.
-                    @
-                    @ *   **Some Text**: Some Description
-                    @ *   More Text
-                    @
-                    @ Something new
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-            expected: r#"
-                    @ There is some text here.
-                    @
-                    @ - **Some Text**: Some Description
-                    @ - More Text
-                    @
-                    @ Something new
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-        },
-
-        // Inner bullets are set to be 3 spaces with pulldown_cmark_to_cmark
-        TestData::Identical {
-            name: "list-001",
-            data: r#"
-                    @ 1. Some Item
-                    @    - And its description
-                    @ 2. Another Item
-                    @    - And another description
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-        },
-
-        // Nested bullets are expected to visually align after the "{n}. " of their parent numbered bullets
-        // This has expected rules in CommonMarkdown spec. See [list-items](https://spec.commonmark.org/0.31.2/#list-items).
-        TestData::Identical {
-            name: "list-002",
-            data: r#"
-                    @ 1. Item
-                    @    - Desc
-                    @ 2. Item
-                    @ 3. Item
-                    @ 4. Item
-                    @ 5. Item
-                    @ 6. Item
-                    @ 7. Item
-                    @ 8. Item
-                    @ 9. Item
-                    @ 10. Item
-                    @     - Desc
-                    @ 11. Item
-                    @ 12. Item
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-        },
-
-        TestData::Identical {
-            name: "quote-000",
-            data: r#"
-                    @ > Quotes should be preserved!
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-        },
-
-        // Presence of the block identifers changes the behavior here
-        TestData::Identical {
-            name: "quote-001",
-            data: r#"
-                    @ > dominant sequence transduction models
-                    @ >[[#^quote-paper]]
-                    @ ^keyword-000
-                    @
-                    @ > Achieving {N} BLEU on the WMT 2014 English-to-German translation task
-                    @ >[[#^quote-paper-paraphrase]]
-                    @ ^keyword-001
-                    @
-                    @ > sequence modeling and transduction problems such as language modeling and machine translation
-                    @ >[[#^quote-paper]]
-                    @ ^keyword-002
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-        },
-
-        // Need to use consistent spacing to avoid problems here with quotes
-        TestData::Identical {
-            name: "quote-002",
-            data: r#"
-                    @ > dominant sequence transduction models
-                    @ > [[#^quote-paper]]
-                    @
-                    @ > Achieving {N} BLEU on the WMT 2014 English-to-German translation task
-                    @ > [[#^quote-paper-paraphrase]]
-                    @
-                    @ > sequence modeling and transduction problems such as language modeling and machine translation
-                    @ > [[#^quote-paper]]
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-        },
-
-        TestData::Different {
-            name: "quote-003",
-            data: r#"
-                    @ > dominant sequence transduction models
-                    @ >[[#^quote-paper]]
-                    @ ^keyword-000
-                    @
-                    @ > Achieving {N} BLEU on the WMT 2014 English-to-German translation task
-                    @ >[[#^quote-paper-paraphrase]]
-                    @ ^keyword-001
-                    @
-                    @ > sequence modeling and transduction problems such as language modeling and machine translation
-                    @ > [[#^quote-paper]]
-                    @ ^keyword-002
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-            expected: r#"
-                    @ > dominant sequence transduction models
-                    @ >[[#^quote-paper]]
-                    @ ^keyword-000
-                    @
-                    @ > Achieving {N} BLEU on the WMT 2014 English-to-German translation task
-                    @ >[[#^quote-paper-paraphrase]]
-                    @ ^keyword-001
-                    @
-                    @ > sequence modeling and transduction problems such as language modeling and machine translation
-                    @ >[[#^quote-paper]]
-                    @ ^keyword-002
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-        },
-
-        TestData::Identical {
-            name: "quote-003",
-            data: r#"
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("                    ", ""),
-        },
-
-        // Note that pulldown_cmark does not give an event for the [^l2] line likely because it's unused.
-        TestData::Different {
-            name: "refs-000",
-            data: r#"
-                    @ # 1 Refs [^l1]
-                    @ [^l1]: https://www.google.com
-                    @ [^l2]: https://www.duckduckgo.com
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-            expected: r#"
-                    @ # 1 Refs [^l1]
-                    @ [^l1]: https://www.google.com
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-        },
-
-        // If the [^l1] line doesn't have a link, it will still be given as an event
-        TestData::Identical {
-            name: "refs-001",
-            data: r#"
-                    @ # 1 Refs
-                    @ [^l1]: Some Text
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-        },
+fn get_fixtures(fixtures_folder: &Path) -> Vec<Fixture> {
+    let dir_entries = common::get_and_categorize_dir_entries(fixtures_folder)
+        .expect("Failed to read the fixtures folder");
+
+    dir_entries
+        .into_iter()
+        .flat_map(|entry| match entry {
+            common::CategorizedDirEntry::Dir(dir_entry) => Some(dir_entry.path()),
+            _ => None,
+        })
+        .map(|case_folder| Fixture {
+            name: case_folder
+                .file_name()
+                .expect("Case folders have names")
+                .to_string_lossy()
+                .to_string(),
+            input_path: case_folder.join("input.md"),
+            expected_path: case_folder.join("expected.md"),
+        })
+        .sorted_by(|a, b| a.name.cmp(&b.name))
+        .collect::<Vec<_>>()
+}
 
-        // Unnecessary spacing at the end is trimmed, but lines should not collapse. We ran into an issue where
-        // the middle new line would be removed.
-        TestData::Different {
-            name: "spacing-000",
-            data: r#"
-                    @ Spawned by: [[Some Note]]
-                    @
-                    @ Spawned in: [[Some Note#^spawn-entry-000000|^spawn-entry-000000]]
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-            expected: r#"
-                    @ Spawned by: [[Some Note]]
-                    @
-                    @ Spawned in: [[Some Note#^spawn-entry-000000|^spawn-entry-000000]]
-                "#
-            .trim()
-            .replace("@ ", "")
-            .replace("@", "")
-            .replace("                    ", ""),
-        },
-    ]
+/// Rewrites the expected output of the fixture. Cases where writeback changes nothing only keep their input.
+fn bless_fixture(fixture: &Fixture, data: &str, new_data: &str) {
+    if data == new_data {
+        if fixture.expected_path.exists() {
+            std::fs::remove_file(&fixture.expected_path).expect("Failed to remove expected file");
+        }
+    } else {
+        common::write_file_content(new_data, &fixture.expected_path)
+            .expect("Failed to write expected file");
+    }
 }
 
 #[test]
 fn test_obsidian_patch_writeback() {
     init();
 
-    let tds = get_test_data();
-
-    for td in tds {
-        let manual_test_case_filtering_enabled = false;
-        let manual_test_case = "";
-
-        if manual_test_case_filtering_enabled {
-            match td {
-                TestData::Identical { name, .. } | TestData::Different { name, .. } => {
-                    if name != manual_test_case {
-                        continue;
-                    }
-                }
-            };
-        }
+    let bless = env::var_os("BLESS").is_some();
+    let opt_fixture_filter = env::var("FIXTURE").ok();
 
-        match td {
-            TestData::Identical { name, data } => {
-                let events = common::parse_markdown_file(&data);
+    let fixtures = get_fixtures(&Path::new(env!("CARGO_MANIFEST_DIR")).join(FIXTURES_FOLDER));
 
-                let new_data = common::render_events_to_common_markdown(&events)
-                    .expect("Failed to render back to common markdown")
-                    .pipe(|new_data| {
-                        common::adhoc_fix_rendered_markdown_output_for_obsidian(&data, &new_data)
-                    });
+    assert!(
+        !fixtures.is_empty(),
+        "No fixtures found in {FIXTURES_FOLDER}"
+    );
 
-                if data != new_data {
-                    println!("failed with test data: {name}");
+    let mut mut_failed_names = vec![];
 
-                    println!("\n<events>");
-                    for event in events.iter() {
-                        println!("{event:?}");
-                    }
-                    println!("</events>\n");
+    for fixture in fixtures {
+        if let Some(fixture_filter) = &opt_fixture_filter
+            && fixture.name != *fixture_filter
+        {
+            continue;
+        }
 
-                    println!("\n<old>");
-                    println!("{data}");
-                    println!("</old>\n");
+        let name = &fixture.name;
 
-                    println!("\n<new>");
-                    println!("{new_data}");
-                    println!("</new>\n");
+        let data = common::read_file_content(&fixture.input_path)
+            .unwrap_or_else(|| panic!("Failed to read input for test case: {name}"));
 
-                    drivers::display_diff(&data, &new_data, drivers::DisplayDiffFrom::default());
+        let expected = match fixture.expected_path.exists() {
+            true => common::read_file_content(&fixture.expected_path)
+                .unwrap_or_else(|| panic!("Failed to read expected for test case: {name}")),
+            false => data.clone(),
+        };
 
-                    panic!("data does not match for test case: {name}");
-                }
-            }
-            TestData::Different {
-                name,
-                data,
-                expected,
-            } => {
-                let events = common::parse_markdown_file(&data);
+        let new_data = common::writeback_markdown_content(&data)
+            .expect("Failed to render back to common markdown");
 
-                let new_data = common::render_events_to_common_markdown(&events)
-                    .expect("Failed to render back to common markdown")
-                    .pipe(|new_data| {
-                        common::adhoc_fix_rendered_markdown_output_for_obsidian(&data, &new_data)
-                    });
+        if bless {
+            bless_fixture(&fixture, &data, &new_data);
+            continue;
+        }
 
-                if expected != new_data {
-                    println!("failed with test case: {name}");
+        if expected != new_data {
+            println!("failed with test case: {name}");
 
-                    println!("\n<events>");
-                    for event in events.iter() {
-                        println!("{event:?}");
-                    }
-                    println!("</events>\n");
+            println!("\n<events>");
+            for event in common::parse_markdown_file(&data).iter() {
+                println!("{event:?}");
+            }
+            println!("</events>\n");
 
-                    println!("\n<old>");
-                    println!("{data}");
-                    println!("</old>\n");
+            println!("\n<old>");
+            println!("{data}");
+            println!("</old>\n");
 
-                    println!("\n<expected>");
-                    println!("{expected}");
-                    println!("</expected>\n");
+            println!("\n<expected>");
+            println!("{expected}");
+            println!("</expected>\n");
 
-                    println!("\n<new>");
-                    println!("{new_data}");
-                    println!("</new>\n");
+            println!("\n<new>");
+            println!("{new_data}");
+            println!("</new>\n");
 
-                    drivers::display_diff(&data, &new_data, drivers::DisplayDiffFrom::default());
+            println!("\n<diff expected new>");
+            drivers::display_diff(&expected, &new_data, drivers::DisplayDiffFrom::default());
+            println!("\n</diff>\n");
 
-                    panic!("data does not match for test case: {name}");
-                }
-            }
+            mut_failed_names.push(name.clone());
         }
     }
+
+    if !mut_failed_names.is_empty() {
+        panic!("data does not match for test cases: {mut_failed_names:?}");
+    }
 }
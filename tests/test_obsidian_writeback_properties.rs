@@ -0,0 +1,311 @@
+//! Property testing that writeback (parse, render and obsidian fix) is stable on generated obsidian markdown.
+//!
+//! Two properties are checked for every generated note:
+//! - Idempotence: running writeback on its own output changes nothing.
+//! - Meaning: the output parses to the same markdown events as the input.
+//!
+//! On failure, the input is shrunk to a minimal reproducer which is printed. With `SAVE_REPRODUCER` set, it is also
+//! saved as a new case in `tests/fixtures/writeback` to be blessed once writeback is fixed.
+
+use migration_rs::*;
+use proptest::{
+    prelude::*,
+    test_runner::{Config, TestCaseError, TestError, TestRunner},
+};
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::Once,
+};
+
+static G_INIT_ONCE: Once = Once::new();
+
+pub fn init() {
+    G_INIT_ONCE.call_once(|| {
+        drivers::init_logging_with_level(log::LevelFilter::Info);
+    });
+}
+
+const WORDS: [&str; 12] = [
+    "Some", "text", "here", "note", "Task", "done", "rust", "x", "0", "42", "⊕", "🕸️",
+];
+
+const NOTES: [&str; 4] = [
+    "Some Note",
+    "000 Note Repo Migration Sept 8",
+    "2025-09-02",
+    "Summary-2025-09-01",
+];
+
+fn words(max: usize) -> impl Strategy<Value = String> {
+    prop::collection::vec(prop::sample::select(&WORDS[..]), 1..max)
+        .prop_map(|words| words.join(" "))
+}
+
+fn wikilink(in_table: bool) -> impl Strategy<Value = String> {
+    let bar = if in_table { "\\|" } else { "|" };
+
+    (
+        prop::sample::select(&NOTES[..]),
+        prop::option::of(words(3)),
+        prop::option::of(words(3)),
+    )
+        .prop_map(move |(note, opt_sublink, opt_title)| {
+            let sublink = opt_sublink.map(|s| format!("#{s}")).unwrap_or_default();
+            let title = opt_title.map(|s| format!("{bar}{s}")).unwrap_or_default();
+
+            format!("[[{note}{sublink}{title}]]")
+        })
+}
+
+fn inline(in_table: bool) -> impl Strategy<Value = String> {
+    prop_oneof![
+        4 => words(5),
+        1 => words(3).prop_map(|s| format!("`{s}`")),
+        1 => wikilink(in_table),
+        1 => words(3).prop_map(|s| format!("**{s}**")),
+        1 => Just("$x_1 + y_2$".to_owned()),
+    ]
+}
+
+fn inline_line(in_table: bool) -> impl Strategy<Value = String> {
+    prop::collection::vec(inline(in_table), 1..4).prop_map(|parts| parts.join(" "))
+}
+
+fn frontmatter() -> impl Strategy<Value = String> {
+    prop::collection::vec(
+        (
+            prop::sample::select(&["status", "parent", "spawned_by", "context_type"][..]),
+            prop_oneof![
+                prop::sample::select(&["todo", "done", "entry", "task"][..])
+                    .prop_map(str::to_owned),
+                prop::sample::select(&NOTES[..]).prop_map(|note| format!("\"[[{note}]]\"")),
+            ],
+        ),
+        1..4,
+    )
+    .prop_map(|props| {
+        let lines = props
+            .into_iter()
+            .map(|(k, v)| format!("{k}: {v}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        format!("---\n{lines}\n---")
+    })
+}
+
+fn heading() -> impl Strategy<Value = String> {
+    (1..4usize, words(4)).prop_map(|(level, text)| format!("{} {text}", "#".repeat(level)))
+}
+
+fn paragraph() -> impl Strategy<Value = String> {
+    prop::collection::vec(inline_line(false), 1..3).prop_map(|lines| lines.join("\n"))
+}
+
+fn table() -> impl Strategy<Value = String> {
+    (1..4usize)
+        .prop_flat_map(|ncols| {
+            (
+                prop::collection::vec(words(2), ncols),
+                prop::collection::vec(prop::collection::vec(inline_line(true), ncols), 1..4),
+            )
+        })
+        .prop_map(|(header, rows)| {
+            let row_line = |cells: &[String]| format!("| {} |", cells.join(" | "));
+
+            let separator = header.iter().map(|_| "---".to_owned()).collect::<Vec<_>>();
+
+            let mut mut_lines = vec![row_line(&header), row_line(&separator)];
+
+            mut_lines.extend(rows.iter().map(|row| row_line(row)));
+
+            mut_lines.join("\n")
+        })
+}
+
+/// Obsidian uses tabs for nested list items
+fn list() -> impl Strategy<Value = String> {
+    let item = (
+        0..3usize,
+        prop::sample::select(&["- ", "- [ ] ", "- [x] "][..]),
+        inline_line(false),
+    );
+
+    prop::collection::vec(item, 1..6).prop_map(|items| {
+        let mut mut_max_depth = 0;
+
+        items
+            .into_iter()
+            .map(|(depth, marker, text)| {
+                // Nesting can only go one level deeper than the previous item
+                let depth = depth.min(mut_max_depth);
+                mut_max_depth = depth + 1;
+
+                format!("{}{marker}{text}", "\t".repeat(depth))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    })
+}
+
+fn ordered_list() -> impl Strategy<Value = String> {
+    prop::collection::vec(
+        (inline_line(false), prop::option::of(inline_line(false))),
+        1..4,
+    )
+    .prop_map(|items| {
+        items
+            .into_iter()
+            .enumerate()
+            .map(|(i, (text, opt_desc))| match opt_desc {
+                Some(desc) => format!("{}. {text}\n   - {desc}", i + 1),
+                None => format!("{}. {text}", i + 1),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    })
+}
+
+fn quote() -> impl Strategy<Value = String> {
+    prop::collection::vec(inline_line(false), 1..3).prop_map(|lines| {
+        lines
+            .iter()
+            .map(|line| format!("> {line}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    })
+}
+
+fn callout() -> impl Strategy<Value = String> {
+    (
+        prop::sample::select(&["note", "warning", "todo"][..]),
+        words(3),
+        prop::collection::vec(inline_line(false), 1..3),
+    )
+        .prop_map(|(kind, title, lines)| {
+            let body = lines
+                .iter()
+                .map(|line| format!("> {line}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            format!("> [!{kind}] {title}\n{body}")
+        })
+}
+
+fn math_block() -> impl Strategy<Value = String> {
+    prop::sample::select(&["x_1 + y_2", "\\sum_{i=0}^{n} a_i", "f(x) = x^2"][..])
+        .prop_map(|math| format!("$$\n{math}\n$$"))
+}
+
+/// Blocks are tagged with whether they are lists
+fn block() -> impl Strategy<Value = (bool, String)> {
+    prop_oneof![
+        heading().prop_map(|s| (false, s)),
+        paragraph().prop_map(|s| (false, s)),
+        table().prop_map(|s| (false, s)),
+        list().prop_map(|s| (true, s)),
+        ordered_list().prop_map(|s| (true, s)),
+        quote().prop_map(|s| (false, s)),
+        callout().prop_map(|s| (false, s)),
+        math_block().prop_map(|s| (false, s)),
+    ]
+}
+
+fn obsidian_note() -> impl Strategy<Value = String> {
+    (
+        prop::option::of(frontmatter()),
+        prop::collection::vec(block(), 1..6),
+    )
+        .prop_map(|(opt_frontmatter, blocks)| {
+            let mut mut_blocks = vec![];
+            let mut mut_last_is_list = false;
+
+            mut_blocks.extend(opt_frontmatter);
+
+            for (is_list, block) in blocks {
+                // Consecutive lists merge into one loose list, which pulldown_cmark_to_cmark does not render
+                // faithfully. So keep them apart.
+                if is_list && mut_last_is_list {
+                    mut_blocks.push("Some text".to_owned());
+                }
+
+                mut_blocks.push(block);
+                mut_last_is_list = is_list;
+            }
+
+            mut_blocks.join("\n\n")
+        })
+}
+
+pub const FIXTURES_FOLDER: &str = "tests/fixtures/writeback";
+
+/// Saves the reproducer as the input of a new `proptest-{NNN}` fixture and returns its path
+fn save_reproducer_fixture(data: &str) -> PathBuf {
+    let fixtures_folder = Path::new(env!("CARGO_MANIFEST_DIR")).join(FIXTURES_FOLDER);
+
+    let case_folder = (0..)
+        .map(|i| fixtures_folder.join(format!("proptest-{i:03}")))
+        .find(|case_folder| !case_folder.exists())
+        .expect("There is always a free case name");
+
+    std::fs::create_dir_all(&case_folder).expect("Failed to create fixture folder");
+
+    let input_path = case_folder.join("input.md");
+
+    common::write_file_content(data, &input_path).expect("Failed to write fixture input");
+
+    input_path
+}
+
+fn check_writeback_properties(data: &str) -> Result<(), TestCaseError> {
+    let new_data = common::writeback_markdown_content(data).map_err(|e| {
+        TestCaseError::fail(format!("Failed to render back to common markdown: {e:?}"))
+    })?;
+
+    let new_data2 = common::writeback_markdown_content(&new_data).map_err(|e| {
+        TestCaseError::fail(format!("Failed to render back to common markdown: {e:?}"))
+    })?;
+
+    prop_assert_eq!(
+        common::parse_markdown_file(data),
+        common::parse_markdown_file(&new_data),
+        "writeback changed the meaning of the note. Output:\n{}",
+        new_data
+    );
+
+    prop_assert_eq!(&new_data, &new_data2, "writeback is not idempotent");
+
+    Ok(())
+}
+
+#[test]
+fn test_obsidian_writeback_properties() {
+    init();
+
+    let mut runner = TestRunner::new(Config {
+        cases: 512,
+        failure_persistence: None,
+        ..Config::default()
+    });
+
+    match runner.run(&obsidian_note(), |data| check_writeback_properties(&data)) {
+        Ok(()) => {}
+        Err(TestError::Fail(reason, minimal_data)) => {
+            println!("\n<reproducer>");
+            println!("{minimal_data}");
+            println!("</reproducer>\n");
+
+            if env::var_os("SAVE_REPRODUCER").is_some() {
+                let input_path = save_reproducer_fixture(&minimal_data);
+
+                println!("Saved reproducer to {input_path:?}");
+            }
+
+            panic!("writeback properties do not hold: {reason}");
+        }
+        Err(TestError::Abort(reason)) => panic!("property test aborted: {reason}"),
+    }
+}
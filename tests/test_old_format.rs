@@ -0,0 +1,233 @@
+//! Testing that old format entries are found under headings with inline markup, at the heading levels of the note
+
+mod common;
+
+use migration_rs::{cluster_note::OldFormatLayout, common as comm, vault_config::VaultConfig, *};
+use pulldown_cmark::{Event, HeadingLevel};
+
+#[test]
+fn test_flatten_heading_events() {
+    // Without markdown extensions, wikilinks, strikethrough, math and footnotes are text
+    let content =
+        "## Fix `foo` crash in *[[Note|the note]]* and [docs](https://x.y) ~~now~~ $x$[^1]";
+
+    let events = comm::parse_markdown_file(content);
+
+    let heading = comm::parse_heading_events(&events).unwrap();
+
+    assert_eq!(heading.level, HeadingLevel::H2);
+    assert_eq!(
+        heading.text,
+        "Fix `foo` crash in *[[Note|the note]]* and [docs](https://x.y) ~~now~~ $x$[^1]"
+    );
+    assert!(
+        heading
+            .inline_events
+            .iter()
+            .any(|event| matches!(event, Event::Code(code) if code.as_ref() == "foo"))
+    );
+    assert_eq!(heading.event_count(), heading.inline_events.len() + 2);
+
+    // Empty headings are not linkable
+    assert!(comm::process_heading_event(&comm::parse_markdown_file("#\n")).is_none());
+}
+
+#[test]
+fn test_old_format_entries_with_rich_headings() {
+    let content = "# Notes\n\nIgnored\n\n# Tasks\n\n## Fix `foo` crash\n\nContent A\n\n### Details\n\nMore\n\n\
+                   # Issues\n\n## See [[Other]]\n\nContent B\n";
+
+    let events = comm::parse_markdown_file(content);
+
+    let entries = cluster_note::get_note_old_format_entries(
+        content,
+        &events,
+        &VaultConfig::default(),
+        &OldFormatLayout::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        entries
+            .iter()
+            .map(|entry| (
+                entry.context_type.folder.as_str(),
+                entry.entry_name.as_str()
+            ))
+            .collect::<Vec<_>>(),
+        [("tasks", "Fix `foo` crash"), ("issues", "See [[Other]]"),]
+    );
+    assert_eq!(entries[0].heading_events.len(), 3);
+    assert!(
+        entries[0]
+            .events
+            .iter()
+            .any(|event| matches!(event, Event::Text(text) if text.as_ref() == "More"))
+    );
+}
+
+#[test]
+fn test_old_format_entry_names_keep_their_source() {
+    let content = "# Tasks\n\n## Fix _foo_ in __bar__ ##\n\nContent\n";
+
+    let events = comm::parse_markdown_file(content);
+
+    let entries = cluster_note::get_note_old_format_entries(
+        content,
+        &events,
+        &VaultConfig::default(),
+        &OldFormatLayout::default(),
+    )
+    .unwrap();
+
+    // Flattened back from the events, the emphasis would read `*foo*` and `**bar**`
+    assert_eq!(entries[0].entry_name, "Fix _foo_ in __bar__");
+}
+
+#[test]
+fn test_old_format_errors_are_located() {
+    let path = std::path::Path::new("vault/Note.md");
+
+    // Content under an old format H1 but before any entry heading
+    let content = "# Intro\n\nText\n\n# Tasks\n\nStray `content`\n\n## Entry\n\nA\n";
+
+    let events = comm::parse_markdown_file(content);
+
+    assert_eq!(
+        comm::get_markdown_event_offsets(content).len(),
+        events.len()
+    );
+
+    let error = cluster_note::get_note_old_format_entries_located(
+        path,
+        content,
+        &events,
+        &VaultConfig::default(),
+        &OldFormatLayout::default(),
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        error.location,
+        comm::SourceLocation {
+            path: path.to_path_buf(),
+            line: 7,
+            column: 1
+        }
+    );
+    assert!(matches!(
+        &error.error,
+        cluster_note::GetNoteOldFormatEntriesError::EventTypeAndNameNotConfigured {
+            heading_context,
+            ..
+        } if heading_context == &["Tasks"]
+    ));
+    assert!(error.to_string().starts_with("vault/Note.md:7:1: "));
+
+    // An empty entry heading used to panic
+    let content = "# Tasks\n\n## A\n\nContent\n\n  ##\n\nMore\n";
+
+    let events = comm::parse_markdown_file(content);
+
+    let error = cluster_note::get_note_old_format_entries_located(
+        path,
+        content,
+        &events,
+        &VaultConfig::default(),
+        &OldFormatLayout::default(),
+    )
+    .unwrap_err();
+
+    assert_eq!((error.location.line, error.location.column), (7, 3));
+    assert!(matches!(
+        &error.error,
+        cluster_note::GetNoteOldFormatEntriesError::UnparsableHeading {
+            heading_context,
+            error: comm::ProcessHeadingEventError::EmptyHeading,
+            ..
+        } if heading_context == &["Tasks", "A"]
+    ));
+}
+
+#[test]
+fn test_old_format_entries_under_a_title() {
+    let content = "# Project\n\nIntro\n\n## Tasks\n\n### Fix crash\n\nContent A\n\n#### Details\n\nMore\n\n\
+                   ## Links\n\n### Not an entry\n\nContent B\n\n## Issues\n\n### Slow start\n\nContent C\n\n\
+                   # Appendix\n\n### Not an entry either\n\nContent D\n";
+
+    let events = comm::parse_markdown_file(content);
+
+    let config = VaultConfig::default();
+
+    let layout = cluster_note::detect_old_format_layout(&events, &config);
+
+    assert_eq!(
+        layout,
+        OldFormatLayout {
+            category_level: HeadingLevel::H2,
+            entry_level: HeadingLevel::H3,
+        }
+    );
+
+    let entries =
+        cluster_note::get_note_old_format_entries(content, &events, &config, &layout).unwrap();
+
+    assert_eq!(
+        entries
+            .iter()
+            .map(|entry| (
+                entry.context_type.folder.as_str(),
+                entry.entry_name.as_str()
+            ))
+            .collect::<Vec<_>>(),
+        [("tasks", "Fix crash"), ("issues", "Slow start"),]
+    );
+    assert!(
+        entries[0]
+            .events
+            .iter()
+            .any(|event| matches!(event, Event::Text(text) if text.as_ref() == "More"))
+    );
+
+    // Read with the default layout, the title is the only category and nothing is an entry
+    assert!(
+        cluster_note::get_note_old_format_entries(
+            content,
+            &events,
+            &config,
+            &OldFormatLayout::default()
+        )
+        .unwrap()
+        .is_empty()
+    );
+
+    // Notes without old format headings keep the default layout
+    assert_eq!(
+        cluster_note::detect_old_format_layout(
+            &comm::parse_markdown_file("# Notes\n\n## A\n"),
+            &config
+        ),
+        OldFormatLayout::default()
+    );
+    assert_eq!(OldFormatLayout::with_category_level(HeadingLevel::H6), None);
+}
+
+#[test]
+fn test_block_identifiers_keep_their_caret() {
+    let events = comm::parse_markdown_file(
+        "Spawn [[Other]] ^spawn-task-0a1b2c\n\nPrice is 2^ 3 ^ not one\n",
+    );
+
+    let block_identifiers = comm::extract_linkable_obsidian_md_items(&events)
+        .into_iter()
+        .flat_map(|item| match item.item_data {
+            comm::ObsidianLinkableData::BlockIdentifier(block_identifier) => {
+                Some(block_identifier.text)
+            }
+            comm::ObsidianLinkableData::Heading(..) => None,
+        })
+        .collect::<Vec<_>>();
+
+    // Identifiers are written with their caret, the way links name them after `#`
+    assert_eq!(block_identifiers, ["^spawn-task-0a1b2c"]);
+}
@@ -0,0 +1,140 @@
+//! Testing that files processed in parallel keep their order, and that failures and panics are collected per file
+
+use migration_rs::drivers::{self, ProcessFileError};
+use std::{
+    panic,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+/// The panic hook is global, so the test that replaces it can't run alongside the others
+static PANIC_HOOK_LOCK: Mutex<()> = Mutex::new(());
+
+fn get_paths(count: usize) -> Vec<PathBuf> {
+    (0..count)
+        .map(|i| PathBuf::from(format!("{i:03}.md")))
+        .collect()
+}
+
+fn get_number(path: &Path) -> usize {
+    path.file_stem().unwrap().to_str().unwrap().parse().unwrap()
+}
+
+#[test]
+fn test_results_keep_the_order_of_paths() {
+    let _lock = PANIC_HOOK_LOCK.lock().unwrap_or_else(|p| p.into_inner());
+
+    let paths = get_paths(40);
+
+    let processed_files = drivers::process_files_parallel(paths.clone(), 4, |path| {
+        let number = get_number(path);
+
+        // Later files finish first
+        thread::sleep(Duration::from_micros(((40 - number) * 50) as u64));
+
+        Ok::<_, ()>(number * 2)
+    });
+
+    assert_eq!(
+        processed_files
+            .iter()
+            .map(|processed_file| processed_file.path.clone())
+            .collect::<Vec<_>>(),
+        paths
+    );
+    assert!(
+        processed_files
+            .iter()
+            .all(|processed_file| processed_file.result.as_ref().ok()
+                == Some(&(get_number(&processed_file.path) * 2)))
+    );
+}
+
+#[test]
+fn test_failures_are_collected() {
+    let _lock = PANIC_HOOK_LOCK.lock().unwrap_or_else(|p| p.into_inner());
+
+    let processed_files =
+        drivers::process_files_parallel(get_paths(10), 3, |path| match get_number(path) % 3 {
+            0 => Err(format!("{path:?} failed")),
+            _ => Ok(()),
+        });
+
+    assert_eq!(processed_files.len(), 10);
+    assert_eq!(drivers::log_processed_files(&processed_files), 4);
+    assert!(matches!(
+        &processed_files[3].result,
+        Err(ProcessFileError::Failed(e)) if e == "\"003.md\" failed"
+    ));
+}
+
+#[test]
+fn test_panics_are_collected_without_the_panic_hook() {
+    let _lock = PANIC_HOOK_LOCK.lock().unwrap_or_else(|p| p.into_inner());
+
+    let processed_files = drivers::process_files_parallel(get_paths(6), 2, |path| {
+        if get_number(path) == 4 {
+            panic!("Bad file {path:?}");
+        }
+
+        Ok::<_, ()>(())
+    });
+
+    assert_eq!(
+        processed_files
+            .iter()
+            .filter(|processed_file| processed_file.result.is_ok())
+            .count(),
+        5
+    );
+    assert!(matches!(
+        &processed_files[4].result,
+        Err(ProcessFileError::Panicked { message, opt_location: Some(location) })
+            if message == "Bad file \"004.md\""
+                && location.starts_with("tests/test_process_files_parallel.rs:")
+    ));
+}
+
+#[test]
+fn test_panic_hooks_installed_later_are_kept() {
+    let _lock = PANIC_HOOK_LOCK.lock().unwrap_or_else(|p| p.into_inner());
+
+    // The worker hook is in place once files were processed
+    drivers::process_files_parallel(get_paths(1), 1, |_| Ok::<_, ()>(()));
+
+    let hook_calls = Arc::new(AtomicUsize::new(0));
+
+    let previous_hook = panic::take_hook();
+
+    panic::set_hook({
+        let hook_calls = hook_calls.clone();
+
+        Box::new(move |_| {
+            hook_calls.fetch_add(1, Ordering::SeqCst);
+        })
+    });
+
+    let processed_files = drivers::process_files_parallel(get_paths(2), 2, |path| {
+        if get_number(path) == 1 {
+            panic!("Bad file {path:?}");
+        }
+
+        Ok::<_, ()>(())
+    });
+
+    // The later hook sees the panic, the message is still collected, and the hook stays in place after the run
+    assert!(matches!(
+        &processed_files[1].result,
+        Err(ProcessFileError::Panicked { message, .. }) if message == "Bad file \"001.md\""
+    ));
+    assert_eq!(hook_calls.load(Ordering::SeqCst), 1);
+    assert!(thread::spawn(|| panic!("Elsewhere")).join().is_err());
+    assert_eq!(hook_calls.load(Ordering::SeqCst), 2);
+
+    panic::set_hook(previous_hook);
+}
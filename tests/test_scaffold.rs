@@ -0,0 +1,129 @@
+//! Testing that new clusters and peripheral notes are created from the templates of the vault
+
+mod common;
+
+use common::{TestVault, read, write_note};
+use migration_rs::{common as comm, *};
+use std::{
+    fs,
+    time::{Duration, SystemTime},
+};
+
+#[test]
+fn test_format_date() {
+    assert_eq!(comm::format_date(SystemTime::UNIX_EPOCH), "1970-01-01");
+    assert_eq!(
+        comm::format_date(SystemTime::UNIX_EPOCH + Duration::from_secs(19_782 * 86_400 + 3_600)),
+        "2024-02-29"
+    );
+    assert_eq!(
+        comm::format_date(SystemTime::UNIX_EPOCH + Duration::from_secs(11_016 * 86_400)),
+        "2000-02-29"
+    );
+}
+
+#[test]
+fn test_render_template() {
+    let variables = scaffold::TemplateVariables {
+        date: "2025-01-02".to_owned(),
+        title: "Deploy: prod".to_owned(),
+        name: "003 Deploy prod".to_owned(),
+        parent: "[[Cluster]]".to_owned(),
+        number: "003".to_owned(),
+        block_id: "^spawn-task-0a1b2c".to_owned(),
+    };
+
+    assert_eq!(
+        scaffold::render_template(
+            "{{ number }} {{title}} {{unknown}}\n<% tp.file.title %> <%* tp.date.now(\"YYYY-MM-DD\") %> <% tp.file.folder() %>",
+            &variables
+        ),
+        "003 Deploy: prod {{unknown}}\n003 Deploy prod 2025-01-02 <% tp.file.folder() %>"
+    );
+}
+
+#[test]
+fn test_new_cluster_and_peripheral_note() {
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+    fs::create_dir_all(vault.join("projects")).unwrap();
+
+    write_note(
+        &vault.join("templater/tasks.md"),
+        "---\nparent: \"{{parent}}\"\nstatus: todo\n---\n\n{{number}} started {{date}} {{block_id}}\n",
+    );
+
+    let vault_path = test_vault.vault_path();
+
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(19_782 * 86_400);
+
+    let mutations =
+        scaffold::plan_new_cluster(&vault_path, &vault.join("projects"), "Web: site", now).unwrap();
+
+    mutation::apply_mutations(&vault_path, "new_cluster", mutations).unwrap();
+
+    let core_note = vault.join("projects/Web site/Web site.md");
+
+    assert_eq!(
+        read(&core_note),
+        "---\ncreated: 2024-02-29\n---\n\n# Web: site\n\n%% index start %%\n\n%% index end %%\n"
+    );
+    assert_eq!(
+        cluster_note::is_cluster_root_folder(&vault.join("projects/Web site")),
+        Some(true)
+    );
+
+    // The cluster is given by its root folder, and the type by its singular name
+    let mutations = scaffold::plan_new_peripheral_note(
+        &vault_path,
+        &vault.join("projects/Web site"),
+        "Task",
+        "Deploy",
+        now,
+    )
+    .unwrap();
+
+    mutation::apply_mutations(&vault_path, "new_peripheral", mutations).unwrap();
+
+    let task = read(&vault.join("projects/Web site/tasks/000 Deploy.md"));
+
+    assert!(task.starts_with(
+        "---\nparent: \"[[Web site]]\"\nstatus: todo\n---\n\n000 started 2024-02-29 ^spawn-task-"
+    ));
+
+    // The core note gets the spawn marker the block identifier of the note points to
+    let block_id = task
+        .split_whitespace()
+        .find(|word| word.starts_with("^spawn-task-"))
+        .unwrap();
+    assert_eq!(
+        cluster_note::is_cluster_category_folder(
+            &vault.join("projects/Web site/tasks"),
+            &vault_path.config
+        ),
+        Some(true)
+    );
+
+    // Without a template for the context type, the default one is used
+    let mutations =
+        scaffold::plan_new_peripheral_note(&vault_path, &core_note, "ideas", "Dark mode", now)
+            .unwrap();
+
+    mutation::apply_mutations(&vault_path, "new_peripheral", mutations).unwrap();
+
+    assert_eq!(
+        read(&vault.join("projects/Web site/ideas/000 Dark mode.md")),
+        "---\nparent: \"[[Web site]]\"\ncreated: 2024-02-29\n---\n\n# Dark mode\n"
+    );
+    assert_eq!(
+        read(&core_note),
+        format!(
+            "---\ncreated: 2024-02-29\n---\n\n# Web: site\n\nSpawn [[000 Deploy]] {block_id}\n\n\
+             %% index start %%\n\n- Ideas\n  - [[000 Dark mode]]\n- Tasks\n  - [[000 Deploy]]\n\n%% index end %%\n"
+        )
+    );
+
+    assert!(
+        scaffold::plan_new_cluster(&vault_path, &vault.join("projects"), "Web site", now).is_err()
+    );
+}
@@ -0,0 +1,48 @@
+//! Testing the migration statistics of a vault
+
+mod common;
+
+use common::{TestVault, write_note};
+use migration_rs::*;
+
+#[test]
+fn test_vault_stats() {
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+
+    let cluster = vault.join("000 Cluster");
+
+    write_note(
+        &cluster.join("000 Cluster.md"),
+        "# Objective\n\nSpawn [[000 Plain]] ^spawn-task-0a1b2c\n",
+    );
+    write_note(&cluster.join("tasks/000 Task.md"), "Task");
+    write_note(&cluster.join("tasks/001 Task.md"), "Task");
+    write_note(&cluster.join("entries/000 Entry.md"), "Entry");
+    write_note(&cluster.join("diagram.png"), "");
+    write_note(
+        &vault.join("000 Plain.md"),
+        "From [[000 Cluster#^spawn-task-0a1b2c]] in [[000 Cluster]]\n\n[site](https://example.com)",
+    );
+    write_note(
+        &vault.join("old note.md"),
+        "# Old\n\n# Tasks\n\n## Task A\n\nA\n\n# Issues\n\n## Issue B\n\nB\n",
+    );
+    write_note(&vault.join("Board Kanban.md"), "");
+
+    let vault_path = test_vault.vault_path();
+    let vault_stats = stats::get_vault_stats(&vault_path).unwrap();
+
+    assert_eq!(vault_stats.normal_notes, 2);
+    assert_eq!(vault_stats.clusters, 1);
+    assert_eq!(vault_stats.peripheral_notes(), 3);
+    assert_eq!(vault_stats.peripheral_notes_per_category["tasks"], 2);
+    assert_eq!(vault_stats.attachments, 1);
+    assert_eq!(vault_stats.managed_notes, 1);
+    assert_eq!(vault_stats.notes_with_old_format_entries, 1);
+    assert_eq!(vault_stats.old_format_entries, 2);
+    assert_eq!(vault_stats.spawning_markers, 1);
+    assert_eq!(vault_stats.spawned_markers, 1);
+    assert_eq!(vault_stats.obsidian_links, 3);
+    assert_eq!(vault_stats.markdown_links, 1);
+}
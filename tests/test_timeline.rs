@@ -0,0 +1,142 @@
+//! Testing that Timeline notes are parsed, logged to, and kept linking to notes that move
+
+mod common;
+
+use common::{TestVault, read, write_note};
+use migration_rs::{cluster_note::WorkingPath, *};
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn test_parse_timeline() {
+    let content = "# Timeline\n\n- Undated\n\n## 2025-09-08\n\n- Started [[000 Do it|it]] in [[Cluster#Goal]]\n  \
+                   - Nested\n- [ ] Checked [[#Here]]\n\n## Later\n\n- [[2025-09-10]] - [[000 Do it]] status: done\n\
+                   * 2025-09-11: Plain\n";
+
+    let entries = timeline::parse_timeline(content);
+
+    assert_eq!(
+        entries
+            .iter()
+            .map(|entry| (entry.line, entry.date.as_str(), entry.text.as_str()))
+            .collect::<Vec<_>>(),
+        [
+            (
+                6,
+                "2025-09-08",
+                "Started [[000 Do it|it]] in [[Cluster#Goal]]"
+            ),
+            (8, "2025-09-08", "Checked [[#Here]]"),
+            (12, "2025-09-10", "[[000 Do it]] status: done"),
+            (13, "2025-09-11", "Plain"),
+        ]
+    );
+    assert_eq!(entries[0].links, ["000 Do it", "Cluster"]);
+    assert!(entries[1].links.is_empty());
+
+    assert_eq!(
+        timeline::get_recorded_statuses(&entries)
+            .into_iter()
+            .collect::<Vec<_>>(),
+        [("000 Do it".to_owned(), "done".to_owned())]
+    );
+}
+
+#[test]
+fn test_timeline_maintenance() {
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+
+    write_note(
+        &vault.join("Timeline.md"),
+        "# Timeline\n\n- 2025-09-01 [[000 Old]] status: todo\n- 2025-09-01 See [[A/tasks/000 Old]]\n",
+    );
+    write_note(
+        &vault.join("A/A.md"),
+        "A\n\n%% index start %%\n\n- Tasks\n  - [[000 Old]]\n\n%% index end %%\n",
+    );
+    write_note(
+        &vault.join("A/tasks/000 Old.md"),
+        "---\nparent: \"[[A]]\"\nstatus: done\n---\n",
+    );
+    write_note(&vault.join("B/B.md"), "B\n");
+    write_note(
+        &vault.join("templater/tasks.md"),
+        "---\nparent: \"{{parent}}\"\nstatus: todo\n---\n",
+    );
+
+    let vault_path = test_vault.vault_path();
+
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(20_340 * 86_400);
+
+    // Creating a note logs it with its status
+    let mutations =
+        scaffold::plan_new_peripheral_note(&vault_path, &vault.join("A/A.md"), "task", "New", now)
+            .unwrap();
+
+    mutation::apply_mutations(&vault_path, "new_peripheral", mutations).unwrap();
+
+    // Only the status that changed since it was recorded is logged
+    let mutations = timeline::plan_record_status_changes(&vault_path, now).unwrap();
+
+    mutation::apply_mutations(&vault_path, "timeline", mutations).unwrap();
+
+    assert_eq!(
+        read(&vault.join("Timeline.md")),
+        "# Timeline\n\n- 2025-09-01 [[000 Old]] status: todo\n- 2025-09-01 See [[A/tasks/000 Old]]\n\
+         - 2025-09-09 Created [[001 New]] in [[A]]\n- 2025-09-09 [[001 New]] status: todo\n\
+         - 2025-09-09 [[000 Old]] status: done\n"
+    );
+    assert!(
+        timeline::plan_record_status_changes(&vault_path, now)
+            .unwrap()
+            .is_empty()
+    );
+
+    // Links of the Timeline note follow the note when it moves
+    let mutations = cluster_move::plan_move_peripheral_note(
+        &vault_path,
+        &vault.join("A/tasks/000 Old.md"),
+        &vault.join("B/B.md"),
+    )
+    .unwrap();
+
+    mutation::apply_mutations(&vault_path, "move_peripheral", mutations).unwrap();
+
+    assert!(read(&vault.join("Timeline.md")).contains("See [[B/tasks/000 Old]]"));
+}
+
+#[test]
+fn test_redirect_links_to_new_peripheral_note() {
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+
+    write_note(&vault.join("Old.md"), "See [[#Task A]]\n");
+    write_note(
+        &vault.join("Timeline.md"),
+        "- 2025-09-01 Did [[Old#Task A|task A]] and [[Old#Task B]]\n",
+    );
+
+    let vault_path = test_vault.vault_path();
+
+    let items: Vec<WorkingPath> =
+        cluster_note::get_working_item_paths_in_vault(&vault_path).unwrap();
+
+    let mutations = cluster_note_io::redirect_links_to_new_peripheral_note(
+        &items,
+        Some("Old".to_owned()),
+        "Task A".to_owned(),
+        "000 Task A".to_owned(),
+    )
+    .unwrap();
+
+    // Nothing is written until the mutations are applied
+    assert_eq!(read(&vault.join("Old.md")), "See [[#Task A]]\n");
+
+    mutation::apply_mutations(&vault_path, "extract", mutations).unwrap();
+
+    assert_eq!(read(&vault.join("Old.md")), "See [[000 Task A]]\n");
+    assert_eq!(
+        read(&vault.join("Timeline.md")),
+        "- 2025-09-01 Did [[000 Task A|task A]] and [[Old#Task B]]\n"
+    );
+}
@@ -0,0 +1,166 @@
+//! Testing that a cluster folds back into one note, and that unclustering can be undone through the journal
+
+mod common;
+
+use common::{TestVault, write_note};
+use migration_rs::{common as comm, *};
+
+#[test]
+fn test_uncluster_and_undo() {
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+
+    let cluster = vault.join("000 Cluster");
+    let core_note = cluster.join("000 Cluster.md");
+
+    write_note(
+        &core_note,
+        "Core\n\n%% index start %%\n\n- Tasks\n  - [[000 Task]]\n\n%% index end %%\n",
+    );
+    write_note(
+        &cluster.join("tasks/000 Task.md"),
+        "---\nparent: \"[[000 Cluster]]\"\nstatus: todo\n---\n\n# Steps\n\nSpawn [[Other]] ^spawn-task-0a1b2c\n",
+    );
+    write_note(
+        &cluster.join("ideas/000 Idea.md"),
+        "---\nparent: \"[[000 Cluster]]\"\n---\n\nSee [[000 Task|the task]]\n",
+    );
+    write_note(
+        &vault.join("Other.md"),
+        "From [[#^spawn-task-0a1b2c]] in [[000 Task]]\n\nAlso [[000 Idea]] and [[000 Task#^spawn-task-0a1b2c]]\n",
+    );
+
+    let vault_path = test_vault.vault_path();
+
+    let plan = uncluster::plan_uncluster(&vault_path, &core_note).unwrap();
+
+    assert_eq!(plan.note_path, vault.join("000 Cluster.md"));
+    assert_eq!(
+        plan.content,
+        "Core\n\n# Ideas\n\n## 000 Idea\n\nSee [[000 Cluster#000 Task|the task]]\n\n\
+         # Tasks\n\n## 000 Task\n\nstatus: todo\n\n### Steps\n\nSpawn [[Other]] ^spawn-task-0a1b2c\n"
+    );
+    assert!(plan.attachment_files.is_empty());
+
+    let other_before = comm::read_file_content(&vault.join("Other.md")).unwrap();
+
+    mutation::apply_mutations(&vault_path, "uncluster", plan.mutations).unwrap();
+
+    assert!(!cluster.exists());
+    assert_eq!(
+        comm::read_file_content(&vault.join("000 Cluster.md")).unwrap(),
+        plan.content
+    );
+    assert_eq!(
+        comm::read_file_content(&vault.join("Other.md")).unwrap(),
+        "From [[#^spawn-task-0a1b2c]] in [[000 Cluster]]\n\n\
+         Also [[000 Cluster#000 Idea]] and [[000 Cluster#^spawn-task-0a1b2c]]\n"
+    );
+
+    mutation::undo_last_mutations(&vault_path).unwrap().unwrap();
+
+    assert!(!vault.join("000 Cluster.md").exists());
+    assert!(cluster.join("tasks/000 Task.md").is_file());
+    assert!(cluster.join("ideas/000 Idea.md").is_file());
+    assert_eq!(
+        comm::read_file_content(&vault.join("Other.md")).unwrap(),
+        other_before
+    );
+}
+
+#[test]
+fn test_uncluster_keeps_attachments() {
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+
+    let cluster = vault.join("Cluster");
+
+    write_note(&cluster.join("Cluster.md"), "Core\n");
+    write_note(
+        &cluster.join("tasks/Task.md"),
+        "---\nparent: \"[[Cluster]]\"\n---\n\nTask\n",
+    );
+    write_note(&cluster.join("tasks/image.png"), "png");
+
+    let vault_path = test_vault.vault_path();
+
+    let plan = uncluster::plan_uncluster(&vault_path, &cluster.join("Cluster.md")).unwrap();
+
+    assert_eq!(plan.attachment_files, [cluster.join("tasks/image.png")]);
+
+    mutation::apply_mutations(&vault_path, "uncluster", plan.mutations).unwrap();
+
+    assert!(vault.join("Cluster.md").is_file());
+    assert!(cluster.join("tasks/image.png").is_file());
+    assert!(!cluster.join("tasks/Task.md").exists());
+
+    // A note is not a core note
+    assert!(uncluster::plan_uncluster(&vault_path, &vault.join("Cluster.md")).is_err());
+}
+
+#[test]
+fn test_unclustered_sections_skip_fenced_code() {
+    let config = vault_config::VaultConfig::default();
+    let tasks = config.get_context_type_by_folder("tasks").unwrap();
+
+    let core_note_content = "Core\n\n```\n# Tasks\n```\n\n# Tasks\n\n## Old\n\n```sh\n# comment\n```\n\n# Notes\n\nEnd\n";
+
+    let content = uncluster::render_unclustered_note(
+        core_note_content,
+        "Core",
+        &[(
+            tasks,
+            vec![uncluster::FoldedNote {
+                stem: "000 New".to_owned(),
+                content: "Body\n".to_owned(),
+            }],
+        )],
+        &cluster_note::OldFormatLayout::default(),
+    );
+
+    // The folded note joins the real Tasks section, after its code block
+    assert_eq!(
+        content,
+        "Core\n\n```\n# Tasks\n```\n\n# Tasks\n\n## Old\n\n```sh\n# comment\n```\n\n## 000 New\n\nBody\n\n# Notes\n\nEnd\n"
+    );
+}
+
+#[test]
+fn test_uncluster_restores_spawn_markers_and_layout() {
+    let test_vault = TestVault::new()
+        .with_note(
+            "Cluster/Cluster.md",
+            "# Cluster\n\n## Tasks\n\nSpawn [[000 Fix]] ^spawn-task-abc123\n\n\
+             %% index start %%\n- Tasks\n  - [[000 Fix]]\n%% index end %%\n",
+        )
+        .with_note(
+            "Cluster/tasks/000 Fix.md",
+            "---\nparent: \"[[Cluster]]\"\n---\n\nFrom [[#^spawn-task-abc123]] in [[Cluster]]\n\nSteps\n",
+        )
+        .with_note(
+            "Cluster/ideas/000 Idea.md",
+            "---\nparent: \"[[Cluster]]\"\n---\n\nIdea body\n",
+        )
+        .with_note(
+            "Other.md",
+            "See [[Cluster/tasks/000 Fix]], [[000 Idea.md|the idea]] and [[Cluster/Cluster#Tasks]]\n",
+        );
+    let vault = test_vault.path();
+    let vault_path = test_vault.vault_path();
+
+    let plan = uncluster::plan_uncluster(&vault_path, &vault.join("Cluster/Cluster.md")).unwrap();
+
+    // The task goes back in place of its marker, and the idea joins a category at the levels of the note
+    assert_eq!(
+        plan.content,
+        "# Cluster\n\n## Tasks\n\n### 000 Fix\n\nSteps\n\n## Ideas\n\n### 000 Idea\n\nIdea body\n"
+    );
+
+    mutation::apply_mutations(&vault_path, "uncluster", plan.mutations).unwrap();
+
+    // Links by path or with the markdown extension follow the notes into the sections
+    assert_eq!(
+        comm::read_file_content(&vault.join("Other.md")).unwrap(),
+        "See [[Cluster#000 Fix]], [[Cluster#000 Idea|the idea]] and [[Cluster#Tasks]]\n"
+    );
+}
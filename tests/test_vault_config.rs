@@ -0,0 +1,285 @@
+//! Testing that context types and the other settings come from the vault config
+
+mod common;
+
+use common::{TestVault, read, write_note};
+use migration_rs::{
+    cluster_note::*,
+    common::{self as comm, ObsidianVaultPath, OpenObsidianVaultError},
+    diagnostics::ClusterRuleViolation,
+    vault_config::{LoadVaultConfigError, NumberingScope, VaultConfig},
+    vault_index::{BuildVaultIndexError, VaultIndex},
+    *,
+};
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn test_context_types_from_vault_config() {
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+
+    write_note(
+        &VaultConfig::path_in_vault(vault),
+        r#"(
+    context_types: [
+        (folder: "decisions", block_code: "decis", heading_singular: "Decision", heading_plural: "Decisions", is_doer: false),
+        (folder: "references", block_code: "ref", heading_singular: "Reference", heading_plural: "References", is_doer: false),
+    ],
+)"#,
+    );
+
+    let cluster = vault.join("000 Cluster");
+    let core_note_path = cluster.join("000 Cluster.md");
+
+    write_note(
+        &core_note_path,
+        &format!("{CORE_NOTE_INDEX_START}\n{CORE_NOTE_INDEX_END}"),
+    );
+    write_note(&cluster.join("decisions/000 Use RON.md"), "Decision");
+    write_note(&cluster.join("tasks/000 Task.md"), "Task");
+
+    let config = test_vault.vault_path().config;
+
+    assert_eq!(config.context_types.len(), 2);
+    // Old format headings were left out of the config, so they keep their defaults
+    assert!(config.old_format_headings.contains(&"Tasks".to_owned()));
+
+    // The file system and the index agree on the configured context types
+    let index = VaultIndex::build(vault).unwrap();
+
+    for (folder, expected) in [("decisions", true), ("tasks", false)] {
+        assert_eq!(
+            is_cluster_category_folder(&cluster.join(folder), &config),
+            Some(expected)
+        );
+        assert_eq!(
+            index.is_cluster_category_folder(&cluster.join(folder)),
+            Some(expected)
+        );
+    }
+
+    let working_items = index.get_working_items(vault).unwrap();
+
+    assert_eq!(
+        working_items
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (
+                diagnostic.path.clone(),
+                diagnostic.opt_violation().cloned().unwrap()
+            ))
+            .collect::<Vec<_>>(),
+        [(
+            cluster.join("tasks"),
+            ClusterRuleViolation::UnknownCategoryFolder
+        )]
+    );
+    assert!(
+        working_items.diagnostics[0]
+            .suggested_fix()
+            .contains("decisions, references")
+    );
+
+    let core_note = CoreNoteFilePath::new(&core_note_path).unwrap();
+
+    assert!(cluster_note_io::generate_index_for_core_note(&core_note, &config).unwrap());
+    assert!(
+        comm::read_file_content(&core_note_path)
+            .unwrap()
+            .contains("- Decisions\n  - [[000 Use RON]]")
+    );
+}
+
+#[test]
+fn test_broken_vault_config_fails_commands() {
+    let test_vault = TestVault::new().with_config("(context_types: [])");
+    let vault = test_vault.path();
+
+    // Broken configs are reported rather than silently replaced by the defaults
+    assert!(matches!(
+        ObsidianVaultPath::new(vault),
+        Err(OpenObsidianVaultError::Config(
+            LoadVaultConfigError::NoContextTypes(_)
+        ))
+    ));
+    assert!(matches!(
+        VaultIndex::build(vault),
+        Err(BuildVaultIndexError::Config(_))
+    ));
+
+    write_note(&VaultConfig::path_in_vault(vault), "(numbering: Weekly)");
+
+    assert!(matches!(
+        ObsidianVaultPath::new(vault),
+        Err(OpenObsidianVaultError::Config(LoadVaultConfigError::Parse(
+            ..
+        )))
+    ));
+}
+
+#[test]
+fn test_settings_from_vault_config() {
+    let test_vault = TestVault::new()
+        .with_config(
+            r#"(
+    numbering: Year,
+    closed_statuses: ["shipped"],
+    templates_folder: "meta/templates",
+    timeline_note: "Journal/Log.md",
+)"#,
+        )
+        .with_note("2025/A/A.md", "A")
+        .with_note("2025/A/tasks/000 First.md", "---\nstatus: shipped\n---\n")
+        .with_note("2025/B/B.md", "B")
+        .with_note("2025/B/tasks/000 Second.md", "---\nstatus: done\n---\n")
+        .with_note("meta/templates/tasks.md", "From the configured folder")
+        .with_note("Journal/Log.md", "# Log\n");
+    let vault = test_vault.path();
+    let vault_path = test_vault.vault_path();
+
+    // Year numbering counts both clusters together
+    assert_eq!(vault_path.config.numbering, NumberingScope::Year);
+    assert_eq!(
+        numbering::get_next_peripheral_note_name(&vault_path, &vault.join("2025/A/tasks"), "New"),
+        Some("001 New".to_owned())
+    );
+
+    // Only the configured statuses close doer notes
+    let report = dashboard::get_status_report(&vault_path, 30).unwrap();
+
+    assert_eq!(
+        report
+            .clusters
+            .iter()
+            .flat_map(|cluster| &cluster.open_notes)
+            .map(|doer_note| doer_note.name())
+            .collect::<Vec<_>>(),
+        ["000 Second"]
+    );
+
+    // New notes come from the configured templates folder and are logged to the configured timeline note
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(20_340 * 86_400);
+
+    let mutations = scaffold::plan_new_peripheral_note(
+        &vault_path,
+        &vault.join("2025/A/A.md"),
+        "task",
+        "New",
+        now,
+    )
+    .unwrap();
+
+    mutation::apply_mutations(&vault_path, "new_peripheral", mutations).unwrap();
+
+    assert!(read(&vault.join("2025/A/tasks/001 New.md")).contains("From the configured folder"));
+    assert!(timeline::is_timeline_note(
+        &vault_path,
+        &vault.join("Journal/Log.md")
+    ));
+    assert!(!timeline::is_timeline_note(
+        &vault_path,
+        &vault.join("Timeline.md")
+    ));
+    assert!(read(&vault.join("Journal/Log.md")).contains("Created [[001 New]] in [[A]]"));
+
+    // The configured note is managed by the timeline plugin, and so are the notes named after timelines
+    for managed_path in ["Journal/Log.md", "Timeline.md", "2025/A/Timeline 2025.md"] {
+        assert!(drivers::skip_processing_managed_path(
+            &vault_path,
+            &vault.join(managed_path)
+        ));
+    }
+    assert!(!drivers::skip_processing_managed_path(
+        &vault_path,
+        &vault.join("2025/A/A.md")
+    ));
+}
+
+#[test]
+fn test_old_format_headings_from_vault_config() {
+    let test_vault = TestVault::new()
+        .with_config(
+            r#"(
+    context_types: [
+        (folder: "decisions", block_code: "decis", heading_singular: "Decision", heading_plural: "Decisions", is_doer: false),
+    ],
+    old_format_headings: ["Decisions", "Tasks"],
+)"#,
+        )
+        .with_note("Project.md", "# Decisions\n\n## Use RON\n\nIt reads well\n");
+    let vault = test_vault.path();
+    let vault_path = test_vault.vault_path();
+    let note_path = vault.join("Project.md");
+
+    let content = read(&note_path);
+    let events = comm::parse_markdown_file(&content);
+    let layout = detect_old_format_layout(&events, &vault_path.config);
+
+    let entries =
+        get_note_old_format_entries(&content, &events, &vault_path.config, &layout).unwrap();
+
+    // The category resolves to the configured context type, which only exists in the config
+    assert_eq!(
+        entries
+            .iter()
+            .map(|entry| (
+                entry.context_type.folder.as_str(),
+                entry.entry_name.as_str()
+            ))
+            .collect::<Vec<_>>(),
+        [("decisions", "Use RON")]
+    );
+
+    let mutations = cluster_note_io::create_new_peripheral_note_from_old_format_entry(
+        &vault_path,
+        &note_path,
+        &entries[0],
+    )
+    .unwrap();
+
+    mutation::apply_mutations(&vault_path, "extract", mutations).unwrap();
+
+    assert!(read(&vault.join("Project/decisions/000 Use RON.md")).contains("It reads well"));
+
+    // A heading without a context type can't be extracted
+    let content = "# Tasks\n\n## Fix crash\n\nContent\n";
+    let events = comm::parse_markdown_file(content);
+
+    assert!(matches!(
+        get_note_old_format_entries(
+            content,
+            &events,
+            &vault_path.config,
+            &OldFormatLayout::default()
+        ),
+        Err(GetNoteOldFormatEntriesError::UnknownContextType { .. })
+    ));
+}
+
+#[test]
+fn test_working_items_of_a_folder_use_the_vault_config() {
+    let test_vault = TestVault::new()
+        .with_config(
+            r#"(
+    context_types: [
+        (folder: "decisions", block_code: "decis", heading_singular: "Decision", heading_plural: "Decisions", is_doer: false),
+    ],
+)"#,
+        )
+        .with_note("area/Project/Project.md", "Core")
+        .with_note("area/Project/decisions/000 Use RON.md", "Decision");
+    let vault = test_vault.path();
+    let vault_path = test_vault.vault_path();
+
+    // The folder has no config of its own, so its context types come from the vault
+    let items =
+        get_working_item_paths_recursive(&vault.join("area"), vault_path.config.clone()).unwrap();
+
+    assert!(matches!(
+        items.as_slice(),
+        [WorkingPath::ClusterFolder {
+            category_folders_with_peripheral_files,
+            ..
+        }] if category_folders_with_peripheral_files.len() == 1
+    ));
+}
@@ -0,0 +1,482 @@
+//! Testing that the vault index classifies notes the same way the file system checks do
+
+mod common;
+
+use common::{TestVault, create_vault, write_note};
+use migration_rs::{
+    cluster_note::*,
+    common as comm,
+    diagnostics::{VaultDiagnostic, VaultDiagnosticKind},
+    vault_config::VaultConfig,
+    vault_index::VaultIndex,
+    *,
+};
+use std::{path::Path, sync::Arc};
+
+fn write_test_notes(vault: &Path) {
+    write_note(&vault.join("lan/entries/000 Plain.md"), "Plain");
+    write_note(&vault.join("lan/image.png"), "");
+
+    let cluster = vault.join("lan/tasks/000 Cluster");
+
+    write_note(&cluster.join("000 Cluster.md"), "Core");
+    write_note(&cluster.join("tasks/000 Task.md"), "Task");
+    write_note(&cluster.join("entries/000 Entry.md"), "Entry");
+
+    // Two files, so this is not a cluster and its notes are normal notes
+    let not_cluster = vault.join("lan/tasks/001 Not Cluster");
+
+    write_note(&not_cluster.join("001 Not Cluster.md"), "Core");
+    write_note(&not_cluster.join("Other.md"), "Other");
+    write_note(&not_cluster.join("tasks/000 Task.md"), "Task");
+}
+
+fn working_item_markdown_paths(items: &[WorkingPath]) -> Vec<String> {
+    items
+        .iter()
+        .flat_map(|item| match item {
+            WorkingPath::Note(normal_note_file_path) => vec![normal_note_file_path.path.clone()],
+            WorkingPath::ClusterFolder {
+                core_note_file,
+                category_folders_with_peripheral_files,
+                ..
+            } => {
+                let mut mut_paths = vec![core_note_file.path.clone()];
+
+                mut_paths.extend(
+                    category_folders_with_peripheral_files
+                        .iter()
+                        .flat_map(|(_, files)| files)
+                        .map(|file| file.path.clone()),
+                );
+
+                mut_paths
+            }
+        })
+        .map(|path| path.to_string_lossy().to_string())
+        .collect()
+}
+
+#[test]
+fn test_vault_index_classification() {
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+
+    write_test_notes(vault);
+
+    let index = VaultIndex::build(vault).unwrap();
+
+    let cluster = vault.join("lan/tasks/000 Cluster");
+    let not_cluster = vault.join("lan/tasks/001 Not Cluster");
+
+    for folder in [
+        &cluster,
+        &not_cluster,
+        &cluster.join("tasks"),
+        &not_cluster.join("tasks"),
+    ] {
+        assert_eq!(
+            index.is_cluster_root_folder(folder),
+            is_cluster_root_folder(folder),
+            "{folder:?}"
+        );
+        assert_eq!(
+            index.is_cluster_category_folder(folder),
+            is_cluster_category_folder(folder, &index.config),
+            "{folder:?}"
+        );
+    }
+
+    for path in [
+        cluster.join("000 Cluster.md"),
+        cluster.join("tasks/000 Task.md"),
+        not_cluster.join("tasks/000 Task.md"),
+        vault.join("lan/image.png"),
+    ] {
+        assert_eq!(
+            index.is_cluster_core_file_path(&path),
+            is_cluster_core_file_path(&path),
+            "{path:?}"
+        );
+        assert_eq!(
+            index.is_cluster_peripheral_file_path(&path),
+            is_cluster_peripheral_file_path(&path, &index.config),
+            "{path:?}"
+        );
+        assert_eq!(
+            index.is_normal_markdown_file_path(&path),
+            is_normal_markdown_file_path(&path, &index.config),
+            "{path:?}"
+        );
+    }
+
+    let paths = working_item_markdown_paths(&index.get_working_item_paths(vault).unwrap());
+
+    assert_eq!(
+        paths,
+        [
+            "lan/entries/000 Plain.md",
+            "lan/tasks/000 Cluster/000 Cluster.md",
+            "lan/tasks/000 Cluster/entries/000 Entry.md",
+            "lan/tasks/000 Cluster/tasks/000 Task.md",
+            "lan/tasks/001 Not Cluster/tasks/000 Task.md",
+            "lan/tasks/001 Not Cluster/001 Not Cluster.md",
+            "lan/tasks/001 Not Cluster/Other.md",
+        ]
+        .map(|p| vault.join(p).to_string_lossy().to_string())
+    );
+}
+
+#[test]
+fn test_vault_index_cache_relists_changed_folders() {
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+
+    write_test_notes(vault);
+
+    let cache_path = vault.join(comm::TOOL_FOLDER_NAME).join("vault_index.ron");
+
+    let index = VaultIndex::build(vault).unwrap();
+    index.save_cache(&cache_path).unwrap();
+
+    let cached = VaultIndex::load_cache(&cache_path).unwrap();
+
+    // An unchanged folder is reused from the cache, even if the cached listing is stale
+    let mut mut_stale = cached.clone();
+    let entries = vault.join("lan/entries");
+    mut_stale
+        .dirs
+        .get_mut(&entries)
+        .unwrap()
+        .listing
+        .files
+        .push("Stale.md".into());
+
+    let reused = VaultIndex::build_reusing(vault, Some(&mut_stale)).unwrap();
+    assert!(reused.is_file(&entries.join("Stale.md")));
+
+    // A new file changes the folder mtime, so it is listed again
+    let cluster = vault.join("lan/tasks/000 Cluster");
+    write_note(&cluster.join("Other.md"), "Other");
+
+    let rebuilt = VaultIndex::build_reusing(vault, Some(&cached)).unwrap();
+
+    assert!(rebuilt.is_file(&cluster.join("Other.md")));
+    assert_eq!(rebuilt.is_cluster_root_folder(&cluster), Some(false));
+}
+
+#[test]
+fn test_vault_index_skips_unreadable_folders() {
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+
+    write_test_notes(vault);
+
+    // A stale cached listing names a folder that is gone by the time it is walked
+    let mut mut_stale = VaultIndex::build(vault).unwrap();
+    let entries = vault.join("lan/entries");
+    mut_stale
+        .dirs
+        .get_mut(&entries)
+        .unwrap()
+        .listing
+        .dirs
+        .push("Gone".into());
+
+    let index = VaultIndex::build_reusing(vault, Some(&mut_stale)).unwrap();
+
+    assert!(!index.is_dir(&entries.join("Gone")));
+    assert!(index.get_listing(&entries).unwrap().dirs.is_empty());
+    assert!(index.is_file(&entries.join("000 Plain.md")));
+
+    let working_items = index.get_working_items(vault).unwrap();
+
+    assert_eq!(
+        working_item_markdown_paths(&working_items.items).len(),
+        working_item_markdown_paths(
+            &VaultIndex::build(vault)
+                .unwrap()
+                .get_working_item_paths(vault)
+                .unwrap()
+        )
+        .len()
+    );
+    assert!(matches!(
+        working_items.diagnostics.as_slice(),
+        [VaultDiagnostic {
+            path,
+            kind: VaultDiagnosticKind::UnreadableFolder(_),
+        }] if *path == entries.join("Gone")
+    ));
+}
+
+#[test]
+fn test_vault_index_exclusions() {
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+
+    write_test_notes(vault);
+
+    write_note(
+        &vault.join(".obsidian/app.json"),
+        r#"{"userIgnoreFilters": ["lan/archive/", "/Draft$/"]}"#,
+    );
+    write_note(&vault.join(".trash/Deleted.md"), "Deleted");
+    write_note(&vault.join("lan/archive/Old.md"), "Old");
+    write_note(&vault.join("lan/entries/001 Draft"), "Draft");
+    write_note(&vault.join("lan/.gitignore"), "*.tmp.md\n!keep.tmp.md\n");
+    write_note(&vault.join("lan/entries/scratch.tmp.md"), "Scratch");
+    write_note(&vault.join("lan/entries/keep.tmp.md"), "Keep");
+    write_note(&vault.join(exclusion::TOOL_IGNORE_FILE_NAME), "private/\n");
+    write_note(&vault.join("lan/private/Secret.md"), "Secret");
+
+    let index = VaultIndex::build(vault).unwrap();
+
+    let excluded = |path: &str| index.excluded.get(&vault.join(path)).cloned();
+
+    assert_eq!(excluded(".trash"), Some(exclusion::ExclusionRule::Hidden));
+    assert_eq!(
+        excluded("lan/archive"),
+        Some(exclusion::ExclusionRule::ObsidianExcludedFiles(
+            "lan/archive/".to_owned()
+        ))
+    );
+    assert_eq!(
+        excluded("lan/entries/001 Draft"),
+        Some(exclusion::ExclusionRule::ObsidianExcludedFiles(
+            "/Draft$/".to_owned()
+        ))
+    );
+    assert_eq!(
+        excluded("lan/entries/scratch.tmp.md"),
+        Some(exclusion::ExclusionRule::Gitignore {
+            file: vault.join("lan/.gitignore"),
+            pattern: "*.tmp.md".to_owned()
+        })
+    );
+    assert_eq!(
+        excluded("lan/private"),
+        Some(exclusion::ExclusionRule::ToolIgnore {
+            file: vault.join(exclusion::TOOL_IGNORE_FILE_NAME),
+            pattern: "private/".to_owned()
+        })
+    );
+    assert_eq!(excluded("lan/entries/keep.tmp.md"), None);
+
+    // Excluded folders are not walked at all
+    assert!(!index.is_dir(&vault.join("lan/archive")));
+    assert!(!index.is_dir(&vault.join(".trash")));
+    assert!(index.is_file(&vault.join("lan/entries/keep.tmp.md")));
+
+    let mut mut_rules = exclusion::ExclusionRules::load(vault).unwrap();
+
+    assert_eq!(
+        mut_rules.check_path(&vault.join("lan/private/Secret.md")),
+        excluded("lan/private")
+    );
+}
+
+#[test]
+fn test_vault_index_symlink_policy() {
+    use vault_index::{SkippedSymlinkReason, SymlinkPolicy};
+
+    let tmp = tempfile::tempdir().unwrap();
+    let vault = tmp.path().join("vault");
+    let shared = tmp.path().join("shared");
+
+    create_vault(&vault);
+    write_test_notes(&vault);
+    write_note(&shared.join("000 Shared/000 Shared.md"), "Core");
+    write_note(&shared.join("000 Shared/tasks/000 Task.md"), "Task");
+
+    std::os::unix::fs::symlink(&shared, vault.join("lan/shared")).unwrap();
+    std::os::unix::fs::symlink(vault.join("lan"), vault.join("lan/entries/loop")).unwrap();
+
+    let build = |symlink_policy| {
+        let rules = exclusion::ExclusionRules::load(&vault).unwrap();
+        let config = Arc::new(VaultConfig::load(&vault).unwrap());
+        VaultIndex::build_with_rules(&vault, None, rules, config, symlink_policy).unwrap()
+    };
+
+    let skipped = build(SymlinkPolicy::Skip);
+    assert_eq!(
+        skipped.skipped_symlinks.get(&vault.join("lan/shared")),
+        Some(&SkippedSymlinkReason::Policy)
+    );
+
+    let inside = build(SymlinkPolicy::FollowInsideVault);
+    assert!(matches!(
+        inside.skipped_symlinks.get(&vault.join("lan/shared")),
+        Some(SkippedSymlinkReason::OutsideVault(_))
+    ));
+    assert!(matches!(
+        inside.skipped_symlinks.get(&vault.join("lan/entries/loop")),
+        Some(SkippedSymlinkReason::Cycle(_))
+    ));
+
+    // Followed notes keep the symlinked path
+    let followed = build(SymlinkPolicy::Follow);
+    let cluster = vault.join("lan/shared/000 Shared");
+
+    assert_eq!(followed.is_cluster_root_folder(&cluster), Some(true));
+    assert_eq!(
+        followed.is_cluster_peripheral_file_path(&cluster.join("tasks/000 Task.md")),
+        Some(true)
+    );
+    assert!(matches!(
+        followed
+            .skipped_symlinks
+            .get(&vault.join("lan/entries/loop")),
+        Some(SkippedSymlinkReason::Cycle(_))
+    ));
+}
+
+#[test]
+fn test_vault_index_diagnostics() {
+    use diagnostics::ClusterRuleViolation;
+
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+
+    write_test_notes(vault);
+
+    let cluster = vault.join("lan/tasks/000 Cluster");
+
+    write_note(&cluster.join("misc/000 Misc.md"), "Misc");
+    write_note(&cluster.join("tasks/sub/000 Nested.md"), "Nested");
+    write_note(&cluster.join("entries/image.png"), "");
+
+    let index = VaultIndex::build(vault).unwrap();
+    let working_items = index.get_working_items(vault).unwrap();
+
+    let violations = working_items
+        .diagnostics
+        .iter()
+        .map(|diagnostic| {
+            (
+                diagnostic
+                    .path
+                    .strip_prefix(&cluster)
+                    .unwrap()
+                    .to_path_buf(),
+                diagnostic.opt_violation().cloned().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        violations,
+        [
+            ("misc".into(), ClusterRuleViolation::UnknownCategoryFolder),
+            (
+                "tasks/sub".into(),
+                ClusterRuleViolation::FolderInCategoryFolder
+            ),
+        ]
+    );
+
+    // The valid notes of the malformed cluster and the rest of the vault are still there
+    let paths = working_item_markdown_paths(&working_items.items);
+
+    assert!(
+        paths.contains(
+            &vault
+                .join("lan/entries/000 Plain.md")
+                .to_string_lossy()
+                .to_string()
+        )
+    );
+    assert!(
+        paths.contains(
+            &cluster
+                .join("tasks/000 Task.md")
+                .to_string_lossy()
+                .to_string()
+        )
+    );
+
+    let cluster_root_folder = ClusterRootFolderPath::new(&cluster).unwrap();
+    let (_, fs_diagnostics) = get_category_folders_with_peripheral_files_from_cluster_root_folder(
+        &cluster_root_folder,
+        &index.config,
+    )
+    .unwrap();
+
+    assert_eq!(fs_diagnostics.len(), working_items.diagnostics.len());
+}
+
+#[test]
+fn test_vault_index_cluster_attachments() {
+    let test_vault = TestVault::new();
+    let vault = test_vault.path();
+
+    write_test_notes(vault);
+
+    let cluster = vault.join("lan/tasks/000 Cluster");
+
+    write_note(&cluster.join("diagram.png"), "");
+    write_note(&cluster.join("000 Cluster.excalidraw.md"), "Drawing");
+    write_note(&cluster.join("entries/image.png"), "");
+    write_note(&cluster.join("attachments/scan.pdf"), "");
+    write_note(
+        &cluster.join("attachments/sketches/Sketch.excalidraw.md"),
+        "Drawing",
+    );
+
+    let index = VaultIndex::build(vault).unwrap();
+    let working_items = index.get_working_items(vault).unwrap();
+
+    assert!(working_items.diagnostics.is_empty());
+
+    let attachment_paths = working_items
+        .items
+        .iter()
+        .find_map(|item| match item {
+            WorkingPath::ClusterFolder {
+                cluster_root_folder,
+                attachment_files,
+                ..
+            } if cluster_root_folder.path == cluster => Some(
+                attachment_files
+                    .iter()
+                    .map(|file| file.path.strip_prefix(&cluster).unwrap().to_path_buf())
+                    .collect::<Vec<_>>(),
+            ),
+            _ => None,
+        })
+        .unwrap();
+
+    assert_eq!(
+        attachment_paths,
+        [
+            "000 Cluster.excalidraw.md",
+            "attachments/scan.pdf",
+            "attachments/sketches/Sketch.excalidraw.md",
+            "diagram.png",
+            "entries/image.png",
+        ]
+        .map(std::path::PathBuf::from)
+    );
+
+    // Drawings are not notes of the cluster
+    let paths = working_item_markdown_paths(&working_items.items);
+
+    assert!(!paths.iter().any(|path| path.ends_with(".excalidraw.md")));
+
+    // The filesystem classification agrees
+    let cluster_root_folder = ClusterRootFolderPath::new(&cluster).unwrap();
+
+    assert_eq!(
+        get_attachment_files_from_cluster_root_folder(&cluster_root_folder, &index.config)
+            .unwrap()
+            .len(),
+        attachment_paths.len()
+    );
+    assert_eq!(
+        is_normal_markdown_file_path(
+            &cluster.join("attachments/sketches/Sketch.excalidraw.md"),
+            &index.config
+        ),
+        Some(false)
+    );
+}
@@ -0,0 +1,55 @@
+//! Testing the pipeline the watcher runs on saved notes
+
+mod common;
+
+use common::{TestVault, read, write_note};
+use migration_rs::{lint::LintIssue, watch::*};
+use std::{
+    fs,
+    time::{Duration, SystemTime},
+};
+
+#[test]
+fn test_watch_pipeline() {
+    let test_vault = TestVault::new().with_note("000 Note.md", "*   Item\n\nSee [[001 Other]]\n");
+    let vault = test_vault.vault_path();
+    let note_path = test_vault.path().join("000 Note.md");
+
+    let mut mut_pipeline = WatchPipeline::new(&WATCH_STEPS_ALL);
+
+    // Writeback runs before the lint, so only the broken link is left to report
+    let issues = mut_pipeline.run(&vault, &note_path);
+
+    assert!(matches!(
+        issues.as_slice(),
+        [LintIssue::BrokenLink(link)] if link == "001 Other"
+    ));
+    assert_eq!(read(&note_path), "- Item\n\nSee [[001 Other]]");
+    assert!(mut_pipeline.is_own_write(&note_path));
+
+    // A note that is already normalized is not written again
+    let old_mtime = SystemTime::now() - Duration::from_secs(3600);
+
+    fs::File::options()
+        .write(true)
+        .open(&note_path)
+        .unwrap()
+        .set_modified(old_mtime)
+        .unwrap();
+
+    mut_pipeline.run(&vault, &note_path);
+
+    assert_eq!(
+        fs::metadata(&note_path).unwrap().modified().unwrap(),
+        old_mtime
+    );
+
+    // The link check keeps the working items it built until notes come and go
+    write_note(&test_vault.path().join("001 Other.md"), "# Other\n");
+
+    assert_eq!(mut_pipeline.run(&vault, &note_path).len(), 1);
+
+    mut_pipeline.invalidate_working_items();
+
+    assert!(mut_pipeline.run(&vault, &note_path).is_empty());
+}
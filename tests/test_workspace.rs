@@ -0,0 +1,246 @@
+//! Testing that links between the vaults of a workspace are parsed, checked, rewritten between forms and graphed
+
+mod common;
+
+use common::{create_vault, write_note};
+use migration_rs::{common as comm, workspace::*, *};
+use std::path::Path;
+
+#[test]
+fn test_cross_vault_links() {
+    let tmp = tempfile::tempdir().unwrap();
+
+    for vault in ["delta-trace", "lan-setup-notes"] {
+        create_vault(&tmp.path().join(vault));
+    }
+
+    write_note(
+        &tmp.path().join("lan-setup-notes/setup/000 Shell Setup.md"),
+        "# Shell\n\n## Key Bindings: Vi",
+    );
+
+    let config_path = tmp.path().join("workspace.ron");
+
+    comm::write_file_content(
+        r#"(
+    vaults: [
+        (name: "delta-trace", path: "delta-trace"),
+        (
+            name: "lan-setup-notes",
+            path: "lan-setup-notes",
+            opt_github_url: Some("https://github.com/LanHikari22/lan-setup-notes/blob/main/"),
+        ),
+    ],
+)"#,
+        &config_path,
+    )
+    .unwrap();
+
+    let workspace = Workspace::load(&config_path).unwrap();
+
+    let github_url = "https://github.com/LanHikari22/lan-setup-notes/blob/main/setup/000%20Shell%20Setup.md#shell";
+    let obsidian_uri =
+        "obsidian://open?vault=lan-setup-notes&file=setup%2F000%20Shell%20Setup%23Shell";
+
+    let link = workspace.parse_cross_vault_link(github_url).unwrap();
+
+    assert_eq!(link.vault_name, "lan-setup-notes");
+    assert_eq!(link.relative_path, Path::new("setup/000 Shell Setup.md"));
+    // The GitHub anchor maps back to the heading it is the slug of
+    assert_eq!(link.opt_fragment.as_deref(), Some("Shell"));
+
+    // Both forms map to the same note and back
+    assert_eq!(
+        workspace.parse_cross_vault_link(obsidian_uri),
+        Some(link.clone())
+    );
+    assert_eq!(
+        workspace
+            .format_cross_vault_link(&link, CrossVaultLinkForm::ObsidianUri)
+            .unwrap(),
+        obsidian_uri
+    );
+    assert_eq!(
+        workspace
+            .format_cross_vault_link(&link, CrossVaultLinkForm::GithubWebview)
+            .unwrap(),
+        github_url
+    );
+    assert!(workspace.resolve_cross_vault_link(&link).is_some());
+
+    let heading_link = CrossVaultLink {
+        opt_fragment: Some("Key Bindings: Vi".to_owned()),
+        ..link.clone()
+    };
+    let heading_url = workspace
+        .format_cross_vault_link(&heading_link, CrossVaultLinkForm::GithubWebview)
+        .unwrap();
+
+    assert!(heading_url.ends_with("#key-bindings-vi"));
+    assert_eq!(
+        workspace.parse_cross_vault_link(&heading_url),
+        Some(heading_link)
+    );
+
+    // Unrelated URLs are not cross vault links
+    assert!(
+        workspace
+            .parse_cross_vault_link("https://github.com/LanHikari22/other/blob/main/a.md")
+            .is_none()
+    );
+
+    let note_path = tmp.path().join("delta-trace/000 Trace.md");
+    let broken_url = "obsidian://open?vault=lan-setup-notes&file=setup%2FMissing";
+
+    write_note(
+        &note_path,
+        &format!(
+            "See [shell]({github_url}) and [missing]({broken_url})\n\n`({github_url})` stays as it is"
+        ),
+    );
+
+    let issues = lint::check_note_cross_vault_links(&workspace, &note_path).unwrap();
+
+    assert!(matches!(
+        issues.as_slice(),
+        [lint::LintIssue::BrokenCrossVaultLink(url)] if url == broken_url
+    ));
+
+    let content = comm::read_file_content(&note_path).unwrap();
+
+    let (new_content, count) =
+        rewrite_cross_vault_links(&workspace, &content, CrossVaultLinkForm::ObsidianUri);
+
+    assert_eq!(count, 1);
+    assert_eq!(
+        new_content,
+        format!(
+            "See [shell]({obsidian_uri}) and [missing]({broken_url})\n\n`({github_url})` stays as it is"
+        )
+    );
+
+    // The broken link can be rewritten too, but delta-trace has no GitHub URL to rewrite links into it with
+    let (new_content, count) =
+        rewrite_cross_vault_links(&workspace, &new_content, CrossVaultLinkForm::GithubWebview);
+
+    assert_eq!(count, 2);
+    assert!(new_content.starts_with(&format!("See [shell]({github_url})")));
+}
+
+#[test]
+fn test_redirect_cross_vault_links() {
+    let tmp = tempfile::tempdir().unwrap();
+
+    for vault in ["delta-trace", "lan-setup-notes"] {
+        create_vault(&tmp.path().join(vault));
+    }
+
+    let old_path = tmp.path().join("lan-setup-notes/setup/000 Shell Setup.md");
+    let new_path = tmp.path().join("lan-setup-notes/setup/001 Shell Setup.md");
+
+    write_note(&old_path, "# Shell");
+
+    let config_path = tmp.path().join("workspace.ron");
+
+    comm::write_file_content(
+        r#"(
+    vaults: [
+        (name: "delta-trace", path: "delta-trace"),
+        (
+            name: "lan-setup-notes",
+            path: "lan-setup-notes",
+            opt_github_url: Some("https://github.com/LanHikari22/lan-setup-notes/blob/main"),
+        ),
+    ],
+)"#,
+        &config_path,
+    )
+    .unwrap();
+
+    let workspace = Workspace::load(&config_path).unwrap();
+
+    let note_path = tmp.path().join("delta-trace/000 Trace.md");
+
+    write_note(
+        &note_path,
+        "See [shell](https://github.com/LanHikari22/lan-setup-notes/blob/main/setup/000%20Shell%20Setup.md#shell) \
+         and [it](obsidian://open?vault=lan-setup-notes&file=setup%2F000%20Shell%20Setup)",
+    );
+
+    let vault = comm::ObsidianVaultPath::new(&tmp.path().join("lan-setup-notes")).unwrap();
+
+    let planned = mutation::get_rename_note_mutations(&vault, &[], &old_path, &new_path);
+    let mutations = redirect_cross_vault_links(&workspace, &vault, planned);
+
+    // The other vault is written before the note moves, and both links keep their form
+    assert!(matches!(
+        mutations.as_slice(),
+        [mutation::Mutation::WriteFile { path, .. }, mutation::Mutation::Rename { .. }] if *path == note_path
+    ));
+
+    mutation::apply_mutations(&vault, "rename", mutations).unwrap();
+
+    assert_eq!(
+        comm::read_file_content(&note_path).unwrap(),
+        "See [shell](https://github.com/LanHikari22/lan-setup-notes/blob/main/setup/001%20Shell%20Setup.md#shell) \
+         and [it](obsidian://open?vault=lan-setup-notes&file=setup%2F001%20Shell%20Setup)"
+    );
+
+    // Undo in the vault reverts the links rewritten in the other one too
+    mutation::undo_last_mutations(&vault).unwrap();
+
+    assert!(
+        comm::read_file_content(&note_path)
+            .unwrap()
+            .contains("000%20Shell%20Setup.md#shell")
+    );
+}
+
+#[test]
+fn test_workspace_link_graph() {
+    let tmp = tempfile::tempdir().unwrap();
+
+    for vault in ["delta-trace", "lan-setup-notes"] {
+        create_vault(&tmp.path().join(vault));
+    }
+
+    write_note(
+        &tmp.path().join("delta-trace/000 Trace.md"),
+        "See [[001 Notes]], [[Missing]] and \
+         [shell](obsidian://open?vault=lan-setup-notes&file=setup%2F000%20Shell%20Setup)",
+    );
+    write_note(&tmp.path().join("delta-trace/001 Notes.md"), "Notes");
+    write_note(
+        &tmp.path().join("lan-setup-notes/setup/000 Shell Setup.md"),
+        "# Shell",
+    );
+
+    let config_path = tmp.path().join("workspace.ron");
+
+    comm::write_file_content(
+        r#"(
+    vaults: [
+        (name: "delta-trace", path: "delta-trace"),
+        (name: "lan-setup-notes", path: "lan-setup-notes"),
+    ],
+)"#,
+        &config_path,
+    )
+    .unwrap();
+
+    let workspace = Workspace::load(&config_path).unwrap();
+
+    let link_graph = graph::get_workspace_link_graph(&workspace).unwrap();
+
+    // The broken link is left out
+    assert_eq!(
+        link_graph.to_string(),
+        "digraph links {\n  \
+         \"delta-trace/000 Trace.md\";\n  \
+         \"delta-trace/001 Notes.md\";\n  \
+         \"lan-setup-notes/setup/000 Shell Setup.md\";\n  \
+         \"delta-trace/000 Trace.md\" -> \"delta-trace/001 Notes.md\";\n  \
+         \"delta-trace/000 Trace.md\" -> \"lan-setup-notes/setup/000 Shell Setup.md\";\n\
+         }"
+    );
+}